@@ -1,8 +1,18 @@
 use super::PeerResult;
+use crate::invite::{DEFAULT_INVITE_TTL, Invite};
+use crate::mailbox::Delivery;
+use crate::diagnostics::NetworkInfo;
+use crate::message::FileOffer;
+use crate::metrics::{CommandLatency, MetricsSnapshot};
+use crate::outbox::SendOutcome;
+use crate::signal::SignalKind;
+use crate::store::{Reactions, StoredMessage};
 use bon::Builder;
+use libp2p::Multiaddr;
 use derive_getters::Getters;
-use libp2p::gossipsub::MessageId;
 use std::fmt::Debug;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use tokio::sync::{
     mpsc::UnboundedSender,
     oneshot::{self, Sender},
@@ -10,9 +20,50 @@ use tokio::sync::{
 
 #[derive(Debug)]
 pub enum PeerCommand {
-    SendMessage(Command<SendMessageCommand, MessageId>),
+    SendMessage(Command<SendMessageCommand, SendOutcome>),
     Subscribe(Command<SubscribeCommand, bool>),
     Unsubscribe(Command<UnsubscribeCommand, bool>),
+    History(Command<HistoryCommand, Vec<StoredMessage>>),
+    Thread(Command<ThreadCommand, Vec<StoredMessage>>),
+    Import(Command<ImportCommand, usize>),
+    React(Command<ReactCommand, Reactions>),
+    Signal(Command<SignalCommand, bool>),
+    Receipt(Command<ReceiptCommand, ()>),
+    OfferFile(Command<OfferFileCommand, FileOffer>),
+    DownloadFile(Command<DownloadFileCommand, ()>),
+    DirectMessage(Command<DirectMessageCommand, (String, Delivery)>),
+    CreateInvite(Command<CreateInviteCommand, Invite>),
+    AcceptInvite(Command<AcceptInviteCommand, Invite>),
+    Dial(Command<DialCommand, ()>),
+    MetricsSnapshot(Command<MetricsSnapshotCommand, MetricsSnapshot>),
+    NetworkInfo(Command<NetworkInfoCommand, NetworkInfo>),
+    Disconnect(Command<DisconnectCommand, ()>),
+}
+
+impl PeerCommand {
+    /// Name of the command, as labelled in metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PeerCommand::SendMessage(_) => "send_message",
+            PeerCommand::Subscribe(_) => "subscribe",
+            PeerCommand::Unsubscribe(_) => "unsubscribe",
+            PeerCommand::History(_) => "history",
+            PeerCommand::Thread(_) => "thread",
+            PeerCommand::Import(_) => "import",
+            PeerCommand::React(_) => "react",
+            PeerCommand::Signal(_) => "signal",
+            PeerCommand::Receipt(_) => "receipt",
+            PeerCommand::OfferFile(_) => "offer_file",
+            PeerCommand::DownloadFile(_) => "download_file",
+            PeerCommand::DirectMessage(_) => "direct_message",
+            PeerCommand::CreateInvite(_) => "create_invite",
+            PeerCommand::AcceptInvite(_) => "accept_invite",
+            PeerCommand::Dial(_) => "dial",
+            PeerCommand::MetricsSnapshot(_) => "metrics_snapshot",
+            PeerCommand::NetworkInfo(_) => "network_info",
+            PeerCommand::Disconnect(_) => "disconnect",
+        }
+    }
 }
 
 pub struct Command<C, R> {
@@ -22,7 +73,7 @@ pub struct Command<C, R> {
 
 impl<C, R> Command<C, R> {
     pub fn send(self, response: PeerResult<R>) {
-        self.sender.send(response);
+        let _ = self.sender.send(response);
     }
 }
 
@@ -40,14 +91,18 @@ impl<C: Debug, R> Debug for Command<C, R> {
     }
 }
 
+/// Publishes a message to a room, queueing it while nobody is there.
 #[derive(Debug, Getters, Builder)]
 pub struct SendMessageCommand {
     message: String,
     topic: String,
+    parent_id: Option<String>,
+    #[builder(default)]
+    mentions: Vec<String>,
 }
 
 impl IntoPeerCommand for SendMessageCommand {
-    type Output = MessageId;
+    type Output = SendOutcome;
     fn into_command(
         self,
         sender: oneshot::Sender<PeerResult<Self::Output>>,
@@ -59,6 +114,77 @@ impl IntoPeerCommand for SendMessageCommand {
     }
 }
 
+/// Asks for the current state of the mesh and every metric.
+#[derive(Debug, Getters, Builder)]
+pub struct MetricsSnapshotCommand {}
+
+impl IntoPeerCommand for MetricsSnapshotCommand {
+    type Output = MetricsSnapshot;
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::MetricsSnapshot(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+/// Asks for our addresses, connections and mesh.
+#[derive(Debug, Getters, Builder)]
+pub struct NetworkInfoCommand {}
+
+impl IntoPeerCommand for NetworkInfoCommand {
+    type Output = NetworkInfo;
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::NetworkInfo(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+/// Closes every connection to a peer.
+#[derive(Debug, Getters, Builder)]
+pub struct DisconnectCommand {
+    peer_id: String,
+}
+
+impl IntoPeerCommand for DisconnectCommand {
+    type Output = ();
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::Disconnect(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+#[derive(Debug, Getters, Builder)]
+pub struct DialCommand {
+    address: Multiaddr,
+}
+
+impl IntoPeerCommand for DialCommand {
+    type Output = ();
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::Dial(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
 #[derive(Debug, Getters, Builder)]
 pub struct SubscribeCommand {
     topic: String,
@@ -95,14 +221,246 @@ impl IntoPeerCommand for UnsubscribeCommand {
     }
 }
 
+#[derive(Debug, Getters, Builder)]
+pub struct HistoryCommand {
+    topic: String,
+    limit: Option<usize>,
+}
+
+impl IntoPeerCommand for HistoryCommand {
+    type Output = Vec<StoredMessage>;
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::History(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+/// Adds messages exported from another peer to the local history,
+/// answering how many of them were new.
+#[derive(Debug, Getters, Builder)]
+pub struct ImportCommand {
+    messages: Vec<StoredMessage>,
+}
+
+impl IntoPeerCommand for ImportCommand {
+    type Output = usize;
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::Import(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+#[derive(Debug, Getters, Builder)]
+pub struct ThreadCommand {
+    message_id: String,
+}
+
+impl IntoPeerCommand for ThreadCommand {
+    type Output = Vec<StoredMessage>;
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::Thread(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+/// Toggles the local peer's `emoji` reaction on a message, answering with
+/// the message's reactions after the change.
+#[derive(Debug, Getters, Builder)]
+pub struct ReactCommand {
+    topic: String,
+    message_id: String,
+    emoji: String,
+}
+
+impl IntoPeerCommand for ReactCommand {
+    type Output = Reactions;
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::React(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+#[derive(Debug, Getters, Builder)]
+pub struct SignalCommand {
+    topic: String,
+    kind: SignalKind,
+}
+
+impl IntoPeerCommand for SignalCommand {
+    type Output = bool;
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::Signal(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+/// Broadcasts a signed read receipt for the last message read in a room.
+#[derive(Debug, Getters, Builder)]
+pub struct ReceiptCommand {
+    topic: String,
+    message_id: String,
+}
+
+impl IntoPeerCommand for ReceiptCommand {
+    type Output = ();
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::Receipt(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+/// Offers a local file to a room, answering once it is hashed and announced.
+#[derive(Debug, Getters, Builder)]
+pub struct OfferFileCommand {
+    topic: String,
+    path: PathBuf,
+}
+
+impl IntoPeerCommand for OfferFileCommand {
+    type Output = FileOffer;
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::OfferFile(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+/// Starts downloading an offered file into `directory`; the outcome is
+/// reported through transfer events.
+#[derive(Debug, Getters, Builder)]
+pub struct DownloadFileCommand {
+    peer_id: String,
+    offer: FileOffer,
+    directory: PathBuf,
+}
+
+impl IntoPeerCommand for DownloadFileCommand {
+    type Output = ();
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::DownloadFile(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+/// Sends an encrypted message to a single peer, answering its id and
+/// whether it went straight to the peer or into its mailbox.
+#[derive(Debug, Getters, Builder)]
+pub struct DirectMessageCommand {
+    peer_id: String,
+    message: String,
+}
+
+impl IntoPeerCommand for DirectMessageCommand {
+    type Output = (String, Delivery);
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::DirectMessage(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+/// Issues a signed invite to a room, carrying our current addresses.
+#[derive(Debug, Getters, Builder)]
+pub struct CreateInviteCommand {
+    topic: String,
+    /// Key of the room, handed to whoever redeems the invite.
+    key: Option<Vec<u8>>,
+    #[builder(default = DEFAULT_INVITE_TTL)]
+    ttl: Duration,
+}
+
+impl IntoPeerCommand for CreateInviteCommand {
+    type Output = Invite;
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::CreateInvite(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
+/// Checks an invite token, connects to its issuer and joins the room.
+#[derive(Debug, Getters, Builder)]
+pub struct AcceptInviteCommand {
+    token: String,
+}
+
+impl IntoPeerCommand for AcceptInviteCommand {
+    type Output = Invite;
+    fn into_command(
+        self,
+        sender: oneshot::Sender<PeerResult<Self::Output>>,
+    ) -> PeerCommand {
+        PeerCommand::AcceptInvite(Command {
+            command: self,
+            sender,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PeerCommandBus {
     sender: UnboundedSender<PeerCommand>,
+    latency: CommandLatency,
 }
 
 impl PeerCommandBus {
     pub fn new(sender: UnboundedSender<PeerCommand>) -> Self {
-        Self { sender }
+        Self {
+            sender,
+            latency: CommandLatency::default(),
+        }
+    }
+
+    pub(crate) fn with_latency(mut self, latency: CommandLatency) -> Self {
+        self.latency = latency;
+        self
     }
 
     pub async fn send<C: IntoPeerCommand>(
@@ -110,8 +468,13 @@ impl PeerCommandBus {
         command: C,
     ) -> PeerResult<C::Output> {
         let (tx, rx) = oneshot::channel();
-        self.sender.send(command.into_command(tx))?;
-        rx.await?
+        let command = command.into_command(tx);
+        let name = command.name();
+        let start = Instant::now();
+        self.sender.send(command)?;
+        let response = rx.await?;
+        self.latency.observe(name, start.elapsed().as_secs_f64());
+        response
     }
 }
 
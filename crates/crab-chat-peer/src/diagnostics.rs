@@ -0,0 +1,90 @@
+use bon::Builder;
+use derive_getters::Getters;
+use libp2p::Multiaddr;
+use libp2p::multiaddr::Protocol;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Dial failures kept for [`NetworkInfo::dial_errors`].
+pub(crate) const MAX_DIAL_ERRORS: usize = 10;
+
+/// How the peer is connected, as answered by
+/// [`NetworkInfoCommand`](crate::NetworkInfoCommand).
+#[derive(
+    Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Getters, Builder,
+)]
+pub struct NetworkInfo {
+    peer_id: String,
+    listen_addrs: Vec<String>,
+    peers: Vec<ConnectedPeer>,
+    routing_table_size: usize,
+    /// Mesh peers of every room we are in.
+    mesh: Vec<TopicMesh>,
+    /// Most recent last.
+    dial_errors: Vec<DialFailure>,
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Getters, Builder,
+)]
+pub struct ConnectedPeer {
+    peer_id: String,
+    address: String,
+    transport: String,
+    /// Round trip of the latest ping, once one came back.
+    rtt: Option<Duration>,
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Getters, Builder,
+)]
+pub struct TopicMesh {
+    topic: String,
+    peers: Vec<String>,
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Getters, Builder,
+)]
+pub struct DialFailure {
+    /// The peer we meant to reach, when we knew who it was.
+    peer_id: Option<String>,
+    error: String,
+    timestamp: u64,
+}
+
+/// A live connection, as tracked by the event loop.
+#[derive(Debug, Clone)]
+pub(crate) struct Connection {
+    pub address: Multiaddr,
+    pub rtt: Option<Duration>,
+}
+
+/// Name of the transport an address goes over, such as `tcp` or `memory`.
+pub(crate) fn transport_name(address: &Multiaddr) -> String {
+    address
+        .iter()
+        .find_map(|protocol| match protocol {
+            Protocol::Tcp(_) => Some("tcp"),
+            Protocol::Udp(_) => Some("udp"),
+            Protocol::Memory(_) => Some("memory"),
+            Protocol::Ws(_) | Protocol::Wss(_) => Some("websocket"),
+            _ => None,
+        })
+        .unwrap_or("unknown")
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport_name() {
+        let tcp: Multiaddr = "/ip4/10.0.0.1/tcp/4001".parse().unwrap();
+        assert_eq!(transport_name(&tcp), "tcp");
+        let memory: Multiaddr = "/memory/7".parse().unwrap();
+        assert_eq!(transport_name(&memory), "memory");
+        assert_eq!(transport_name(&Multiaddr::empty()), "unknown");
+    }
+}
@@ -1,6 +1,8 @@
 use libp2p::{
     TransportError,
     gossipsub::{PublishError, SubscriptionError},
+    identity::SigningError,
+    swarm::DialError,
 };
 use tokio::{io, sync::mpsc};
 
@@ -14,6 +16,39 @@ pub enum PeerError {
     #[error("Failed to parse bootstrap address: {0}")]
     InvalidBootstrapError(Box<dyn std::error::Error + Send + Sync>),
 
+    #[error("Invalid reaction: {0}")]
+    InvalidReaction(String),
+
+    #[error("Failed to sign: {0}")]
+    SigningError(#[from] SigningError),
+
+    #[error("Invalid peer id: {0}")]
+    InvalidPeerId(String),
+
+    #[error("File of {0} bytes exceeds the size limit")]
+    FileTooLarge(u64),
+
+    #[error("Not connected to {0}")]
+    NotConnected(String),
+
+    #[error("Failed to dial: {0}")]
+    DialError(#[from] DialError),
+
+    #[error("Invalid invite: {0}")]
+    InvalidInvite(String),
+
+    #[error("The invite has expired")]
+    InviteExpired,
+
+    #[error("Failed to load identity: {0}")]
+    IdentityError(io::Error),
+
+    #[error("Failed to seal or open a direct message: {0}")]
+    EncryptionError(String),
+
+    #[error("File transfer failed: {0}")]
+    TransferError(#[from] io::Error),
+
     #[error("Failed to send command: {0}")]
     CommandResponseError(#[from] tokio::sync::oneshot::error::RecvError),
 
@@ -27,5 +62,12 @@ pub enum PeerError {
     SubscribeError(#[from] SubscriptionError),
 
     #[error("Failed to send event: {0}")]
-    SendError(#[from] mpsc::error::SendError<PeerCommand>),
+    SendError(Box<mpsc::error::SendError<PeerCommand>>),
+}
+
+// boxed as the command can be large, and every result carries this type
+impl From<mpsc::error::SendError<PeerCommand>> for PeerError {
+    fn from(error: mpsc::error::SendError<PeerCommand>) -> Self {
+        Self::SendError(Box::new(error))
+    }
 }
@@ -1,12 +1,102 @@
 use bon::Builder;
 use derive_getters::Getters;
+use futures::future::ready;
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use tokio::sync::broadcast;
 
+use crate::message::FileOffer;
+use crate::outbox::OutboundStatus;
+use crate::signal::SignalKind;
+use crate::store::Reactions;
+use crate::transfer::{TransferDirection, TransferStatus};
+
 #[derive(Clone, Debug)]
 pub enum PeerEvent {
     MessageReceived(MessageReceivedEvent),
     PeerJoined(PeerJoinedEvent),
     PeerLeft(PeerLeftEvent),
+    ReactionsChanged(ReactionsChangedEvent),
+    PresenceChanged(PresenceChangedEvent),
+    ReadReceipt(ReadReceiptEvent),
+    FileOffered(FileOfferedEvent),
+    FileTransfer(FileTransferEvent),
+    Outbound(OutboundEvent),
+    Connection(ConnectionEvent),
+    /// Never emitted on the bus: a listener that fell behind reports how
+    /// many events it missed before going on with the next ones.
+    Lagged(LaggedEvent),
+}
+
+/// Kind of a [`PeerEvent`], to subscribe to some of them only.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    MessageReceived,
+    PeerJoined,
+    PeerLeft,
+    ReactionsChanged,
+    PresenceChanged,
+    ReadReceipt,
+    FileOffered,
+    FileTransfer,
+    Outbound,
+    Connection,
+    Lagged,
+}
+
+impl PeerEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::MessageReceived(_) => EventKind::MessageReceived,
+            Self::PeerJoined(_) => EventKind::PeerJoined,
+            Self::PeerLeft(_) => EventKind::PeerLeft,
+            Self::ReactionsChanged(_) => EventKind::ReactionsChanged,
+            Self::PresenceChanged(_) => EventKind::PresenceChanged,
+            Self::ReadReceipt(_) => EventKind::ReadReceipt,
+            Self::FileOffered(_) => EventKind::FileOffered,
+            Self::FileTransfer(_) => EventKind::FileTransfer,
+            Self::Outbound(_) => EventKind::Outbound,
+            Self::Connection(_) => EventKind::Connection,
+            Self::Lagged(_) => EventKind::Lagged,
+        }
+    }
+
+    /// The room the event happened in, for the events tied to one.
+    pub fn topic(&self) -> Option<&str> {
+        match self {
+            Self::MessageReceived(event) => Some(event.topic()),
+            Self::PeerJoined(event) => Some(event.topic()),
+            Self::PeerLeft(event) => Some(event.topic()),
+            Self::ReactionsChanged(event) => Some(event.topic()),
+            Self::PresenceChanged(event) => Some(event.topic()),
+            Self::ReadReceipt(event) => Some(event.topic()),
+            Self::FileOffered(event) => Some(event.topic()),
+            Self::Outbound(event) => Some(event.topic()),
+            Self::FileTransfer(_) | Self::Connection(_) | Self::Lagged(_) => {
+                None
+            }
+        }
+    }
+}
+
+/// The listener could not keep up and `missed` events were dropped for it,
+/// whether or not they would have passed its filter.
+#[derive(Clone, Debug, Getters, Builder)]
+pub struct LaggedEvent {
+    missed: u64,
+}
+
+/// We got our first connection to a peer, or lost our last one to it.
+#[derive(Clone, Debug, Getters, Builder)]
+pub struct ConnectionEvent {
+    peer_id: String,
+    connected: bool,
+    /// Peers we are connected to now.
+    connected_peers: usize,
 }
 
 #[derive(Clone, Debug, Getters, Builder)]
@@ -30,42 +120,364 @@ pub struct MessageReceivedEvent {
     timestamp: u64,
     topic: String,
     peer_id: String,
+    nickname: Option<String>,
+    parent_id: Option<String>,
+    #[builder(default)]
+    mentions: Vec<String>,
+}
+
+#[derive(Clone, Debug, Getters, Builder)]
+pub struct ReactionsChangedEvent {
+    message_id: String,
+    topic: String,
+    peer_id: String,
+    reactions: Reactions,
+    timestamp: u64,
+}
+
+/// A peer's ephemeral state in a room changed; `state` is `None` once it
+/// was cleared or expired.
+#[derive(Clone, Debug, Getters, Builder)]
+pub struct PresenceChangedEvent {
+    topic: String,
+    peer_id: String,
+    nickname: Option<String>,
+    state: Option<SignalKind>,
+    timestamp: u64,
+}
+
+/// A peer read a room up to `message_id`; only emitted for receipts whose
+/// signature checked out.
+#[derive(Clone, Debug, Getters, Builder)]
+pub struct ReadReceiptEvent {
+    topic: String,
+    peer_id: String,
+    nickname: Option<String>,
+    message_id: String,
+    timestamp: u64,
+}
+
+/// A file was offered in a room, by another peer or by us.
+#[derive(Clone, Debug, Getters, Builder)]
+pub struct FileOfferedEvent {
+    message_id: String,
+    topic: String,
+    peer_id: String,
+    nickname: Option<String>,
+    offer: FileOffer,
+    timestamp: u64,
+}
+
+/// Progress of a file being sent to or received from `peer_id`.
+#[derive(Clone, Debug, Getters, Builder)]
+pub struct FileTransferEvent {
+    file_id: String,
+    peer_id: String,
+    direction: TransferDirection,
+    status: TransferStatus,
+    timestamp: u64,
+}
+
+/// A message that found nobody to receive it was queued, sent later, or
+/// given up on.
+#[derive(Clone, Debug, Getters, Builder)]
+pub struct OutboundEvent {
+    /// Outbox id, as answered when the message was queued.
+    id: String,
+    topic: String,
+    status: OutboundStatus,
+    timestamp: u64,
 }
 
+/// Which events a listener receives; empty lists let everything through.
+///
+/// Events tied to no room are dropped once topics are given, and lag
+/// notifications always get through.
+#[derive(Clone, Debug, Default, Getters, Builder)]
+pub struct EventFilter {
+    #[builder(default)]
+    kinds: Vec<EventKind>,
+    #[builder(default)]
+    topics: Vec<String>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &PeerEvent) -> bool {
+        let kind = event.kind();
+        if kind == EventKind::Lagged {
+            return true;
+        }
+        if !self.kinds.is_empty() && !self.kinds.contains(&kind) {
+            return false;
+        }
+        self.topics.is_empty()
+            || event
+                .topic()
+                .is_some_and(|topic| self.topics.iter().any(|t| t == topic))
+    }
+}
+
+/// Events a listener may fall behind by before it starts missing some.
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Recent events kept to replay to listeners that subscribe late.
+const REPLAY_CAPACITY: usize = 100;
+
+/// Fans events out to any number of listeners, possibly none, keeping the
+/// latest ones around for listeners that ask for a replay.
 #[derive(Clone, Debug)]
 pub struct PeerEventBus {
     sender: broadcast::Sender<PeerEvent>,
+    recent: Arc<Mutex<VecDeque<PeerEvent>>>,
+    replay_capacity: usize,
+}
+
+impl Default for PeerEventBus {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PeerEventBus {
     pub fn new() -> Self {
-        let (sender, _) = broadcast::channel(100);
-        Self { sender }
+        Self::with_capacity(CHANNEL_CAPACITY, REPLAY_CAPACITY)
     }
 
-    pub fn subscribe(&self) -> PeerEventListener {
-        PeerEventListener {
-            receiver: self.sender.subscribe(),
+    pub fn with_capacity(capacity: usize, replay_capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender,
+            recent: Arc::new(Mutex::new(VecDeque::new())),
+            replay_capacity,
         }
     }
 
+    /// Listens to every event from now on.
+    pub fn subscribe(&self) -> PeerEventListener {
+        self.subscribe_with(EventFilter::default(), false)
+    }
+
+    /// Listens to the events passing `filter`, starting with the recent ones
+    /// still kept when `replay` is set.
+    pub fn subscribe_with(
+        &self,
+        filter: EventFilter,
+        replay: bool,
+    ) -> PeerEventListener {
+        // subscribing under the lock so no event is both replayed and
+        // received, or neither
+        let recent = self.recent.lock().unwrap();
+        let pending = match replay {
+            true => recent
+                .iter()
+                .filter(|event| filter.matches(event))
+                .cloned()
+                .collect(),
+            false => VecDeque::new(),
+        };
+        PeerEventListener::new(self.sender.subscribe(), filter, pending)
+    }
+
     pub fn emit(&self, event: PeerEvent) {
-        self.sender.send(event).unwrap();
+        let mut recent = self.recent.lock().unwrap();
+        if self.replay_capacity > 0 {
+            if recent.len() == self.replay_capacity {
+                recent.pop_front();
+            }
+            recent.push_back(event.clone());
+        }
+        // nobody listening is fine, the event only goes to the replay buffer
+        let _ = self.sender.send(event);
     }
 }
 
+/// A subscription to the bus, read with [`PeerEventListener::recv`] or as a
+/// [`Stream`] that ends once the peer is gone.
 pub struct PeerEventListener {
+    events: BoxStream<'static, PeerEvent>,
+}
+
+/// What the listener stream is unfolded from.
+struct ListenerState {
     receiver: broadcast::Receiver<PeerEvent>,
+    filter: EventFilter,
+    /// Replayed events, handed out before the live ones.
+    pending: VecDeque<PeerEvent>,
+}
+
+impl ListenerState {
+    async fn next(&mut self) -> Option<PeerEvent> {
+        if let Some(event) = self.pending.pop_front() {
+            return Some(event);
+        }
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) if self.filter.matches(&event) => return Some(event),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    let event = LaggedEvent::builder().missed(missed).build();
+                    return Some(PeerEvent::Lagged(event));
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
 }
 
 impl PeerEventListener {
-    pub fn new(receiver: broadcast::Receiver<PeerEvent>) -> Self {
-        Self { receiver }
+    fn new(
+        receiver: broadcast::Receiver<PeerEvent>,
+        filter: EventFilter,
+        pending: VecDeque<PeerEvent>,
+    ) -> Self {
+        let state = ListenerState {
+            receiver,
+            filter,
+            pending,
+        };
+        let events = stream::unfold(state, |mut state| async move {
+            let event = state.next().await?;
+            Some((event, state))
+        });
+        Self {
+            events: events.boxed(),
+        }
     }
 
-    pub async fn recv(
-        &mut self,
-    ) -> Result<PeerEvent, broadcast::error::RecvError> {
-        self.receiver.recv().await
+    /// The next event passing the filter, a [`PeerEvent::Lagged`] when some
+    /// were missed, or `None` once the peer is gone.
+    pub async fn recv(&mut self) -> Option<PeerEvent> {
+        self.events.next().await
+    }
+
+    /// The received messages only; lag notifications are dropped.
+    pub fn messages(
+        self,
+    ) -> impl Stream<Item = MessageReceivedEvent> + Send + Unpin {
+        self.filter_map(|event| {
+            ready(match event {
+                PeerEvent::MessageReceived(event) => Some(event),
+                _ => None,
+            })
+        })
+    }
+
+    /// The presence changes only; lag notifications are dropped.
+    pub fn presence(
+        self,
+    ) -> impl Stream<Item = PresenceChangedEvent> + Send + Unpin {
+        self.filter_map(|event| {
+            ready(match event {
+                PeerEvent::PresenceChanged(event) => Some(event),
+                _ => None,
+            })
+        })
+    }
+}
+
+impl Stream for PeerEventListener {
+    type Item = PeerEvent;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<PeerEvent>> {
+        self.events.poll_next_unpin(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn joined(topic: &str) -> PeerEvent {
+        PeerEvent::PeerJoined(
+            PeerJoinedEvent::builder()
+                .peer_id("peer".to_owned())
+                .topic(topic.to_owned())
+                .timestamp(0)
+                .build(),
+        )
+    }
+
+    fn left(topic: &str) -> PeerEvent {
+        PeerEvent::PeerLeft(
+            PeerLeftEvent::builder()
+                .peer_id("peer".to_owned())
+                .topic(topic.to_owned())
+                .timestamp(0)
+                .build(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_filter_and_no_subscribers() {
+        let bus = PeerEventBus::new();
+        // nobody listens yet
+        bus.emit(joined("a"));
+
+        let filter = EventFilter::builder()
+            .kinds(vec![EventKind::PeerLeft])
+            .topics(vec!["b".to_owned()])
+            .build();
+        let mut listener = bus.subscribe_with(filter, false);
+        bus.emit(left("a"));
+        bus.emit(joined("b"));
+        bus.emit(left("b"));
+
+        let event = listener.recv().await.unwrap();
+        assert_eq!(event.kind(), EventKind::PeerLeft);
+        assert_eq!(event.topic(), Some("b"));
+        drop(bus);
+        assert!(listener.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_replay_and_lag() {
+        let bus = PeerEventBus::with_capacity(2, 2);
+        bus.emit(joined("a"));
+        bus.emit(joined("b"));
+        bus.emit(joined("c"));
+
+        let mut late = bus.subscribe_with(EventFilter::default(), true);
+        let mut slow = bus.subscribe();
+        assert_eq!(late.recv().await.unwrap().topic(), Some("b"));
+        assert_eq!(late.recv().await.unwrap().topic(), Some("c"));
+
+        for topic in ["d", "e", "f", "g", "h"] {
+            bus.emit(joined(topic));
+        }
+        let Some(PeerEvent::Lagged(lagged)) = slow.recv().await else {
+            panic!("expected a lag notification");
+        };
+        assert_eq!(*lagged.missed(), 3);
+        assert_eq!(slow.recv().await.unwrap().topic(), Some("g"));
+    }
+
+    #[tokio::test]
+    async fn test_typed_streams() {
+        let bus = PeerEventBus::new();
+        let messages = bus.subscribe().messages();
+        let kinds = bus.subscribe().map(|event| event.kind());
+        for text in ["hi", "there"] {
+            bus.emit(joined("a"));
+            bus.emit(PeerEvent::MessageReceived(
+                MessageReceivedEvent::builder()
+                    .message_id(text.to_owned())
+                    .message(text.to_owned())
+                    .timestamp(0)
+                    .topic("a".to_owned())
+                    .peer_id("peer".to_owned())
+                    .build(),
+            ));
+        }
+        drop(bus);
+
+        let texts: Vec<_> = messages
+            .map(|event| event.message().clone())
+            .collect()
+            .await;
+        assert_eq!(texts, vec!["hi", "there"]);
+        assert_eq!(kinds.count().await, 4);
     }
 }
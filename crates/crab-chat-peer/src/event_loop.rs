@@ -0,0 +1,1201 @@
+use super::command::{
+    AcceptInviteCommand, Command, CreateInviteCommand, DirectMessageCommand,
+    DownloadFileCommand, OfferFileCommand, PeerCommand, ReactCommand,
+    ReceiptCommand, SignalCommand,
+};
+use super::event::{
+    ConnectionEvent, MessageReceivedEvent, PeerEvent, PeerEventBus,
+    PeerJoinedEvent, FileOfferedEvent, FileTransferEvent, PeerLeftEvent,
+    PresenceChangedEvent, OutboundEvent, ReactionsChangedEvent,
+    ReadReceiptEvent,
+};
+use super::invite::{Invite, MAX_INVITE_ADDRESSES};
+use super::mailbox::{
+    self, Delivered, Delivery, Envelope, Letter, MAILBOX_TTL, inbox_topic,
+    mailbox_key,
+};
+use super::outbox::{OutboundStatus, Outbox, SendOutcome};
+use super::message::{
+    FileOffer, Message, MessageKind, Reaction, ReadReceipt, TextMessage,
+};
+use super::diagnostics::{
+    ConnectedPeer, Connection, DialFailure, MAX_DIAL_ERRORS, NetworkInfo,
+    TopicMesh, transport_name,
+};
+use super::metrics::{MetricsSnapshot, PeerMetrics, TopicMetrics};
+use super::peer::{PeerBehaviour, PeerBehaviourEvent};
+use super::signal::{Presence, Signal, SignalKind, room_of_signal_topic};
+use super::store::{MessageStore, Reactions, StoredMessage};
+use super::transfer::{
+    self, MAX_FILE_SIZE, SharedFile, SharedFiles, StreamId, TransferDirection,
+    TransferStatus,
+};
+use super::{PeerError, PeerResult, SubscribeCommand};
+use chrono::Utc;
+use futures::StreamExt;
+use libp2p::gossipsub::{self, MessageId};
+use libp2p::identity::Keypair;
+use libp2p::swarm::SwarmEvent;
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::dial_opts::DialOpts;
+use libp2p::{Multiaddr, PeerId, Swarm, kad, mdns, ping};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Owns the swarm and every piece of state mutated by network events or
+/// commands, driving both from a single task.
+pub struct EventLoop {
+    local_peer_id: PeerId,
+    keypair: Keypair,
+    nickname: Option<String>,
+    swarm: Swarm<PeerBehaviour>,
+    command_bus_rx: mpsc::UnboundedReceiver<PeerCommand>,
+    event_bus: PeerEventBus,
+    store: MessageStore,
+    presence: Presence,
+    shared_files: SharedFiles,
+    downloads: HashMap<StreamId, PendingDownload>,
+    /// Offers hashed off the loop, waiting to be announced.
+    offers_tx: mpsc::UnboundedSender<PreparedOffer>,
+    offers_rx: mpsc::UnboundedReceiver<PreparedOffer>,
+    delivered: Delivered,
+    outbox: Outbox,
+    mailbox_queries: HashMap<kad::QueryId, MailboxQuery>,
+    metrics: PeerMetrics,
+    connections: HashMap<PeerId, Connection>,
+    dial_errors: VecDeque<DialFailure>,
+}
+
+/// How often we look for direct messages left while we were away.
+const MAILBOX_POLL: Duration = Duration::from_secs(60);
+
+type PreparedOffer =
+    (Command<OfferFileCommand, FileOffer>, io::Result<SharedFile>);
+
+/// Why a mailbox record is being looked up.
+enum MailboxQuery {
+    /// Collecting messages left for us.
+    Fetch,
+    /// Adding an envelope for an offline peer to its mailbox.
+    Deliver(PeerId, Envelope),
+}
+
+/// A download waiting for its stream to the offering peer.
+struct PendingDownload {
+    peer_id: PeerId,
+    offer: FileOffer,
+    directory: PathBuf,
+}
+
+#[bon::bon]
+impl EventLoop {
+    #[builder]
+    pub fn new(
+        swarm: Swarm<PeerBehaviour>,
+        keypair: Keypair,
+        nickname: Option<String>,
+        delivered_path: Option<PathBuf>,
+        history_path: Option<PathBuf>,
+        history_limit: usize,
+        outbox_ttl: Duration,
+        command_bus_rx: mpsc::UnboundedReceiver<PeerCommand>,
+        event_bus: PeerEventBus,
+        metrics: PeerMetrics,
+    ) -> Self {
+        let (offers_tx, offers_rx) = mpsc::unbounded_channel();
+        Self {
+            local_peer_id: *swarm.local_peer_id(),
+            keypair,
+            nickname,
+            swarm,
+            command_bus_rx,
+            event_bus,
+            store: history_path.map_or_else(MessageStore::new, |path| {
+                MessageStore::open(&path, history_limit)
+            }),
+            presence: Presence::new(),
+            shared_files: SharedFiles::default(),
+            downloads: HashMap::new(),
+            offers_tx,
+            offers_rx,
+            delivered: Delivered::load(delivered_path, now()),
+            outbox: Outbox::new(outbox_ttl),
+            mailbox_queries: HashMap::new(),
+            metrics,
+            connections: HashMap::new(),
+            dial_errors: VecDeque::new(),
+        }
+    }
+
+    pub async fn run(mut self) -> PeerResult<()> {
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+        let mut mailbox_tick = tokio::time::interval(MAILBOX_POLL);
+        loop {
+            tokio::select! {
+                event = self.swarm.select_next_some() => {
+                    self.handle_swarm_event(event);
+                }
+                cmd = self.command_bus_rx.recv() => match cmd {
+                    Some(cmd) => self.handle_command(cmd),
+                    // every handle to the peer is gone
+                    None => return Ok(()),
+                },
+                _ = tick.tick() => {
+                    self.expire_presence();
+                    self.expire_outbox();
+                }
+                _ = mailbox_tick.tick() => self.fetch_mailbox(),
+                Some((cmd, prepared)) = self.offers_rx.recv() => {
+                    self.publish_offer(cmd, prepared);
+                }
+            }
+        }
+    }
+
+    fn handle_swarm_event(&mut self, event: SwarmEvent<PeerBehaviourEvent>) {
+        self.metrics.record(&event);
+        match event {
+            SwarmEvent::Behaviour(PeerBehaviourEvent::Mdns(
+                mdns::Event::Discovered(items),
+            )) => {
+                for (peer, addr) in items {
+                    self.swarm.behaviour_mut().kad.add_address(&peer, addr);
+                }
+            }
+            SwarmEvent::Behaviour(PeerBehaviourEvent::Gossip(
+                gossipsub::Event::Message {
+                    propagation_source,
+                    message_id,
+                    message,
+                },
+            )) => {
+                let author = message.source.unwrap_or(propagation_source);
+                if message.topic.as_str() == inbox_topic(&self.local_peer_id) {
+                    match serde_json::from_slice::<Envelope>(&message.data) {
+                        Ok(envelope) => self.receive_envelope(envelope),
+                        Err(e) => log::warn!(
+                            "Discarding malformed direct message from {author}: {e}"
+                        ),
+                    }
+                    return;
+                }
+                if let Some(room) = room_of_signal_topic(message.topic.as_str())
+                {
+                    match serde_json::from_slice::<Signal>(&message.data) {
+                        Ok(signal) => self.handle_signal(room, &author, signal),
+                        Err(e) => log::warn!(
+                            "Discarding malformed signal from {author}: {e}"
+                        ),
+                    }
+                    return;
+                }
+                match serde_json::from_slice::<Message>(&message.data) {
+                    Ok(message) => {
+                        self.handle_message(&message_id, &author, message)
+                    }
+                    Err(e) => {
+                        self.metrics.rejected(message.topic.as_str());
+                        log::warn!(
+                            "Discarding malformed message {message_id} from {author}: {e}"
+                        )
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(PeerBehaviourEvent::Transfer(event)) => {
+                self.handle_transfer(event);
+            }
+            SwarmEvent::Behaviour(PeerBehaviourEvent::Kad(event)) => {
+                self.handle_kad(event);
+            }
+            SwarmEvent::ConnectionEstablished {
+                peer_id, endpoint, ..
+            } => {
+                let connection = Connection {
+                    address: endpoint.get_remote_address().clone(),
+                    rtt: None,
+                };
+                if self.connections.insert(peer_id, connection).is_none() {
+                    self.connection_changed(peer_id, true);
+                }
+                // back online, look for what was left while we were away
+                if self.swarm.connected_peers().count() == 1 {
+                    self.fetch_mailbox();
+                }
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established: 0,
+                ..
+            } if self.connections.remove(&peer_id).is_some() => {
+                self.connection_changed(peer_id, false);
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                if self.dial_errors.len() == MAX_DIAL_ERRORS {
+                    self.dial_errors.pop_front();
+                }
+                self.dial_errors.push_back(
+                    DialFailure::builder()
+                        .maybe_peer_id(peer_id.map(|p| p.to_string()))
+                        .error(error.to_string())
+                        .timestamp(now())
+                        .build(),
+                );
+            }
+            SwarmEvent::Behaviour(PeerBehaviourEvent::Ping(ping::Event {
+                peer,
+                result,
+                ..
+            })) => {
+                if let Some(connection) = self.connections.get_mut(&peer) {
+                    connection.rtt = result.ok();
+                }
+            }
+            SwarmEvent::Behaviour(PeerBehaviourEvent::Gossip(
+                gossipsub::Event::Subscribed { peer_id, topic },
+            )) if room_of_signal_topic(topic.as_str()).is_none() => {
+                self.flush_outbox(topic.as_str());
+                self.event_bus.emit(PeerEvent::PeerJoined(
+                    PeerJoinedEvent::builder()
+                        .peer_id(peer_id.to_string())
+                        .topic(topic.to_string())
+                        .timestamp(Utc::now().timestamp() as u64)
+                        .build(),
+                ));
+            }
+            SwarmEvent::Behaviour(PeerBehaviourEvent::Gossip(
+                gossipsub::Event::Unsubscribed { peer_id, topic },
+            )) if room_of_signal_topic(topic.as_str()).is_none() => {
+                self.event_bus.emit(PeerEvent::PeerLeft(
+                    PeerLeftEvent::builder()
+                        .peer_id(peer_id.to_string())
+                        .topic(topic.to_string())
+                        .timestamp(Utc::now().timestamp() as u64)
+                        .build(),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_command(&mut self, cmd: PeerCommand) {
+        match cmd {
+            PeerCommand::SendMessage(cmd) => {
+                let command = cmd.as_ref();
+                let text = TextMessage::builder()
+                    .data(command.message().clone())
+                    .maybe_parent_id(command.parent_id().clone())
+                    .mentions(command.mentions().clone())
+                    .build();
+                let message = self
+                    .message(command.topic(), MessageKind::Text(text.clone()));
+                let response = self.send_message(message, &text);
+                cmd.send(response);
+            }
+            PeerCommand::Subscribe(cmd) => {
+                log::info!("Subscribing to topic: {}", cmd.as_ref().topic());
+                let response =
+                    self.swarm.behaviour_mut().subscribe(cmd.as_ref());
+                cmd.send(response.map_err(PeerError::from));
+            }
+            PeerCommand::Unsubscribe(cmd) => {
+                self.presence.clear(cmd.as_ref().topic());
+                for queued in self.outbox.take(cmd.as_ref().topic()) {
+                    self.emit_outbound(
+                        cmd.as_ref().topic(),
+                        queued.id,
+                        OutboundStatus::Failed {
+                            error: "left the room".to_owned(),
+                        },
+                    );
+                }
+                let response =
+                    self.swarm.behaviour_mut().unsubscribe(cmd.as_ref());
+                cmd.send(Ok(response));
+            }
+            PeerCommand::History(cmd) => {
+                let command = cmd.as_ref();
+                let history =
+                    self.store.history(command.topic(), *command.limit());
+                cmd.send(Ok(history));
+            }
+            PeerCommand::Thread(cmd) => {
+                let thread = self.store.thread(cmd.as_ref().message_id());
+                cmd.send(Ok(thread));
+            }
+            PeerCommand::Import(cmd) => {
+                let messages = cmd.as_ref().messages().clone();
+                let imported = self.store.import(messages);
+                cmd.send(Ok(imported));
+            }
+            PeerCommand::React(cmd) => {
+                let response = self.react(cmd.as_ref());
+                cmd.send(response);
+            }
+            PeerCommand::Signal(cmd) => {
+                let response = self.signal(cmd.as_ref());
+                cmd.send(response);
+            }
+            PeerCommand::Receipt(cmd) => {
+                let response = self.receipt(cmd.as_ref());
+                cmd.send(response);
+            }
+            PeerCommand::OfferFile(cmd) => {
+                // hashing a large file would stall the swarm
+                let offers_tx = self.offers_tx.clone();
+                tokio::task::spawn_blocking(move || {
+                    let prepared = transfer::prepare_offer(cmd.as_ref().path());
+                    let _ = offers_tx.send((cmd, prepared));
+                });
+            }
+            PeerCommand::DownloadFile(cmd) => {
+                let response = self.download(cmd.as_ref());
+                cmd.send(response);
+            }
+            PeerCommand::DirectMessage(cmd) => {
+                let response = self.direct_message(cmd.as_ref());
+                cmd.send(response);
+            }
+            PeerCommand::CreateInvite(cmd) => {
+                let response = self.create_invite(cmd.as_ref());
+                cmd.send(response);
+            }
+            PeerCommand::AcceptInvite(cmd) => {
+                let response = self.accept_invite(cmd.as_ref());
+                cmd.send(response);
+            }
+            PeerCommand::MetricsSnapshot(cmd) => {
+                cmd.send(Ok(self.metrics_snapshot()));
+            }
+            PeerCommand::NetworkInfo(cmd) => {
+                cmd.send(Ok(self.network_info()));
+            }
+            PeerCommand::Disconnect(cmd) => {
+                let response = self.disconnect(cmd.as_ref().peer_id());
+                cmd.send(response);
+            }
+            PeerCommand::Dial(cmd) => {
+                let address = cmd.as_ref().address().clone();
+                log::info!("Dialing {address}");
+                let response = self.swarm.dial(address).map_err(Into::into);
+                cmd.send(response);
+            }
+        }
+    }
+
+    fn network_info(&mut self) -> NetworkInfo {
+        let mut peers: Vec<_> = self
+            .connections
+            .iter()
+            .map(|(peer_id, connection)| {
+                ConnectedPeer::builder()
+                    .peer_id(peer_id.to_string())
+                    .address(connection.address.to_string())
+                    .transport(transport_name(&connection.address))
+                    .maybe_rtt(connection.rtt)
+                    .build()
+            })
+            .collect();
+        peers.sort_by(|a, b| a.peer_id().cmp(b.peer_id()));
+        let listen_addrs =
+            self.swarm.listeners().map(|a| a.to_string()).collect();
+        let behaviour = self.swarm.behaviour_mut();
+        let routing_table_size =
+            behaviour.kad.kbuckets().map(|b| b.num_entries()).sum();
+        let own_inbox = inbox_topic(&self.local_peer_id);
+        let mut mesh: Vec<_> = behaviour
+            .gossip
+            .topics()
+            .filter(|topic| {
+                room_of_signal_topic(topic.as_str()).is_none()
+                    && topic.as_str() != own_inbox
+            })
+            .map(|topic| {
+                TopicMesh::builder()
+                    .topic(topic.to_string())
+                    .peers(
+                        behaviour
+                            .gossip
+                            .mesh_peers(topic)
+                            .map(|p| p.to_string())
+                            .collect(),
+                    )
+                    .build()
+            })
+            .collect();
+        mesh.sort_by(|a, b| a.topic().cmp(b.topic()));
+        NetworkInfo::builder()
+            .peer_id(self.local_peer_id.to_string())
+            .listen_addrs(listen_addrs)
+            .peers(peers)
+            .routing_table_size(routing_table_size)
+            .mesh(mesh)
+            .dial_errors(self.dial_errors.iter().cloned().collect())
+            .build()
+    }
+
+    fn connection_changed(&self, peer_id: PeerId, connected: bool) {
+        self.event_bus.emit(PeerEvent::Connection(
+            ConnectionEvent::builder()
+                .peer_id(peer_id.to_string())
+                .connected(connected)
+                .connected_peers(self.connections.len())
+                .build(),
+        ));
+    }
+
+    fn disconnect(&mut self, peer_id: &str) -> PeerResult<()> {
+        let peer_id: PeerId = peer_id
+            .parse()
+            .map_err(|_| PeerError::InvalidPeerId(peer_id.to_owned()))?;
+        self.swarm
+            .disconnect_peer_id(peer_id)
+            .map_err(|_| PeerError::NotConnected(peer_id.to_string()))
+    }
+
+    fn metrics_snapshot(&mut self) -> MetricsSnapshot {
+        let behaviour = self.swarm.behaviour_mut();
+        let routing_table_size =
+            behaviour.kad.kbuckets().map(|b| b.num_entries()).sum();
+        let own_inbox = inbox_topic(&self.local_peer_id);
+        let topics = behaviour
+            .gossip
+            .topics()
+            .filter(|topic| {
+                room_of_signal_topic(topic.as_str()).is_none()
+                    && topic.as_str() != own_inbox
+            })
+            .map(|topic| {
+                TopicMetrics::new(
+                    topic.to_string(),
+                    behaviour.gossip.mesh_peers(topic).count(),
+                    self.metrics.topic_counts(topic.as_str()),
+                )
+            })
+            .collect();
+        MetricsSnapshot::new(
+            self.swarm.connected_peers().count(),
+            routing_table_size,
+            topics,
+            self.metrics.encode(),
+        )
+    }
+
+    /// Publishes a text message, queueing it when nobody is subscribed or
+    /// older messages of the room still wait.
+    fn send_message(
+        &mut self,
+        message: Message,
+        text: &TextMessage,
+    ) -> PeerResult<SendOutcome> {
+        let topic = message.topic().clone();
+        // the messages waiting for the room go out first
+        self.flush_outbox(&topic);
+        if self.outbox.is_waiting(&topic) {
+            return self.queue(message);
+        }
+        match self.swarm.behaviour_mut().publish(&message) {
+            Ok(message_id) => {
+                self.metrics.published(message.topic());
+                self.store.insert(stored_message(
+                    &message_id,
+                    &self.local_peer_id,
+                    &message,
+                    text,
+                ));
+                Ok(SendOutcome::Sent(message_id))
+            }
+            Err(gossipsub::PublishError::InsufficientPeers) => {
+                self.queue(message)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn queue(&mut self, message: Message) -> PeerResult<SendOutcome> {
+        let topic = message.topic().clone();
+        let Some(id) = self.outbox.push(message, Instant::now()) else {
+            return Err(gossipsub::PublishError::InsufficientPeers.into());
+        };
+        self.emit_outbound(&topic, id.clone(), OutboundStatus::Queued);
+        Ok(SendOutcome::Queued(id))
+    }
+
+    /// Retries the messages waiting for peers in `topic`, in order.
+    fn flush_outbox(&mut self, topic: &str) {
+        let mut queued = self.outbox.take(topic);
+        while let Some(next) = queued.pop_front() {
+            let MessageKind::Text(text) = next.message.kind() else {
+                let error = "only text messages can wait for peers".to_owned();
+                let status = OutboundStatus::Failed { error };
+                self.emit_outbound(topic, next.id, status);
+                continue;
+            };
+            let status = match self.swarm.behaviour_mut().publish(&next.message)
+            {
+                Ok(message_id) => {
+                    self.metrics.published(topic);
+                    self.store.insert(stored_message(
+                        &message_id,
+                        &self.local_peer_id,
+                        &next.message,
+                        text,
+                    ));
+                    OutboundStatus::Sent {
+                        message_id: message_id.to_string(),
+                    }
+                }
+                Err(gossipsub::PublishError::InsufficientPeers) => {
+                    queued.push_front(next);
+                    break;
+                }
+                Err(e) => OutboundStatus::Failed {
+                    error: e.to_string(),
+                },
+            };
+            self.emit_outbound(topic, next.id, status);
+        }
+        self.outbox.restore(topic, queued);
+    }
+
+    fn expire_outbox(&mut self) {
+        for (topic, id) in self.outbox.expire(Instant::now()) {
+            let error = "no peers showed up in time".to_owned();
+            self.emit_outbound(&topic, id, OutboundStatus::Failed { error });
+        }
+    }
+
+    fn emit_outbound(&self, topic: &str, id: String, status: OutboundStatus) {
+        self.event_bus.emit(PeerEvent::Outbound(
+            OutboundEvent::builder()
+                .id(id)
+                .topic(topic.to_owned())
+                .status(status)
+                .timestamp(now())
+                .build(),
+        ));
+    }
+
+    fn message(&self, topic: &str, kind: MessageKind) -> Message {
+        Message::builder()
+            .timestamp(Utc::now().timestamp() as u64)
+            .topic(topic.to_owned())
+            .maybe_nickname(self.nickname.clone())
+            .kind(kind)
+            .build()
+    }
+
+    fn handle_message(
+        &mut self,
+        message_id: &MessageId,
+        author: &PeerId,
+        message: Message,
+    ) {
+        match message.kind() {
+            MessageKind::Text(text) => {
+                self.metrics.received(message.topic());
+                self.store
+                    .insert(stored_message(message_id, author, &message, text));
+                let event = MessageReceivedEvent::builder()
+                    .message_id(message_id.to_string())
+                    .message(text.data().clone())
+                    .timestamp(*message.timestamp())
+                    .topic(message.topic().clone())
+                    .peer_id(author.to_string())
+                    .maybe_nickname(message.nickname().clone())
+                    .maybe_parent_id(text.parent_id().clone())
+                    .mentions(text.mentions().clone())
+                    .build();
+                self.event_bus.emit(PeerEvent::MessageReceived(event));
+            }
+            MessageKind::Reaction(reaction) => {
+                if !Reaction::is_valid_shortcode(reaction.emoji()) {
+                    log::warn!("Discarding invalid reaction from {author}");
+                    return;
+                }
+                let author = author.to_string();
+                if self.store.react(
+                    reaction.message_id(),
+                    reaction.emoji(),
+                    &author,
+                    *message.timestamp(),
+                    *reaction.active(),
+                ) {
+                    self.emit_reactions(
+                        message.topic(),
+                        reaction.message_id(),
+                        author,
+                    );
+                }
+            }
+            MessageKind::FileOffer(offer) => {
+                self.emit_offer(message_id, author, &message, offer.clone());
+            }
+            MessageKind::Receipt(receipt) => {
+                if !receipt.verify(
+                    author,
+                    message.topic(),
+                    *message.timestamp(),
+                ) {
+                    log::warn!("Discarding forged read receipt from {author}");
+                    return;
+                }
+                self.event_bus.emit(PeerEvent::ReadReceipt(
+                    ReadReceiptEvent::builder()
+                        .topic(message.topic().clone())
+                        .peer_id(author.to_string())
+                        .maybe_nickname(message.nickname().clone())
+                        .message_id(receipt.message_id().clone())
+                        .timestamp(*message.timestamp())
+                        .build(),
+                ));
+            }
+        }
+    }
+
+    fn react(&mut self, command: &ReactCommand) -> PeerResult<Reactions> {
+        if !Reaction::is_valid_shortcode(command.emoji()) {
+            return Err(PeerError::InvalidReaction(command.emoji().clone()));
+        }
+
+        let local_peer_id = self.local_peer_id.to_string();
+        let active = !self.store.has_reacted(
+            command.message_id(),
+            command.emoji(),
+            &local_peer_id,
+        );
+        let reaction = Reaction::builder()
+            .message_id(command.message_id().clone())
+            .emoji(command.emoji().clone())
+            .active(active)
+            .build();
+        let message =
+            self.message(command.topic(), MessageKind::Reaction(reaction));
+        self.swarm.behaviour_mut().publish(&message)?;
+
+        self.store.react(
+            command.message_id(),
+            command.emoji(),
+            &local_peer_id,
+            *message.timestamp(),
+            active,
+        );
+        Ok(self.emit_reactions(
+            command.topic(),
+            command.message_id(),
+            local_peer_id,
+        ))
+    }
+
+    fn emit_reactions(
+        &self,
+        topic: &str,
+        message_id: &str,
+        peer_id: String,
+    ) -> Reactions {
+        let reactions = self.store.reactions(message_id);
+        self.event_bus.emit(PeerEvent::ReactionsChanged(
+            ReactionsChangedEvent::builder()
+                .message_id(message_id.to_owned())
+                .topic(topic.to_owned())
+                .peer_id(peer_id)
+                .reactions(reactions.clone())
+                .timestamp(Utc::now().timestamp() as u64)
+                .build(),
+        ));
+        reactions
+    }
+
+    fn publish_offer(
+        &mut self,
+        cmd: Command<OfferFileCommand, FileOffer>,
+        prepared: io::Result<SharedFile>,
+    ) {
+        let response = prepared.map_err(PeerError::from).and_then(|shared| {
+            let offer = shared.offer.clone();
+            let message = self.message(
+                cmd.as_ref().topic(),
+                MessageKind::FileOffer(offer.clone()),
+            );
+            let message_id = self.swarm.behaviour_mut().publish(&message)?;
+            self.shared_files
+                .lock()
+                .unwrap()
+                .insert(offer.file_id().clone(), shared);
+            let local_peer_id = self.local_peer_id;
+            self.emit_offer(
+                &message_id,
+                &local_peer_id,
+                &message,
+                offer.clone(),
+            );
+            Ok(offer)
+        });
+        cmd.send(response);
+    }
+
+    fn emit_offer(
+        &self,
+        message_id: &MessageId,
+        author: &PeerId,
+        message: &Message,
+        offer: FileOffer,
+    ) {
+        self.event_bus.emit(PeerEvent::FileOffered(
+            FileOfferedEvent::builder()
+                .message_id(message_id.to_string())
+                .topic(message.topic().clone())
+                .peer_id(author.to_string())
+                .maybe_nickname(message.nickname().clone())
+                .offer(offer)
+                .timestamp(*message.timestamp())
+                .build(),
+        ));
+    }
+
+    fn download(&mut self, command: &DownloadFileCommand) -> PeerResult<()> {
+        let size = *command.offer().size();
+        if size > MAX_FILE_SIZE {
+            return Err(PeerError::FileTooLarge(size));
+        }
+        let peer_id = command
+            .peer_id()
+            .parse::<PeerId>()
+            .map_err(|_| PeerError::InvalidPeerId(command.peer_id().clone()))?;
+
+        let id = self.swarm.behaviour_mut().transfer.open(peer_id);
+        self.downloads.insert(
+            id,
+            PendingDownload {
+                peer_id,
+                offer: command.offer().clone(),
+                directory: command.directory().clone(),
+            },
+        );
+        Ok(())
+    }
+
+    fn handle_transfer(&mut self, event: transfer::Event) {
+        match event {
+            transfer::Event::Inbound {
+                peer_id,
+                mut stream,
+            } => {
+                let files = self.shared_files.clone();
+                let event_bus = self.event_bus.clone();
+                tokio::spawn(async move {
+                    let result = transfer::serve(
+                        &mut stream,
+                        &files,
+                        |offer, status| {
+                            event_bus.emit(transfer_event(
+                                offer.file_id(),
+                                &peer_id,
+                                TransferDirection::Upload,
+                                status,
+                            ))
+                        },
+                    )
+                    .await;
+                    if let Err(e) = result {
+                        log::warn!("Failed to serve a file to {peer_id}: {e}");
+                    }
+                });
+            }
+            transfer::Event::Outbound { id, mut stream } => {
+                let Some(download) = self.downloads.remove(&id) else {
+                    return;
+                };
+                let event_bus = self.event_bus.clone();
+                tokio::spawn(async move {
+                    let PendingDownload {
+                        peer_id,
+                        offer,
+                        directory,
+                    } = download;
+                    let emit = |status| {
+                        event_bus.emit(transfer_event(
+                            offer.file_id(),
+                            &peer_id,
+                            TransferDirection::Download,
+                            status,
+                        ))
+                    };
+                    let status = match transfer::download(
+                        &mut stream,
+                        &offer,
+                        &directory,
+                        emit,
+                    )
+                    .await
+                    {
+                        Ok(path) => {
+                            TransferStatus::Completed { path: Some(path) }
+                        }
+                        Err(e) => TransferStatus::Failed {
+                            error: e.to_string(),
+                        },
+                    };
+                    emit(status);
+                });
+            }
+            transfer::Event::Failed { id, error } => {
+                if let Some(download) = self.downloads.remove(&id) {
+                    self.event_bus.emit(transfer_event(
+                        download.offer.file_id(),
+                        &download.peer_id,
+                        TransferDirection::Download,
+                        TransferStatus::Failed { error },
+                    ));
+                }
+            }
+        }
+    }
+
+    fn direct_message(
+        &mut self,
+        command: &DirectMessageCommand,
+    ) -> PeerResult<(String, Delivery)> {
+        let recipient = command
+            .peer_id()
+            .parse::<PeerId>()
+            .map_err(|_| PeerError::InvalidPeerId(command.peer_id().clone()))?;
+        let letter = Letter::builder()
+            .maybe_nickname(self.nickname.clone())
+            .timestamp(now())
+            .text(command.message().clone())
+            .build();
+        let envelope = Envelope::seal(&self.keypair, &recipient, letter)?;
+
+        let delivery = match self
+            .swarm
+            .behaviour_mut()
+            .publish_envelope(&recipient, &envelope)
+        {
+            Ok(_) => Delivery::Direct,
+            Err(gossipsub::PublishError::InsufficientPeers) => {
+                let query = self
+                    .swarm
+                    .behaviour_mut()
+                    .kad
+                    .get_record(mailbox_key(&recipient));
+                self.mailbox_queries.insert(
+                    query,
+                    MailboxQuery::Deliver(recipient, envelope.clone()),
+                );
+                Delivery::Mailbox
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        self.store.insert(
+            StoredMessage::builder()
+                .message_id(envelope.id().clone())
+                .peer_id(self.local_peer_id.to_string())
+                .maybe_nickname(self.nickname.clone())
+                .topic(inbox_topic(&recipient))
+                .data(command.message().clone())
+                .timestamp(now())
+                .build(),
+        );
+        Ok((envelope.id().clone(), delivery))
+    }
+
+    fn create_invite(
+        &mut self,
+        command: &CreateInviteCommand,
+    ) -> PeerResult<Invite> {
+        // loopback addresses only help when nothing else is known
+        let (mut addresses, loopback): (Vec<_>, Vec<_>) = self
+            .swarm
+            .external_addresses()
+            .chain(self.swarm.listeners())
+            .cloned()
+            .partition(|address| !is_loopback(address));
+        // a listener may be an external address as well
+        let mut seen = HashSet::new();
+        addresses.retain(|address| seen.insert(address.clone()));
+        if addresses.is_empty() {
+            addresses = loopback;
+        }
+        addresses.truncate(MAX_INVITE_ADDRESSES);
+
+        Invite::create(
+            &self.keypair,
+            command.topic().clone(),
+            command.key().clone(),
+            addresses,
+            now() + command.ttl().as_secs(),
+        )
+    }
+
+    fn accept_invite(
+        &mut self,
+        command: &AcceptInviteCommand,
+    ) -> PeerResult<Invite> {
+        let invite = Invite::decode(command.token(), now())?;
+        let issuer = invite.issuer();
+        if issuer != self.local_peer_id && !invite.addresses().is_empty() {
+            for address in invite.addresses() {
+                self.swarm
+                    .behaviour_mut()
+                    .kad
+                    .add_address(&issuer, address.clone());
+            }
+            let opts = DialOpts::peer_id(issuer)
+                .addresses(invite.addresses().clone())
+                .build();
+            if let Err(e) = self.swarm.dial(opts) {
+                log::warn!("Failed to dial the issuer of an invite: {e}");
+            }
+        }
+        self.swarm.behaviour_mut().subscribe(
+            &SubscribeCommand::builder()
+                .topic(invite.room().clone())
+                .build(),
+        )?;
+        Ok(invite)
+    }
+
+    fn fetch_mailbox(&mut self) {
+        if self.swarm.connected_peers().next().is_none() {
+            return;
+        }
+        let query = self
+            .swarm
+            .behaviour_mut()
+            .kad
+            .get_record(mailbox_key(&self.local_peer_id));
+        self.mailbox_queries.insert(query, MailboxQuery::Fetch);
+    }
+
+    fn handle_kad(&mut self, event: kad::Event) {
+        let kad::Event::OutboundQueryProgressed {
+            id, result, step, ..
+        } = event
+        else {
+            return;
+        };
+        match result {
+            kad::QueryResult::GetRecord(Ok(kad::GetRecordOk::FoundRecord(
+                found,
+            ))) => match self.mailbox_queries.remove(&id) {
+                Some(MailboxQuery::Fetch) => {
+                    for envelope in mailbox::unpack(&found.record.value, now())
+                    {
+                        self.receive_envelope(envelope);
+                    }
+                    // other holders may have a different copy
+                    if !step.last {
+                        self.mailbox_queries.insert(id, MailboxQuery::Fetch);
+                    }
+                }
+                Some(MailboxQuery::Deliver(recipient, envelope)) => {
+                    self.leave_in_mailbox(
+                        &recipient,
+                        envelope,
+                        Some(&found.record.value),
+                    );
+                    if let Some(mut query) =
+                        self.swarm.behaviour_mut().kad.query_mut(&id)
+                    {
+                        query.finish();
+                    }
+                }
+                None => {}
+            },
+            // nothing found, so the mailbox starts with our envelope
+            kad::QueryResult::GetRecord(_) => {
+                if let Some(MailboxQuery::Deliver(recipient, envelope)) =
+                    self.mailbox_queries.remove(&id)
+                {
+                    self.leave_in_mailbox(&recipient, envelope, None);
+                }
+            }
+            kad::QueryResult::PutRecord(Err(e)) => {
+                log::warn!("Failed to store a mailbox: {e}");
+            }
+            _ => {}
+        }
+    }
+
+    fn leave_in_mailbox(
+        &mut self,
+        recipient: &PeerId,
+        envelope: Envelope,
+        mailbox: Option<&[u8]>,
+    ) {
+        // another sender may store the mailbox meanwhile; holders merge the
+        // copies they get rather than keeping the last one
+        let value = mailbox::append(mailbox, envelope, now());
+        let mut record = kad::Record::new(mailbox_key(recipient), value);
+        record.expires = Some(Instant::now() + MAILBOX_TTL);
+        if let Err(e) = self
+            .swarm
+            .behaviour_mut()
+            .kad
+            .put_record(record, kad::Quorum::One)
+        {
+            log::warn!("Failed to store the mailbox of {recipient}: {e}");
+        }
+    }
+
+    fn receive_envelope(&mut self, envelope: Envelope) {
+        if self.delivered.contains(envelope.id()) {
+            return;
+        }
+        let (sender, letter) = match envelope.open(&self.keypair) {
+            Ok(opened) => opened,
+            Err(e) => {
+                log::warn!("Discarding direct message {}: {e}", envelope.id());
+                return;
+            }
+        };
+        self.delivered.insert(&envelope);
+
+        let topic = inbox_topic(&sender);
+        self.store.insert(
+            StoredMessage::builder()
+                .message_id(envelope.id().clone())
+                .peer_id(sender.to_string())
+                .maybe_nickname(letter.nickname().clone())
+                .topic(topic.clone())
+                .data(letter.text().clone())
+                .timestamp(*letter.timestamp())
+                .build(),
+        );
+        self.event_bus.emit(PeerEvent::MessageReceived(
+            MessageReceivedEvent::builder()
+                .message_id(envelope.id().clone())
+                .message(letter.text().clone())
+                .timestamp(*letter.timestamp())
+                .topic(topic)
+                .peer_id(sender.to_string())
+                .maybe_nickname(letter.nickname().clone())
+                .build(),
+        ));
+    }
+
+    fn receipt(&mut self, command: &ReceiptCommand) -> PeerResult<()> {
+        let timestamp = Utc::now().timestamp() as u64;
+        let receipt = ReadReceipt::sign(
+            &self.keypair,
+            command.topic(),
+            timestamp,
+            command.message_id().clone(),
+        )?;
+        let message = Message::builder()
+            .timestamp(timestamp)
+            .topic(command.topic().clone())
+            .maybe_nickname(self.nickname.clone())
+            .kind(MessageKind::Receipt(receipt))
+            .build();
+        self.swarm.behaviour_mut().publish(&message)?;
+        Ok(())
+    }
+
+    fn signal(&mut self, command: &SignalCommand) -> PeerResult<bool> {
+        if !self.presence.should_send(
+            command.topic(),
+            *command.kind(),
+            Instant::now(),
+        ) {
+            return Ok(false);
+        }
+
+        let signal = Signal::builder()
+            .kind(*command.kind())
+            .timestamp(Utc::now().timestamp() as u64)
+            .maybe_nickname(self.nickname.clone())
+            .build();
+        self.swarm
+            .behaviour_mut()
+            .publish_signal(command.topic(), &signal)?;
+        Ok(true)
+    }
+
+    fn handle_signal(&mut self, room: &str, author: &PeerId, signal: Signal) {
+        let peer_id = author.to_string();
+        if let Some(state) = self.presence.receive(
+            room,
+            &peer_id,
+            *signal.kind(),
+            Instant::now(),
+        ) {
+            self.emit_presence(room, peer_id, signal.nickname().clone(), state);
+        }
+    }
+
+    fn expire_presence(&mut self) {
+        for (topic, peer_id) in self.presence.expire(Instant::now()) {
+            self.emit_presence(&topic, peer_id, None, None);
+        }
+    }
+
+    fn emit_presence(
+        &self,
+        topic: &str,
+        peer_id: String,
+        nickname: Option<String>,
+        state: Option<SignalKind>,
+    ) {
+        self.event_bus.emit(PeerEvent::PresenceChanged(
+            PresenceChangedEvent::builder()
+                .topic(topic.to_owned())
+                .peer_id(peer_id)
+                .maybe_nickname(nickname)
+                .maybe_state(state)
+                .timestamp(Utc::now().timestamp() as u64)
+                .build(),
+        ));
+    }
+}
+
+fn is_loopback(address: &Multiaddr) -> bool {
+    address.iter().any(|protocol| match protocol {
+        Protocol::Ip4(ip) => ip.is_loopback(),
+        Protocol::Ip6(ip) => ip.is_loopback(),
+        _ => false,
+    })
+}
+
+fn now() -> u64 {
+    Utc::now().timestamp() as u64
+}
+
+fn stored_message(
+    message_id: &MessageId,
+    author: &PeerId,
+    message: &Message,
+    text: &TextMessage,
+) -> StoredMessage {
+    StoredMessage::builder()
+        .message_id(message_id.to_string())
+        .peer_id(author.to_string())
+        .maybe_nickname(message.nickname().clone())
+        .topic(message.topic().clone())
+        .data(text.data().clone())
+        .timestamp(*message.timestamp())
+        .maybe_parent_id(text.parent_id().clone())
+        .mentions(text.mentions().clone())
+        .build()
+}
+
+fn transfer_event(
+    file_id: &str,
+    peer_id: &PeerId,
+    direction: TransferDirection,
+    status: TransferStatus,
+) -> PeerEvent {
+    PeerEvent::FileTransfer(
+        FileTransferEvent::builder()
+            .file_id(file_id.to_owned())
+            .peer_id(peer_id.to_string())
+            .direction(direction)
+            .status(status)
+            .timestamp(Utc::now().timestamp() as u64)
+            .build(),
+    )
+}
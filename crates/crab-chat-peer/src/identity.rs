@@ -0,0 +1,47 @@
+use crate::{PeerError, PeerResult};
+use libp2p::identity::Keypair;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Loads the identity stored at `path`, generating and saving a new one the
+/// first time, so the peer id and anything addressed to it survive restarts.
+pub fn load_or_generate_keypair(path: &Path) -> PeerResult<Keypair> {
+    load_or_generate(path).map_err(PeerError::IdentityError)
+}
+
+fn load_or_generate(path: &Path) -> io::Result<Keypair> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    match fs::read(path) {
+        Ok(bytes) => Keypair::from_protobuf_encoding(&bytes).map_err(invalid),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let keypair = Keypair::generate_ed25519();
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            write_private(
+                path,
+                &keypair.to_protobuf_encoding().map_err(invalid)?,
+            )?;
+            Ok(keypair)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(bytes)
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    fs::write(path, bytes)
+}
@@ -0,0 +1,219 @@
+use crate::{PeerError, PeerResult};
+use derive_getters::Getters;
+use libp2p::identity::{Keypair, PublicKey};
+use libp2p::{Multiaddr, PeerId};
+use std::time::Duration;
+
+/// How long an invite stays valid unless asked otherwise.
+pub const DEFAULT_INVITE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Prefix of the link form of a token.
+const URL_PREFIX: &str = "crab-chat://invite/";
+
+const VERSION: u8 = 1;
+
+/// At most this many addresses of the issuer travel with an invite.
+pub const MAX_INVITE_ADDRESSES: usize = 4;
+
+/// A signed invitation to a room, shared as a base58 token or link.
+///
+/// It carries everything needed to join without any prior setup: the room,
+/// its key if the room has one, and where the issuer can be reached.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+pub struct Invite {
+    room: String,
+    key: Option<Vec<u8>>,
+    expires_at: u64,
+    addresses: Vec<Multiaddr>,
+    #[getter(skip)]
+    public_key: PublicKey,
+    #[getter(skip)]
+    signature: Vec<u8>,
+}
+
+impl Invite {
+    pub fn create(
+        keypair: &Keypair,
+        room: String,
+        key: Option<Vec<u8>>,
+        mut addresses: Vec<Multiaddr>,
+        expires_at: u64,
+    ) -> PeerResult<Self> {
+        if room.is_empty() || room.len() > u8::MAX as usize {
+            return Err(PeerError::InvalidInvite("bad room name".to_owned()));
+        }
+        if key.as_ref().is_some_and(|key| key.len() > u8::MAX as usize) {
+            return Err(PeerError::InvalidInvite(
+                "room key too long".to_owned(),
+            ));
+        }
+        addresses.retain(|address| address.len() <= u8::MAX as usize);
+        addresses.truncate(MAX_INVITE_ADDRESSES);
+        let mut invite = Self {
+            room,
+            key,
+            expires_at,
+            addresses,
+            public_key: keypair.public(),
+            signature: vec![],
+        };
+        invite.signature = keypair.sign(&invite.payload())?;
+        Ok(invite)
+    }
+
+    /// The peer that issued, and signed, the invite.
+    pub fn issuer(&self) -> PeerId {
+        self.public_key.to_peer_id()
+    }
+
+    /// The token form, to be pasted into `/accept`.
+    pub fn encode(&self) -> String {
+        let mut bytes = self.payload();
+        bytes.extend_from_slice(&self.signature);
+        bs58::encode(bytes).into_string()
+    }
+
+    pub fn to_url(&self) -> String {
+        format!("{URL_PREFIX}{}", self.encode())
+    }
+
+    /// Parses a token or link, accepting it only when its signature holds
+    /// and it did not expire by `now`.
+    pub fn decode(token: &str, now: u64) -> PeerResult<Self> {
+        let invalid =
+            |reason: &str| PeerError::InvalidInvite(reason.to_owned());
+        let token = token.trim();
+        let token = token.strip_prefix(URL_PREFIX).unwrap_or(token);
+        let bytes = bs58::decode(token)
+            .into_vec()
+            .map_err(|_| invalid("not a base58 token"))?;
+
+        let mut reader = Reader(&bytes);
+        if reader.take(1)? != [VERSION] {
+            return Err(invalid("unsupported version"));
+        }
+        let room = String::from_utf8(reader.field()?.to_vec())
+            .map_err(|_| invalid("bad room name"))?;
+        let key = match reader.field()? {
+            [] => None,
+            key => Some(key.to_vec()),
+        };
+        let expires_at =
+            u64::from_be_bytes(reader.take(8)?.try_into().unwrap());
+        let count = reader.take(1)?[0] as usize;
+        let mut addresses = vec![];
+        for _ in 0..count {
+            let address = Multiaddr::try_from(reader.field()?.to_vec())
+                .map_err(|_| invalid("bad address"))?;
+            addresses.push(address);
+        }
+        let public_key = PublicKey::try_decode_protobuf(reader.field()?)
+            .map_err(|_| invalid("bad issuer key"))?;
+        let signature = reader.0.to_vec();
+
+        let invite = Self {
+            room,
+            key,
+            expires_at,
+            addresses,
+            public_key,
+            signature,
+        };
+        if !invite
+            .public_key
+            .verify(&invite.payload(), &invite.signature)
+        {
+            return Err(invalid("bad signature"));
+        }
+        if invite.expires_at <= now {
+            return Err(PeerError::InviteExpired);
+        }
+        Ok(invite)
+    }
+
+    /// Everything but the signature, which covers exactly these bytes.
+    fn payload(&self) -> Vec<u8> {
+        let mut bytes = vec![VERSION];
+        push_field(&mut bytes, self.room.as_bytes());
+        push_field(&mut bytes, self.key.as_deref().unwrap_or_default());
+        bytes.extend_from_slice(&self.expires_at.to_be_bytes());
+        bytes.push(self.addresses.len() as u8);
+        for address in &self.addresses {
+            push_field(&mut bytes, &address.to_vec());
+        }
+        push_field(&mut bytes, &self.public_key.encode_protobuf());
+        bytes
+    }
+}
+
+/// Appends a field prefixed with its one byte length.
+fn push_field(bytes: &mut Vec<u8>, field: &[u8]) {
+    bytes.push(field.len() as u8);
+    bytes.extend_from_slice(field);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> PeerResult<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(PeerError::InvalidInvite("truncated token".to_owned()));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn field(&mut self) -> PeerResult<&'a [u8]> {
+        let len = self.take(1)?[0] as usize;
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invite(keypair: &Keypair) -> Invite {
+        Invite::create(
+            keypair,
+            "rust".to_owned(),
+            Some(vec![7; 32]),
+            vec!["/ip4/192.168.1.2/tcp/4001".parse().unwrap()],
+            100,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let keypair = Keypair::generate_ed25519();
+        let invite = invite(&keypair);
+
+        assert_eq!(Invite::decode(&invite.encode(), 99).unwrap(), invite);
+        let decoded = Invite::decode(&invite.to_url(), 99).unwrap();
+        assert_eq!(decoded.issuer(), keypair.public().to_peer_id());
+        assert_eq!(decoded.room(), "rust");
+        assert_eq!(decoded.key().as_deref(), Some(&[7; 32][..]));
+    }
+
+    #[test]
+    fn test_rejects_expired_and_forged() {
+        let keypair = Keypair::generate_ed25519();
+        let invite = invite(&keypair);
+        assert!(matches!(
+            Invite::decode(&invite.encode(), 100),
+            Err(PeerError::InviteExpired)
+        ));
+
+        let mut bytes = bs58::decode(invite.encode()).into_vec().unwrap();
+        // the room name starts right after its length byte
+        bytes[2] = b'R';
+        let forged = bs58::encode(bytes).into_string();
+        assert!(matches!(
+            Invite::decode(&forged, 0),
+            Err(PeerError::InvalidInvite(_))
+        ));
+        assert!(Invite::decode("not a token", 0).is_err());
+    }
+}
@@ -1,27 +1,113 @@
 mod bootstrap_address;
 mod command;
+mod diagnostics;
 mod error;
 mod event;
+mod event_loop;
+mod identity;
+mod invite;
+mod mailbox;
 mod message;
+mod metrics;
+mod outbox;
 mod peer;
+mod record_store;
+mod signal;
+mod sim;
+mod store;
+mod transfer;
 
 pub type PeerResult<T> = Result<T, PeerError>;
 
 pub use bootstrap_address::BootstrapAddress;
+pub use command::AcceptInviteCommand;
+pub use command::CreateInviteCommand;
+pub use command::DialCommand;
+pub use command::DisconnectCommand;
+pub use command::DirectMessageCommand;
+pub use command::DownloadFileCommand;
+pub use command::HistoryCommand;
+pub use command::ImportCommand;
+pub use command::MetricsSnapshotCommand;
+pub use command::NetworkInfoCommand;
+pub use command::OfferFileCommand;
 pub use command::PeerCommandBus;
+pub use command::ReactCommand;
+pub use command::ReceiptCommand;
 pub use command::SubscribeCommand;
 pub use command::UnsubscribeCommand;
 pub use command::SendMessageCommand;
+pub use command::SignalCommand;
+pub use command::ThreadCommand;
 pub use error::PeerError;
+pub use diagnostics::ConnectedPeer;
+pub use diagnostics::DialFailure;
+pub use diagnostics::NetworkInfo;
+pub use diagnostics::TopicMesh;
+pub use event::ConnectionEvent;
+pub use event::EventFilter;
+pub use event::EventKind;
+pub use event::FileOfferedEvent;
+pub use event::FileTransferEvent;
+pub use event::LaggedEvent;
+pub use event::MessageReceivedEvent;
+pub use event::OutboundEvent;
 pub use event::PeerEvent;
 pub use event::PeerEventListener;
+pub use event::PeerJoinedEvent;
+pub use event::PeerLeftEvent;
+pub use event::PresenceChangedEvent;
+pub use event::ReactionsChangedEvent;
+pub use event::ReadReceiptEvent;
+pub use identity::load_or_generate_keypair;
+pub use invite::DEFAULT_INVITE_TTL;
+pub use invite::Invite;
+pub use libp2p::Multiaddr;
 use libp2p::identity::Keypair;
+pub use mailbox::Delivery;
+pub use mailbox::MAILBOX_TTL;
+pub use mailbox::inbox_topic;
+pub use mailbox::is_inbox_topic;
+pub use message::FileOffer;
+pub use metrics::MetricsSnapshot;
+pub use metrics::TopicMetrics;
+pub use outbox::DEFAULT_OUTBOX_TTL;
+pub use outbox::OutboundStatus;
+pub use outbox::SendOutcome;
 pub use peer::Peer;
 pub use peer::PeerConfig;
+pub use peer::TransportKind;
+pub use sim::LinkConditions;
+pub use sim::SimNetwork;
+pub use signal::SignalKind;
+pub use store::DEFAULT_HISTORY_LIMIT;
+pub use store::HISTORY_FILE;
+pub use store::MessageStore;
+pub use store::Reactions;
+pub use store::StoredMessage;
+pub use transfer::MAX_FILE_SIZE;
+pub use transfer::TransferDirection;
+pub use transfer::TransferStatus;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 
-pub fn create_peer() -> Peer {
-    let keypair = Keypair::generate_ed25519();
+/// Starts a peer listening on every interface; with a `data_dir` its
+/// identity, DHT records and message history are kept there across
+/// restarts, and with a
+/// `metrics_addr` its metrics are served there over HTTP.
+pub fn create_peer(
+    nickname: Option<String>,
+    data_dir: Option<PathBuf>,
+    metrics_addr: Option<SocketAddr>,
+) -> PeerResult<Peer> {
+    let keypair = match &data_dir {
+        Some(dir) => load_or_generate_keypair(&dir.join("identity.key"))?,
+        None => Keypair::generate_ed25519(),
+    };
     let cfg =
-        PeerConfig::new("/ip4/0.0.0.0/tcp/0".parse().unwrap(), vec![], keypair);
-    Peer::new(cfg).unwrap()
+        PeerConfig::new("/ip4/0.0.0.0/tcp/0".parse().unwrap(), vec![], keypair)
+            .with_nickname(nickname)
+            .with_data_dir(data_dir)
+            .with_metrics_addr(metrics_addr);
+    Peer::new(cfg)
 }
@@ -0,0 +1,397 @@
+//! Store-and-forward delivery of direct messages.
+//!
+//! A direct message is sealed into an [`Envelope`] only its recipient can
+//! open. When the recipient is online the envelope travels over their inbox
+//! topic; otherwise it is appended to their mailbox, a Kademlia record keyed
+//! by their peer id which the recipient fetches once it is back.
+
+use crate::{PeerError, PeerResult};
+use bon::Builder;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use derive_getters::Getters;
+use hkdf::Hkdf;
+use libp2p::PeerId;
+use libp2p::identity::{Keypair, PublicKey};
+use libp2p::kad::RecordKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use x25519_dalek::{EphemeralSecret, PublicKey as X25519Public, StaticSecret};
+
+/// How long an undelivered message waits in a mailbox.
+pub const MAILBOX_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Mailboxes must fit in a single record, so the oldest envelopes are
+/// dropped past this size.
+const MAX_MAILBOX_BYTES: usize = 60 * 1024;
+
+/// Topic carrying direct messages to `peer_id` while it is online, also used
+/// as the room name of a conversation with it.
+pub fn inbox_topic(peer_id: &PeerId) -> String {
+    format!("@{peer_id}")
+}
+
+/// Whether `topic` is the inbox of a peer rather than a room.
+pub fn is_inbox_topic(topic: &str) -> bool {
+    topic.starts_with('@')
+}
+
+/// How a direct message left us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Published to the recipient's inbox while it is online.
+    Direct,
+    /// Left in the recipient's mailbox until it comes back.
+    Mailbox,
+}
+
+/// Prefix of the keys of mailbox records.
+const MAILBOX_PREFIX: &str = "/crab-chat/mailbox/";
+
+pub fn mailbox_key(peer_id: &PeerId) -> RecordKey {
+    RecordKey::new(&format!("{MAILBOX_PREFIX}{peer_id}"))
+}
+
+pub fn is_mailbox_key(key: &RecordKey) -> bool {
+    key.as_ref().starts_with(MAILBOX_PREFIX.as_bytes())
+}
+
+/// A direct message sealed for its recipient.
+///
+/// The id and expiry stay in clear so peers can prune and deduplicate
+/// mailboxes, but both are bound to the ciphertext.
+#[derive(Debug, Serialize, Deserialize, Clone, Getters)]
+pub struct Envelope {
+    id: String,
+    expires_at: u64,
+    #[serde(with = "hex::serde")]
+    ephemeral_key: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    nonce: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    ciphertext: Vec<u8>,
+}
+
+/// Content of an [`Envelope`], signed by its sender.
+#[derive(Debug, Serialize, Deserialize, Clone, Getters, Builder)]
+pub struct Letter {
+    #[builder(skip)]
+    #[serde(with = "hex::serde")]
+    public_key: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nickname: Option<String>,
+    timestamp: u64,
+    text: String,
+    #[builder(skip)]
+    #[serde(with = "hex::serde")]
+    signature: Vec<u8>,
+}
+
+impl Envelope {
+    /// Signs `letter` with `keypair` and encrypts it for `recipient`.
+    pub fn seal(
+        keypair: &Keypair,
+        recipient: &PeerId,
+        mut letter: Letter,
+    ) -> PeerResult<Self> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let expires_at = letter.timestamp + MAILBOX_TTL.as_secs();
+        letter.public_key = keypair.public().encode_protobuf();
+        letter.signature =
+            keypair.sign(&letter_payload(recipient, &id, &letter))?;
+
+        let recipient_key = x25519_public(recipient).ok_or_else(|| {
+            PeerError::EncryptionError(format!(
+                "{recipient} has no ed25519 key"
+            ))
+        })?;
+        let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_key = X25519Public::from(&ephemeral);
+        let shared = ephemeral.diffie_hellman(&recipient_key);
+        let cipher = cipher(shared.as_bytes(), &ephemeral_key, &recipient_key);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let plaintext = serde_json::to_vec(&letter).unwrap();
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &plaintext,
+                    aad: &associated_data(&id, expires_at),
+                },
+            )
+            .map_err(|e| PeerError::EncryptionError(e.to_string()))?;
+
+        Ok(Self {
+            id,
+            expires_at,
+            ephemeral_key: ephemeral_key.as_bytes().to_vec(),
+            nonce: nonce.to_vec(),
+            ciphertext,
+        })
+    }
+
+    /// Decrypts an envelope sent to the owner of `keypair`, answering the
+    /// letter along with its verified sender.
+    pub fn open(&self, keypair: &Keypair) -> PeerResult<(PeerId, Letter)> {
+        let failed =
+            |reason: &str| PeerError::EncryptionError(reason.to_owned());
+        let secret = x25519_secret(keypair)
+            .ok_or_else(|| failed("not an ed25519 identity"))?;
+        let ephemeral_key = <[u8; 32]>::try_from(self.ephemeral_key.as_slice())
+            .map(X25519Public::from)
+            .map_err(|_| failed("malformed ephemeral key"))?;
+        if self.nonce.len() != 12 {
+            return Err(failed("malformed nonce"));
+        }
+
+        let shared = secret.diffie_hellman(&ephemeral_key);
+        let cipher = cipher(
+            shared.as_bytes(),
+            &ephemeral_key,
+            &X25519Public::from(&secret),
+        );
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(&self.nonce),
+                Payload {
+                    msg: &self.ciphertext,
+                    aad: &associated_data(&self.id, self.expires_at),
+                },
+            )
+            .map_err(|_| failed("not addressed to us or tampered with"))?;
+        let letter: Letter = serde_json::from_slice(&plaintext)
+            .map_err(|e| PeerError::EncryptionError(e.to_string()))?;
+
+        let sender = PublicKey::try_decode_protobuf(&letter.public_key)
+            .map_err(|_| failed("malformed sender key"))?;
+        let recipient = keypair.public().to_peer_id();
+        if !sender.verify(
+            &letter_payload(&recipient, &self.id, &letter),
+            &letter.signature,
+        ) {
+            return Err(failed("bad sender signature"));
+        }
+        Ok((sender.to_peer_id(), letter))
+    }
+}
+
+fn letter_payload(recipient: &PeerId, id: &str, letter: &Letter) -> Vec<u8> {
+    format!(
+        "crab-chat/letter\n{recipient}\n{id}\n{}\n{}",
+        letter.timestamp, letter.text
+    )
+    .into_bytes()
+}
+
+fn associated_data(id: &str, expires_at: u64) -> Vec<u8> {
+    format!("{id}\n{expires_at}").into_bytes()
+}
+
+fn cipher(
+    shared: &[u8; 32],
+    ephemeral_key: &X25519Public,
+    recipient_key: &X25519Public,
+) -> ChaCha20Poly1305 {
+    let salt = [
+        ephemeral_key.as_bytes().as_slice(),
+        recipient_key.as_bytes(),
+    ]
+    .concat();
+    let mut key = Key::default();
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(b"crab-chat/mailbox", &mut key)
+        .expect("32 bytes is a valid output length");
+    ChaCha20Poly1305::new(&key)
+}
+
+/// The X25519 secret matching an ed25519 identity, derived the same way
+/// ed25519 derives its signing scalar.
+fn x25519_secret(keypair: &Keypair) -> Option<StaticSecret> {
+    let keypair = keypair.clone().try_into_ed25519().ok()?;
+    let hash = Sha512::digest(&keypair.to_bytes()[..32]);
+    let mut scalar = [0; 32];
+    scalar.copy_from_slice(&hash[..32]);
+    Some(StaticSecret::from(scalar))
+}
+
+/// The X25519 public key of a peer, recovered from its peer id which inlines
+/// ed25519 public keys.
+fn x25519_public(peer_id: &PeerId) -> Option<X25519Public> {
+    let multihash = peer_id.as_ref();
+    // identity multihash
+    if multihash.code() != 0 {
+        return None;
+    }
+    let key = PublicKey::try_decode_protobuf(multihash.digest())
+        .ok()?
+        .try_into_ed25519()
+        .ok()?;
+    let point = CompressedEdwardsY(key.to_bytes()).decompress()?;
+    Some(X25519Public::from(point.to_montgomery().to_bytes()))
+}
+
+/// Unexpired envelopes of a mailbox record.
+pub fn unpack(value: &[u8], now: u64) -> Vec<Envelope> {
+    let mut envelopes =
+        serde_json::from_slice::<Vec<Envelope>>(value).unwrap_or_default();
+    envelopes.retain(|e| e.expires_at > now);
+    envelopes
+}
+
+/// Adds `envelope` to a mailbox record, dropping expired envelopes and then
+/// the oldest ones while it does not fit.
+pub fn append(value: Option<&[u8]>, envelope: Envelope, now: u64) -> Vec<u8> {
+    let mut envelopes = value.map(|v| unpack(v, now)).unwrap_or_default();
+    envelopes.retain(|e| e.id != envelope.id);
+    envelopes.push(envelope);
+    pack(envelopes)
+}
+
+/// Merges a mailbox record stored by another peer into the copy we hold.
+///
+/// Senders update a mailbox by reading it and storing it back with their
+/// envelope, so two of them at once would each drop the other's envelope,
+/// and anyone could empty a mailbox by storing a shorter one. Holders keep
+/// every unexpired envelope of both copies instead, up to the size limit.
+pub fn merge(stored: &[u8], incoming: &[u8], now: u64) -> Vec<u8> {
+    let mut envelopes = unpack(stored, now);
+    for envelope in unpack(incoming, now) {
+        if envelopes.iter().all(|e| e.id != envelope.id) {
+            envelopes.push(envelope);
+        }
+    }
+    // oldest first, to be the first dropped
+    envelopes.sort_by_key(|e| e.expires_at);
+    pack(envelopes)
+}
+
+fn pack(mut envelopes: Vec<Envelope>) -> Vec<u8> {
+    loop {
+        let value = serde_json::to_vec(&envelopes).unwrap();
+        if value.len() <= MAX_MAILBOX_BYTES || envelopes.len() == 1 {
+            return value;
+        }
+        envelopes.remove(0);
+    }
+}
+
+/// Ids of the envelopes already delivered to us, kept until they expire so
+/// a mailbox fetched again does not deliver them twice.
+pub struct Delivered {
+    ids: HashMap<String, u64>,
+    path: Option<PathBuf>,
+}
+
+impl Delivered {
+    pub fn load(path: Option<PathBuf>, now: u64) -> Self {
+        let mut ids: HashMap<String, u64> = path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        ids.retain(|_, expires_at| *expires_at > now);
+        Self { ids, path }
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains_key(id)
+    }
+
+    pub fn insert(&mut self, envelope: &Envelope) {
+        self.ids.insert(envelope.id.clone(), envelope.expires_at);
+        if let Some(path) = &self.path {
+            let data = serde_json::to_vec(&self.ids).unwrap();
+            if let Err(e) = std::fs::write(path, data) {
+                log::warn!("Failed to save delivered messages: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn letter(text: &str) -> Letter {
+        Letter::builder()
+            .nickname("alice".to_owned())
+            .timestamp(100)
+            .text(text.to_owned())
+            .build()
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let alice = Keypair::generate_ed25519();
+        let bob = Keypair::generate_ed25519();
+        let bob_id = bob.public().to_peer_id();
+
+        let envelope = Envelope::seal(&alice, &bob_id, letter("hi")).unwrap();
+        let (sender, letter) = envelope.open(&bob).unwrap();
+        assert_eq!(sender, alice.public().to_peer_id());
+        assert_eq!(letter.text(), "hi");
+        assert_eq!(letter.nickname().as_deref(), Some("alice"));
+
+        let eve = Keypair::generate_ed25519();
+        assert!(envelope.open(&eve).is_err());
+
+        let mut tampered = envelope.clone();
+        tampered.expires_at += 1;
+        assert!(tampered.open(&bob).is_err());
+    }
+
+    #[test]
+    fn test_append_prunes_mailbox() {
+        let alice = Keypair::generate_ed25519();
+        let bob_id = Keypair::generate_ed25519().public().to_peer_id();
+        let seal =
+            |text: &str| Envelope::seal(&alice, &bob_id, letter(text)).unwrap();
+
+        let first = seal("first");
+        let value = append(None, first.clone(), 0);
+        let value = append(Some(&value), first.clone(), 0);
+        let value = append(Some(&value), seal("second"), 0);
+        assert_eq!(unpack(&value, 0).len(), 2);
+        // both expire together, past the ttl of their timestamp
+        assert!(unpack(&value, first.expires_at).is_empty());
+
+        let mut value = value;
+        let mut last = first.clone();
+        for _ in 0..10 {
+            last = seal(&"x".repeat(5000));
+            value = append(Some(&value), last.clone(), 0);
+        }
+        assert!(value.len() <= MAX_MAILBOX_BYTES);
+        let ids = unpack(&value, 0)
+            .into_iter()
+            .map(|e| e.id)
+            .collect::<Vec<_>>();
+        assert!(!ids.contains(&first.id));
+        assert_eq!(ids.last(), Some(&last.id));
+    }
+
+    #[test]
+    fn test_merge_keeps_both_copies() {
+        let alice = Keypair::generate_ed25519();
+        let bob_id = Keypair::generate_ed25519().public().to_peer_id();
+        let seal =
+            |text: &str| Envelope::seal(&alice, &bob_id, letter(text)).unwrap();
+
+        // two senders read the same mailbox and each stored their envelope
+        let stored = append(None, seal("first"), 0);
+        let mine = append(Some(&stored), seal("mine"), 0);
+        let theirs = append(Some(&stored), seal("theirs"), 0);
+        let merged = merge(&mine, &theirs, 0);
+        assert_eq!(unpack(&merged, 0).len(), 3);
+
+        // nor can a shorter or bogus copy empty it
+        assert_eq!(merge(&merged, b"[]", 0), merged);
+        assert_eq!(merge(&merged, b"garbage", 0), merged);
+        assert!(is_mailbox_key(&mailbox_key(&bob_id)));
+        assert!(!is_mailbox_key(&RecordKey::new(&"other")));
+    }
+}
@@ -1,10 +1,130 @@
 use bon::Builder;
 use derive_getters::Getters;
+use libp2p::PeerId;
+use libp2p::identity::{Keypair, PublicKey, SigningError};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Builder, Clone, Getters)]
 pub struct Message {
-    data: String,
     timestamp: u64,
     topic: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nickname: Option<String>,
+    kind: MessageKind,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum MessageKind {
+    Text(TextMessage),
+    Reaction(Reaction),
+    Receipt(ReadReceipt),
+    FileOffer(FileOffer),
+}
+
+#[derive(Debug, Serialize, Deserialize, Builder, Clone, Getters)]
+pub struct TextMessage {
+    data: String,
+    /// Id of the message this one replies to, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent_id: Option<String>,
+    /// Peer ids of the peers mentioned in `data`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[builder(default)]
+    mentions: Vec<String>,
+}
+
+/// Adds or removes the sender's `emoji` reaction on a message.
+#[derive(Debug, Serialize, Deserialize, Builder, Clone, Getters)]
+pub struct Reaction {
+    message_id: String,
+    emoji: String,
+    active: bool,
+}
+
+impl Reaction {
+    /// Shortcodes like `thumbsup` or `+1`, without the surrounding colons.
+    pub fn is_valid_shortcode(emoji: &str) -> bool {
+        (1..=32).contains(&emoji.len())
+            && emoji
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_+-".contains(c))
+    }
+}
+
+/// Announces a file the sender is ready to serve over the file transfer
+/// protocol.
+#[derive(
+    Debug, Serialize, Deserialize, Builder, Clone, Getters, PartialEq, Eq,
+)]
+pub struct FileOffer {
+    file_id: String,
+    name: String,
+    size: u64,
+    /// Hex encoded SHA-256 of the content.
+    sha256: String,
+}
+
+/// Tells the room the sender has read everything up to `message_id`.
+///
+/// Signed on top of the gossip envelope so a receipt stays verifiable on
+/// its own, e.g. once relayed or stored by another peer.
+#[derive(Debug, Serialize, Deserialize, Clone, Getters)]
+pub struct ReadReceipt {
+    message_id: String,
+    public_key: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl ReadReceipt {
+    pub fn sign(
+        keypair: &Keypair,
+        topic: &str,
+        timestamp: u64,
+        message_id: String,
+    ) -> Result<Self, SigningError> {
+        let signature =
+            keypair.sign(&receipt_payload(topic, timestamp, &message_id))?;
+        Ok(Self {
+            message_id,
+            public_key: keypair.public().encode_protobuf(),
+            signature,
+        })
+    }
+
+    /// Whether the receipt was signed by `author` for this topic and time.
+    pub fn verify(&self, author: &PeerId, topic: &str, timestamp: u64) -> bool {
+        let Ok(public_key) = PublicKey::try_decode_protobuf(&self.public_key)
+        else {
+            return false;
+        };
+        public_key.to_peer_id() == *author
+            && public_key.verify(
+                &receipt_payload(topic, timestamp, &self.message_id),
+                &self.signature,
+            )
+    }
+}
+
+fn receipt_payload(topic: &str, timestamp: u64, message_id: &str) -> Vec<u8> {
+    format!("crab-chat/receipt\n{topic}\n{timestamp}\n{message_id}")
+        .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receipt_signature() {
+        let keypair = Keypair::generate_ed25519();
+        let author = keypair.public().to_peer_id();
+        let receipt =
+            ReadReceipt::sign(&keypair, "room", 10, "abc".to_owned()).unwrap();
+
+        assert!(receipt.verify(&author, "room", 10));
+        assert!(!receipt.verify(&author, "other", 10));
+        assert!(!receipt.verify(&author, "room", 11));
+        let someone_else = Keypair::generate_ed25519().public().to_peer_id();
+        assert!(!receipt.verify(&someone_else, "room", 10));
+    }
 }
@@ -0,0 +1,346 @@
+use derive_getters::Getters;
+use libp2p::metrics::{Metrics, Recorder};
+use libp2p::swarm::SwarmEvent;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::encoding::text::encode;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::histogram::{Histogram, exponential_buckets};
+use prometheus_client::registry::Registry;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::peer::PeerBehaviourEvent;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub(crate) struct CommandLabels {
+    command: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct TopicLabels {
+    topic: String,
+}
+
+/// Round trip of commands through the event loop, by command.
+#[derive(Clone, Debug)]
+pub(crate) struct CommandLatency(
+    Family<CommandLabels, Histogram, fn() -> Histogram>,
+);
+
+impl CommandLatency {
+    fn new() -> Self {
+        Self(Family::new_with_constructor(|| {
+            // from 100µs to half a minute
+            Histogram::new(exponential_buckets(0.0001, 4.0, 10))
+        }))
+    }
+
+    pub(crate) fn observe(&self, command: &str, seconds: f64) {
+        let labels = CommandLabels {
+            command: command.to_owned(),
+        };
+        self.0.get_or_create(&labels).observe(seconds);
+    }
+}
+
+impl Default for CommandLatency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Everything the peer measures, registered under the `crab_chat` prefix.
+///
+/// The libp2p metrics cover connections, pings and kad queries, gossipsub
+/// registers its own mesh and per topic metrics, and the rest are ours.
+pub(crate) struct PeerMetrics {
+    libp2p: Metrics,
+    published: Family<TopicLabels, Counter>,
+    received: Family<TopicLabels, Counter>,
+    rejected: Family<TopicLabels, Counter>,
+    command_latency: CommandLatency,
+    registry: Arc<Registry>,
+}
+
+impl PeerMetrics {
+    /// Registers our metrics next to the ones already in `registry`.
+    pub(crate) fn new(mut registry: Registry) -> Self {
+        let libp2p = Metrics::new(&mut registry);
+        let published = Family::default();
+        let received = Family::default();
+        let rejected = Family::default();
+        let command_latency = CommandLatency::new();
+        let messages = registry.sub_registry_with_prefix("messages");
+        messages.register(
+            "published",
+            "Text messages published, by room",
+            published.clone(),
+        );
+        messages.register(
+            "received",
+            "Text messages received, by room",
+            received.clone(),
+        );
+        messages.register(
+            "rejected",
+            "Messages discarded as malformed, by room",
+            rejected.clone(),
+        );
+        registry.register(
+            "command_latency_seconds",
+            "Time for the event loop to answer a command",
+            command_latency.0.clone(),
+        );
+        Self {
+            libp2p,
+            published,
+            received,
+            rejected,
+            command_latency,
+            registry: Arc::new(registry),
+        }
+    }
+
+    pub(crate) fn registry(&self) -> Arc<Registry> {
+        self.registry.clone()
+    }
+
+    pub(crate) fn command_latency(&self) -> CommandLatency {
+        self.command_latency.clone()
+    }
+
+    pub(crate) fn record(&self, event: &SwarmEvent<PeerBehaviourEvent>) {
+        self.libp2p.record(event);
+        match event {
+            SwarmEvent::Behaviour(PeerBehaviourEvent::Gossip(event)) => {
+                self.libp2p.record(event)
+            }
+            SwarmEvent::Behaviour(PeerBehaviourEvent::Kad(event)) => {
+                self.libp2p.record(event)
+            }
+            SwarmEvent::Behaviour(PeerBehaviourEvent::Ping(event)) => {
+                self.libp2p.record(event)
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn published(&self, topic: &str) {
+        self.counter(&self.published, topic).inc();
+    }
+
+    pub(crate) fn received(&self, topic: &str) {
+        self.counter(&self.received, topic).inc();
+    }
+
+    pub(crate) fn rejected(&self, topic: &str) {
+        self.counter(&self.rejected, topic).inc();
+    }
+
+    /// Our counters of `topic`: published, received and rejected.
+    pub(crate) fn topic_counts(&self, topic: &str) -> (u64, u64, u64) {
+        (
+            self.counter(&self.published, topic).get(),
+            self.counter(&self.received, topic).get(),
+            self.counter(&self.rejected, topic).get(),
+        )
+    }
+
+    pub(crate) fn encode(&self) -> String {
+        encode_registry(&self.registry)
+    }
+
+    fn counter(
+        &self,
+        family: &Family<TopicLabels, Counter>,
+        topic: &str,
+    ) -> Counter {
+        let labels = TopicLabels {
+            topic: topic.to_owned(),
+        };
+        family.get_or_create(&labels).clone()
+    }
+}
+
+fn encode_registry(registry: &Registry) -> String {
+    let mut text = String::new();
+    // writing to a string does not fail
+    encode(&mut text, registry).unwrap();
+    text
+}
+
+/// Where the mesh stands, as answered by
+/// [`MetricsSnapshotCommand`](crate::MetricsSnapshotCommand).
+#[derive(Debug, Clone, Getters)]
+pub struct MetricsSnapshot {
+    connected_peers: usize,
+    routing_table_size: usize,
+    topics: Vec<TopicMetrics>,
+    /// Every metric in the text exposition format.
+    text: String,
+}
+
+impl MetricsSnapshot {
+    pub(crate) fn new(
+        connected_peers: usize,
+        routing_table_size: usize,
+        topics: Vec<TopicMetrics>,
+        text: String,
+    ) -> Self {
+        Self {
+            connected_peers,
+            routing_table_size,
+            topics,
+            text,
+        }
+    }
+}
+
+/// A room we are subscribed to.
+#[derive(Debug, Clone, Getters)]
+pub struct TopicMetrics {
+    topic: String,
+    mesh_peers: usize,
+    published: u64,
+    received: u64,
+    rejected: u64,
+}
+
+impl TopicMetrics {
+    pub(crate) fn new(
+        topic: String,
+        mesh_peers: usize,
+        (published, received, rejected): (u64, u64, u64),
+    ) -> Self {
+        Self {
+            topic,
+            mesh_peers,
+            published,
+            received,
+            rejected,
+        }
+    }
+}
+
+/// How long a client has to send its request headers.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest request headers we wait for.
+const MAX_REQUEST: usize = 8 * 1024;
+
+/// Answers every request on `address` with the metrics in the text
+/// exposition format, for Prometheus to scrape.
+pub(crate) async fn serve(registry: Arc<Registry>, address: SocketAddr) {
+    let listener = match TcpListener::bind(address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::warn!("Failed to serve metrics on {address}: {e}");
+            return;
+        }
+    };
+    log::info!("Serving metrics on http://{address}/metrics");
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                log::warn!("Failed to accept a metrics request: {e}");
+                continue;
+            }
+        };
+        let registry = registry.clone();
+        tokio::spawn(async move {
+            // whatever was asked for, the answer is the same, but it only
+            // goes to clients that finished asking
+            let request = tokio::time::timeout(
+                REQUEST_TIMEOUT,
+                read_request(&mut stream),
+            );
+            match request.await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    log::debug!("Dropping a metrics request: {e}");
+                    return;
+                }
+                Err(_) => {
+                    log::debug!("Dropping a metrics request: timed out");
+                    return;
+                }
+            }
+            let body = encode_registry(&registry);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\n\
+                 Content-Type: application/openmetrics-text; version=1.0.0; charset=utf-8\r\n\
+                 Content-Length: {}\r\n\
+                 Connection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+/// Reads the request line and headers, up to the blank line ending them.
+async fn read_request(stream: &mut TcpStream) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST {
+            return Err(io::Error::other("request too large"));
+        }
+        match stream.read(&mut buffer).await? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            read => request.extend_from_slice(&buffer[..read]),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serve() {
+        let metrics = PeerMetrics::new(Registry::with_prefix("crab_chat"));
+        metrics.published("rust");
+        metrics.command_latency().observe("send_message", 0.002);
+        assert_eq!(metrics.topic_counts("rust"), (1, 0, 0));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        tokio::spawn(serve(metrics.registry(), address));
+        let mut stream = loop {
+            match TcpStream::connect(address).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::task::yield_now().await,
+            }
+        };
+        // the headers may come in several packets
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\n")
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stream.write_all(b"Accept: */*\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(
+            response.contains(
+                "crab_chat_messages_published_total{topic=\"rust\"} 1"
+            )
+        );
+        assert!(response.contains(
+            "crab_chat_command_latency_seconds_count{command=\"send_message\"} 1"
+        ));
+        assert!(response.ends_with("# EOF\n"));
+    }
+}
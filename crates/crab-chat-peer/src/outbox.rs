@@ -0,0 +1,158 @@
+use crate::message::Message;
+use libp2p::gossipsub::MessageId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// How long a message waits for peers unless configured otherwise.
+pub const DEFAULT_OUTBOX_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Past this many waiting messages in a room, sending fails right away.
+const MAX_QUEUED_PER_TOPIC: usize = 100;
+
+/// What happened to a message sent with
+/// [`SendMessageCommand`](crate::SendMessageCommand).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendOutcome {
+    Sent(MessageId),
+    /// Nobody was there to receive it; it waits in the outbox under this
+    /// id and its fate is reported through outbound events.
+    Queued(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum OutboundStatus {
+    Queued,
+    /// Published once peers showed up, under its final message id.
+    Sent {
+        message_id: String,
+    },
+    /// Expired or rejected; the message is gone.
+    Failed {
+        error: String,
+    },
+}
+
+pub struct Queued {
+    pub id: String,
+    pub message: Message,
+    queued_at: Instant,
+}
+
+/// Messages published to rooms nobody was subscribed to yet, kept per topic
+/// in the order they were sent.
+pub struct Outbox {
+    ttl: Duration,
+    queues: HashMap<String, VecDeque<Queued>>,
+}
+
+impl Outbox {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            queues: HashMap::new(),
+        }
+    }
+
+    /// Queues a message, answering its outbox id or `None` when the room's
+    /// queue is full.
+    pub fn push(&mut self, message: Message, now: Instant) -> Option<String> {
+        let queue = self.queues.entry(message.topic().clone()).or_default();
+        if queue.len() >= MAX_QUEUED_PER_TOPIC {
+            return None;
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        queue.push_back(Queued {
+            id: id.clone(),
+            message,
+            queued_at: now,
+        });
+        Some(id)
+    }
+
+    /// Takes the messages waiting in `topic` to retry them; whatever is not
+    /// sent goes back with [`Outbox::restore`].
+    pub fn take(&mut self, topic: &str) -> VecDeque<Queued> {
+        self.queues.remove(topic).unwrap_or_default()
+    }
+
+    /// Whether messages of `topic` wait for peers.
+    pub fn is_waiting(&self, topic: &str) -> bool {
+        self.queues.contains_key(topic)
+    }
+
+    pub fn restore(&mut self, topic: &str, queued: VecDeque<Queued>) {
+        if !queued.is_empty() {
+            self.queues.insert(topic.to_owned(), queued);
+        }
+    }
+
+    /// Drops the messages that waited too long, answering their topics
+    /// and ids.
+    pub fn expire(&mut self, now: Instant) -> Vec<(String, String)> {
+        let mut expired = vec![];
+        for (topic, queue) in &mut self.queues {
+            while queue
+                .front()
+                .is_some_and(|q| now.duration_since(q.queued_at) >= self.ttl)
+            {
+                let queued = queue.pop_front().unwrap();
+                expired.push((topic.clone(), queued.id));
+            }
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{MessageKind, TextMessage};
+
+    fn message(topic: &str, text: &str) -> Message {
+        Message::builder()
+            .timestamp(0)
+            .topic(topic.to_owned())
+            .kind(MessageKind::Text(
+                TextMessage::builder().data(text.to_owned()).build(),
+            ))
+            .build()
+    }
+
+    #[test]
+    fn test_queue_per_topic_and_expiry() {
+        let start = Instant::now();
+        let mut outbox = Outbox::new(Duration::from_secs(10));
+        let first = outbox.push(message("a", "1"), start).unwrap();
+        let second =
+            outbox.push(message("a", "2"), start + Duration::from_secs(5));
+        outbox.push(message("b", "3"), start).unwrap();
+
+        let mut queued = outbox.take("a");
+        assert_eq!(queued.len(), 2);
+        assert!(outbox.take("a").is_empty());
+        assert!(!outbox.is_waiting("a"));
+        // the first one went out, the second waits again
+        assert_eq!(queued.pop_front().unwrap().id, first);
+        outbox.restore("a", queued);
+        assert!(outbox.is_waiting("a"));
+
+        let expired = outbox.expire(start + Duration::from_secs(10));
+        assert_eq!(expired, vec![("b".to_owned(), expired[0].1.clone())]);
+        let expired = outbox.expire(start + Duration::from_secs(15));
+        assert_eq!(expired, vec![("a".to_owned(), second.unwrap())]);
+        assert!(outbox.take("a").is_empty());
+    }
+
+    #[test]
+    fn test_full_queue() {
+        let now = Instant::now();
+        let mut outbox = Outbox::new(DEFAULT_OUTBOX_TTL);
+        for _ in 0..MAX_QUEUED_PER_TOPIC {
+            assert!(outbox.push(message("a", "x"), now).is_some());
+        }
+        assert!(outbox.push(message("a", "x"), now).is_none());
+        assert!(outbox.push(message("b", "x"), now).is_some());
+    }
+}
@@ -1,23 +1,48 @@
-use super::command::{SendMessageCommand, UnsubscribeCommand};
-use super::event::{PeerEventBus, PeerEventListener};
-use super::message::Message;
+use super::command::{
+    AcceptInviteCommand, CreateInviteCommand, DialCommand, DisconnectCommand,
+    DirectMessageCommand, DownloadFileCommand, HistoryCommand,
+    MetricsSnapshotCommand, NetworkInfoCommand, OfferFileCommand, ReactCommand,
+    ReceiptCommand, SendMessageCommand, SignalCommand, ThreadCommand,
+    UnsubscribeCommand,
+};
+use super::event::{
+    EventFilter, EventKind, MessageReceivedEvent, PeerEventBus,
+    PeerEventListener, PresenceChangedEvent,
+};
+use super::event_loop::EventLoop;
+use super::message::{FileOffer, Message};
+use super::signal::{Signal, SignalKind, signal_topic};
 use super::{
     BootstrapAddress, PeerCommandBus, PeerError, PeerResult, SubscribeCommand,
 };
-use crate::PeerEvent;
-use crate::command::PeerCommand;
-use crate::event::{MessageReceivedEvent, PeerJoinedEvent, PeerLeftEvent};
-use chrono::Utc;
-use futures::StreamExt;
+use crate::diagnostics::NetworkInfo;
+use crate::invite::Invite;
+use crate::outbox::{DEFAULT_OUTBOX_TTL, SendOutcome};
+use crate::mailbox::{Delivery, Envelope, MAILBOX_TTL, inbox_topic};
+use crate::record_store::PersistentStore;
+use crate::store::{DEFAULT_HISTORY_LIMIT, HISTORY_FILE, Reactions, StoredMessage};
+use crate::transfer;
 use libp2p::gossipsub::{IdentTopic, MessageId, PublishError, SubscriptionError};
+use crate::metrics::{self, MetricsSnapshot, PeerMetrics};
+use crate::sim::SimNetwork;
+use prometheus_client::registry::Registry;
+use libp2p::core::muxing::StreamMuxerBox;
+use libp2p::core::transport::{Boxed, MemoryTransport};
+use libp2p::core::upgrade;
 use libp2p::identity::Keypair;
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::kad;
 use libp2p::mdns::{Config as MdsnConfig, tokio::Behaviour as MdsnBehaviour};
-use libp2p::swarm::SwarmEvent;
 use libp2p::{
-    Multiaddr, PeerId, Swarm, SwarmBuilder, gossipsub, mdns, noise, tcp, yamux,
+    Multiaddr, PeerId, Swarm, SwarmBuilder, Transport, gossipsub, noise, ping,
+    tcp, yamux,
 };
 use libp2p_swarm_derive::NetworkBehaviour;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
+use futures::future::ready;
+use futures::{Stream, StreamExt};
 use tokio::sync::mpsc;
 
 #[derive(Debug)]
@@ -25,6 +50,7 @@ pub struct Peer {
     event_bus: PeerEventBus,
     command_bus: PeerCommandBus,
     peer_id: PeerId,
+    nickname: Option<String>,
 }
 
 impl Peer {
@@ -32,6 +58,10 @@ impl Peer {
         &self.peer_id
     }
 
+    pub fn nickname(&self) -> Option<&str> {
+        self.nickname.as_deref()
+    }
+
     pub fn command_bus(&self) -> &PeerCommandBus {
         &self.command_bus
     }
@@ -41,40 +71,111 @@ impl Peer {
         log::info!("Starting peer: {}", peer_id);
         let (command_bus_tx, command_bus_rx) = mpsc::unbounded_channel();
 
-        let mut swarm: Swarm<PeerBehaviour> =
+        let mut registry = Registry::with_prefix("crab_chat");
+        let behaviour = |k: &Keypair| {
+            PeerBehaviour::new(
+                k,
+                config.bootstrap,
+                config.data_dir.as_deref(),
+                config.mdns,
+                &mut registry,
+            )
+        };
+        let builder =
             SwarmBuilder::with_existing_identity(config.keypair.clone())
-                .with_tokio()
+                .with_tokio();
+        let mut swarm: Swarm<PeerBehaviour> = match &config.transport {
+            TransportKind::Tcp => builder
                 .with_tcp(
                     tcp::Config::default(),
                     noise::Config::new,
                     yamux::Config::default,
                 )
                 .map_err(|e| PeerError::SwarmError(e.into()))?
-                .with_behaviour(|k| PeerBehaviour::new(k, config.bootstrap))
+                .with_behaviour(behaviour)
                 .map_err(|e| PeerError::SwarmError(e.into()))?
-                .with_swarm_config(|cfg| {
-                    cfg.with_idle_connection_timeout(Duration::from_secs(
-                        u64::MAX,
-                    ))
+                .with_swarm_config(swarm_config)
+                .build(),
+            TransportKind::Memory | TransportKind::Simulated(_) => builder
+                .with_other_transport(|k| {
+                    memory_transport(k, config.transport.simulation())
                 })
-                .build();
+                .map_err(|e| PeerError::SwarmError(e.into()))?
+                .with_behaviour(behaviour)
+                .map_err(|e| PeerError::SwarmError(e.into()))?
+                .with_swarm_config(swarm_config)
+                .build(),
+        };
 
+        if let TransportKind::Simulated(network) = &config.transport {
+            network.register(config.addr.clone(), peer_id);
+        }
         swarm.listen_on(config.addr.clone())?;
 
+        let metrics = PeerMetrics::new(registry);
+        if let Some(address) = config.metrics_addr {
+            tokio::spawn(metrics::serve(metrics.registry(), address));
+        }
+        let command_bus = PeerCommandBus::new(command_bus_tx)
+            .with_latency(metrics.command_latency());
+
         let event_bus = PeerEventBus::new();
-        tokio::spawn(swarm_loop(
-            peer_id,
-            swarm,
-            command_bus_rx,
-            event_bus.clone(),
-        ));
+        let event_loop = EventLoop::builder()
+            .swarm(swarm)
+            .keypair(config.keypair)
+            .maybe_nickname(config.nickname.clone())
+            .maybe_delivered_path(
+                config.data_dir.as_ref().map(|dir| dir.join("mailbox.json")),
+            )
+            .maybe_history_path(
+                config
+                    .data_dir
+                    .filter(|_| config.keep_history)
+                    .map(|dir| dir.join(HISTORY_FILE)),
+            )
+            .history_limit(config.history_limit)
+            .outbox_ttl(config.outbox_ttl)
+            .command_bus_rx(command_bus_rx)
+            .event_bus(event_bus.clone())
+            .metrics(metrics)
+            .build();
+        tokio::spawn(event_loop.run());
         Ok(Self {
             event_bus,
-            command_bus: PeerCommandBus::new(command_bus_tx),
+            command_bus,
             peer_id,
+            nickname: config.nickname,
         })
     }
 
+    /// Our addresses, connections and mesh.
+    pub async fn network_info(&self) -> PeerResult<NetworkInfo> {
+        self.command_bus
+            .send(NetworkInfoCommand::builder().build())
+            .await
+    }
+
+    /// Closes every connection to `peer_id`.
+    pub async fn disconnect(&self, peer_id: String) -> PeerResult<()> {
+        self.command_bus
+            .send(DisconnectCommand::builder().peer_id(peer_id).build())
+            .await
+    }
+
+    /// Current state of the mesh along with every metric.
+    pub async fn metrics_snapshot(&self) -> PeerResult<MetricsSnapshot> {
+        self.command_bus
+            .send(MetricsSnapshotCommand::builder().build())
+            .await
+    }
+
+    /// Connects to a peer listening on `address`.
+    pub async fn dial(&self, address: Multiaddr) -> PeerResult<()> {
+        self.command_bus
+            .send(DialCommand::builder().address(address).build())
+            .await
+    }
+
     pub async fn subscribe_topic(&self, topic: String) -> PeerResult<bool> {
         self.command_bus
             .send(SubscribeCommand::builder().topic(topic).build())
@@ -91,7 +192,7 @@ impl Peer {
         &self,
         message: String,
         topic: String,
-    ) -> PeerResult<MessageId> {
+    ) -> PeerResult<SendOutcome> {
         self.command_bus
             .send(
                 SendMessageCommand::builder()
@@ -102,80 +203,216 @@ impl Peer {
             .await
     }
 
+    pub async fn reply(
+        &self,
+        message: String,
+        topic: String,
+        parent_id: String,
+    ) -> PeerResult<SendOutcome> {
+        self.command_bus
+            .send(
+                SendMessageCommand::builder()
+                    .message(message)
+                    .topic(topic)
+                    .parent_id(parent_id)
+                    .build(),
+            )
+            .await
+    }
+
+    pub async fn history(
+        &self,
+        topic: String,
+        limit: Option<usize>,
+    ) -> PeerResult<Vec<StoredMessage>> {
+        self.command_bus
+            .send(
+                HistoryCommand::builder()
+                    .topic(topic)
+                    .maybe_limit(limit)
+                    .build(),
+            )
+            .await
+    }
+
+    pub async fn thread(
+        &self,
+        message_id: String,
+    ) -> PeerResult<Vec<StoredMessage>> {
+        self.command_bus
+            .send(ThreadCommand::builder().message_id(message_id).build())
+            .await
+    }
+
+    pub async fn react(
+        &self,
+        topic: String,
+        message_id: String,
+        emoji: String,
+    ) -> PeerResult<Reactions> {
+        self.command_bus
+            .send(
+                ReactCommand::builder()
+                    .topic(topic)
+                    .message_id(message_id)
+                    .emoji(emoji)
+                    .build(),
+            )
+            .await
+    }
+
+    /// Announces an ephemeral signal in a room, answering `false` when it
+    /// was dropped by the rate limiter.
+    pub async fn signal(
+        &self,
+        topic: String,
+        kind: SignalKind,
+    ) -> PeerResult<bool> {
+        self.command_bus
+            .send(SignalCommand::builder().topic(topic).kind(kind).build())
+            .await
+    }
+
+    /// Tells the room we read everything up to `message_id`.
+    pub async fn read_receipt(
+        &self,
+        topic: String,
+        message_id: String,
+    ) -> PeerResult<()> {
+        self.command_bus
+            .send(
+                ReceiptCommand::builder()
+                    .topic(topic)
+                    .message_id(message_id)
+                    .build(),
+            )
+            .await
+    }
+
+    /// Offers a local file to a room.
+    pub async fn offer_file(
+        &self,
+        topic: String,
+        path: PathBuf,
+    ) -> PeerResult<FileOffer> {
+        self.command_bus
+            .send(OfferFileCommand::builder().topic(topic).path(path).build())
+            .await
+    }
+
+    /// Starts downloading a file offered by `peer_id` into `directory`.
+    pub async fn download_file(
+        &self,
+        peer_id: String,
+        offer: FileOffer,
+        directory: PathBuf,
+    ) -> PeerResult<()> {
+        self.command_bus
+            .send(
+                DownloadFileCommand::builder()
+                    .peer_id(peer_id)
+                    .offer(offer)
+                    .directory(directory)
+                    .build(),
+            )
+            .await
+    }
+
+    /// Sends an end-to-end encrypted message to `peer_id`, left in its
+    /// mailbox when it is not online.
+    pub async fn direct_message(
+        &self,
+        peer_id: String,
+        message: String,
+    ) -> PeerResult<(String, Delivery)> {
+        self.command_bus
+            .send(
+                DirectMessageCommand::builder()
+                    .peer_id(peer_id)
+                    .message(message)
+                    .build(),
+            )
+            .await
+    }
+
+    /// Issues an invite to `topic` valid for `ttl`, handing out `key` to
+    /// whoever redeems it.
+    pub async fn create_invite(
+        &self,
+        topic: String,
+        key: Option<Vec<u8>>,
+        ttl: Duration,
+    ) -> PeerResult<Invite> {
+        let command = CreateInviteCommand::builder()
+            .topic(topic)
+            .maybe_key(key)
+            .ttl(ttl)
+            .build();
+        self.command_bus.send(command).await
+    }
+
+    /// Redeems an invite token or link, joining the room it names. The
+    /// invite answered carries the room key, if it was issued with one.
+    pub async fn accept_invite(&self, token: String) -> PeerResult<Invite> {
+        self.command_bus
+            .send(AcceptInviteCommand::builder().token(token).build())
+            .await
+    }
+
     pub fn subscribe(&self) -> PeerEventListener {
         self.event_bus.subscribe()
     }
-}
 
-async fn swarm_loop(
-    local_peer_id: PeerId,
-    mut swarm: Swarm<PeerBehaviour>,
-    mut command_bus_rx: mpsc::UnboundedReceiver<PeerCommand>,
-    event_bus: PeerEventBus,
-) -> PeerResult<()> {
-    loop {
-        tokio::select! {
-            event =  swarm.select_next_some() => {
-                match event {
-                    SwarmEvent::Behaviour(PeerBehaviourEvent::Mdns(mdns::Event::Discovered(items))) => {
-                        for (peer, addr ) in items {
-                            swarm.behaviour_mut().kad.add_address(&peer, addr);
-                        }
-                    },
-                    SwarmEvent::Behaviour(PeerBehaviourEvent::Gossip(gossipsub::Event::Message { propagation_source, message_id, message })) => {
-                        let mesage = serde_json::from_slice::<Message>(&message.data).unwrap();
-                        let event = MessageReceivedEvent::builder()
-                            .message_id(message_id.to_string())
-                            .message(mesage.data().to_string())
-                            .timestamp(*mesage.timestamp())
-                            .topic(mesage.topic().to_string())
-                            .peer_id(propagation_source.to_string())
-                            .build();
-                        event_bus.emit(PeerEvent::MessageReceived(event));
-                    },
-
-                    SwarmEvent::Behaviour(PeerBehaviourEvent::Gossip(gossipsub::Event::Subscribed { peer_id, topic })) => {
-                            event_bus.emit(PeerEvent::PeerJoined(PeerJoinedEvent::builder()
-                                .peer_id(peer_id.to_string())
-                                .topic(topic.to_string())
-                                .timestamp(Utc::now().timestamp() as u64)
-                                .build()));
-
-                    },
-
-                    SwarmEvent::Behaviour(PeerBehaviourEvent::Gossip(gossipsub::Event::Unsubscribed { peer_id, topic })) => {
-                            event_bus.emit(PeerEvent::PeerLeft(PeerLeftEvent::builder()
-                                .peer_id(peer_id.to_string())
-                                .topic(topic.to_string())
-                                .timestamp(Utc::now().timestamp() as u64)
-                                .build()));
-
-                    },
-                    _ => {},
-                }
-            }
-            cmd = command_bus_rx.recv() => {
-                if let Some(cmd) = cmd {
-                    match cmd {
-                        PeerCommand::SendMessage(command)=>{
-                            let r = swarm.behaviour_mut().publish_message(command.as_ref());
-                            command.send(r.map_err(PeerError::from));
-
-                        },
-                        PeerCommand::Subscribe(cmd) => {
-                            log::info!("Subscribing to topic: {}", cmd.as_ref().topic());
-                            let response = swarm.behaviour_mut().subscribe(cmd.as_ref());
-                            cmd.send(response.map_err(PeerError::from));
-
-                        },
-                        PeerCommand::Unsubscribe(cmd) => {
-                            let response = swarm.behaviour_mut().unsubscribe(cmd.as_ref());
-                            cmd.send(Ok(response));
-                        },
-                    }
-                }
-            }
-        }
+    /// Listens to the events passing `filter`; with `replay`, the recent
+    /// events still kept come first.
+    pub fn subscribe_with(
+        &self,
+        filter: EventFilter,
+        replay: bool,
+    ) -> PeerEventListener {
+        self.event_bus.subscribe_with(filter, replay)
+    }
+
+    /// Messages the other peers send to `topic` from now on.
+    pub fn messages(
+        &self,
+        topic: String,
+    ) -> impl Stream<Item = MessageReceivedEvent> + Send + Unpin {
+        self.subscribe_kind(EventKind::MessageReceived, topic)
+            .messages()
+    }
+
+    /// Presence changes of the peers in `topic` from now on.
+    pub fn presence(
+        &self,
+        topic: String,
+    ) -> impl Stream<Item = PresenceChangedEvent> + Send + Unpin {
+        self.subscribe_kind(EventKind::PresenceChanged, topic)
+            .presence()
+    }
+
+    /// Replies to `message_id` in `topic`, for as long as a caller waits
+    /// for answers to a message it sent.
+    pub fn replies(
+        &self,
+        topic: String,
+        message_id: String,
+    ) -> impl Stream<Item = MessageReceivedEvent> + Send + Unpin {
+        self.messages(topic).filter(move |event| {
+            ready(event.parent_id().as_ref() == Some(&message_id))
+        })
+    }
+
+    fn subscribe_kind(
+        &self,
+        kind: EventKind,
+        topic: String,
+    ) -> PeerEventListener {
+        let filter = EventFilter::builder()
+            .kinds(vec![kind])
+            .topics(vec![topic])
+            .build();
+        self.event_bus.subscribe_with(filter, false)
     }
 }
 
@@ -183,6 +420,80 @@ pub struct PeerConfig {
     pub addr: Multiaddr,
     pub bootstrap: Vec<BootstrapAddress>,
     pub keypair: Keypair,
+    /// Display name sent along with our messages and signals.
+    pub nickname: Option<String>,
+    /// Where DHT records and delivered mailbox messages are kept across
+    /// restarts; everything stays in memory without one.
+    pub data_dir: Option<PathBuf>,
+    /// How long messages sent to an empty room wait for peers.
+    pub outbox_ttl: Duration,
+    pub transport: TransportKind,
+    /// Whether peers on the LAN are discovered over mDNS.
+    pub mdns: bool,
+    /// Where metrics are served over HTTP, if anywhere.
+    pub metrics_addr: Option<SocketAddr>,
+    /// Whether the messages seen are written to the data directory, direct
+    /// ones included, for the history to survive restarts.
+    pub keep_history: bool,
+    /// How many messages of each room the history keeps.
+    pub history_limit: usize,
+}
+
+/// How the peer reaches the others.
+#[derive(Debug, Clone, Default)]
+pub enum TransportKind {
+    #[default]
+    Tcp,
+    /// Only reaches peers of the same process, on `/memory/<port>`
+    /// addresses; meant for tests.
+    Memory,
+    /// Like [`TransportKind::Memory`], through links whose latency, loss
+    /// and partitions the network controls.
+    Simulated(SimNetwork),
+}
+
+impl TransportKind {
+    fn simulation(&self) -> Option<SimNetwork> {
+        match self {
+            TransportKind::Simulated(network) => Some(network.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Memory connections, simulated when a network is given, secured and
+/// multiplexed like TCP ones.
+fn memory_transport(
+    keypair: &Keypair,
+    simulation: Option<SimNetwork>,
+) -> Result<
+    Boxed<(PeerId, StreamMuxerBox)>,
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let local_peer_id = keypair.public().to_peer_id();
+    let noise = noise::Config::new(keypair)?;
+    let transport = match simulation {
+        None => MemoryTransport::default()
+            .upgrade(upgrade::Version::V1)
+            .authenticate(noise)
+            .multiplex(yamux::Config::default())
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+            .boxed(),
+        Some(network) => MemoryTransport::default()
+            .and_then(move |stream, endpoint| {
+                network.clone().connect(stream, endpoint, local_peer_id)
+            })
+            .upgrade(upgrade::Version::V1)
+            .authenticate(noise)
+            .multiplex(yamux::Config::default())
+            .map(|(peer_id, muxer), _| (peer_id, StreamMuxerBox::new(muxer)))
+            .boxed(),
+    };
+    Ok(transport)
+}
+
+fn swarm_config(config: libp2p::swarm::Config) -> libp2p::swarm::Config {
+    config.with_idle_connection_timeout(Duration::from_secs(u64::MAX))
 }
 
 impl PeerConfig {
@@ -195,65 +506,171 @@ impl PeerConfig {
             addr,
             bootstrap,
             keypair,
+            nickname: None,
+            data_dir: None,
+            outbox_ttl: DEFAULT_OUTBOX_TTL,
+            transport: TransportKind::default(),
+            mdns: true,
+            metrics_addr: None,
+            keep_history: true,
+            history_limit: DEFAULT_HISTORY_LIMIT,
         }
     }
+
+    pub fn with_metrics_addr(
+        mut self,
+        metrics_addr: Option<SocketAddr>,
+    ) -> Self {
+        self.metrics_addr = metrics_addr;
+        self
+    }
+
+    pub fn with_transport(mut self, transport: TransportKind) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    pub fn with_mdns(mut self, mdns: bool) -> Self {
+        self.mdns = mdns;
+        self
+    }
+
+    pub fn with_nickname(mut self, nickname: Option<String>) -> Self {
+        self.nickname = nickname;
+        self
+    }
+
+    pub fn with_outbox_ttl(mut self, outbox_ttl: Duration) -> Self {
+        self.outbox_ttl = outbox_ttl;
+        self
+    }
+
+    pub fn with_data_dir(mut self, data_dir: Option<PathBuf>) -> Self {
+        self.data_dir = data_dir;
+        self
+    }
+
+    pub fn with_keep_history(mut self, keep_history: bool) -> Self {
+        self.keep_history = keep_history;
+        self
+    }
+
+    pub fn with_history_limit(mut self, history_limit: usize) -> Self {
+        self.history_limit = history_limit;
+        self
+    }
 }
 
 #[derive(NetworkBehaviour)]
 pub struct PeerBehaviour {
     pub gossip: gossipsub::Behaviour,
-    pub mdns: MdsnBehaviour,
-    pub kad: libp2p::kad::Behaviour<libp2p::kad::store::MemoryStore>,
+    pub mdns: Toggle<MdsnBehaviour>,
+    pub kad: kad::Behaviour<PersistentStore>,
+    pub ping: ping::Behaviour,
+    pub transfer: transfer::Behaviour,
 }
 
 impl PeerBehaviour {
-    pub fn new(keypair: &Keypair, bootstrap: Vec<BootstrapAddress>) -> Self {
+    pub fn new(
+        keypair: &Keypair,
+        bootstrap: Vec<BootstrapAddress>,
+        data_dir: Option<&Path>,
+        mdns: bool,
+        registry: &mut Registry,
+    ) -> Self {
         let local_peer_id = keypair.public().to_peer_id();
         let gossip_config = gossipsub::Config::default();
-        let gossip = gossipsub::Behaviour::new(
+        let mut gossip = gossipsub::Behaviour::new_with_metrics(
             gossipsub::MessageAuthenticity::Signed(keypair.clone()),
             gossip_config,
+            registry.sub_registry_with_prefix("gossipsub"),
+            gossipsub::MetricsConfig::default(),
         )
         .unwrap();
-        let mdns =
-            MdsnBehaviour::new(MdsnConfig::default(), local_peer_id).unwrap();
+        gossip
+            .subscribe(&IdentTopic::new(inbox_topic(&local_peer_id)))
+            .unwrap();
+        let mdns = mdns.then(|| {
+            MdsnBehaviour::new(MdsnConfig::default(), local_peer_id).unwrap()
+        });
 
-        let mut kad = libp2p::kad::Behaviour::new(
-            local_peer_id,
-            libp2p::kad::store::MemoryStore::new(local_peer_id),
-        );
+        let store = match data_dir {
+            Some(dir) => {
+                PersistentStore::open(local_peer_id, dir.join("records"))
+                    .unwrap_or_else(|e| {
+                        log::warn!("Keeping DHT records in memory only: {e}");
+                        PersistentStore::new(local_peer_id)
+                    })
+            }
+            None => PersistentStore::new(local_peer_id),
+        };
+        let mut kad_config = kad::Config::new(kad::PROTOCOL_NAME);
+        // peers holding a mailbox keep replicating it until it expires
+        kad_config.set_record_ttl(Some(MAILBOX_TTL));
+        let mut kad =
+            kad::Behaviour::with_config(local_peer_id, store, kad_config);
+        // without a confirmed external address kad would only act as a
+        // client, and nobody on the LAN would hold mailboxes
+        kad.set_mode(Some(kad::Mode::Server));
 
         bootstrap.iter().for_each(|b| {
             kad.add_address(&b.peer_id, b.addr.clone());
         });
 
-        Self { gossip, mdns, kad }
+        Self {
+            gossip,
+            mdns: mdns.into(),
+            kad,
+            ping: ping::Behaviour::default(),
+            transfer: transfer::Behaviour::new(),
+        }
     }
 
+    /// Joins a room along with its side channel for ephemeral signals.
     pub fn subscribe(
         &mut self,
         commad: &SubscribeCommand,
     ) -> Result<bool, SubscriptionError> {
         let topic = IdentTopic::new(commad.topic());
+        let signals = IdentTopic::new(signal_topic(commad.topic()));
+        self.gossip.subscribe(&signals)?;
         self.gossip.subscribe(&topic)
     }
 
     pub fn unsubscribe(&mut self, commad: &UnsubscribeCommand) -> bool {
         let topic = IdentTopic::new(commad.topic());
+        let signals = IdentTopic::new(signal_topic(commad.topic()));
+        self.gossip.unsubscribe(&signals);
         self.gossip.unsubscribe(&topic)
     }
 
-    pub fn publish_message(
+    pub fn publish(
         &mut self,
-        command: &SendMessageCommand,
+        message: &Message,
     ) -> Result<MessageId, PublishError> {
-        let message = Message::builder()
-            .data(command.message().clone())
-            .timestamp(Utc::now().timestamp() as u64)
-            .topic(command.topic().clone())
-            .build();
-        let data = serde_json::to_vec(&message).unwrap();
+        let data = serde_json::to_vec(message).unwrap();
+        self.gossip.publish(IdentTopic::new(message.topic()), data)
+    }
 
-        self.gossip.publish(IdentTopic::new(command.topic()), data)
+    /// Publishes to the inbox of `recipient`, failing with
+    /// [`PublishError::InsufficientPeers`] when it is not reachable.
+    pub fn publish_envelope(
+        &mut self,
+        recipient: &PeerId,
+        envelope: &Envelope,
+    ) -> Result<MessageId, PublishError> {
+        let data = serde_json::to_vec(envelope).unwrap();
+        self.gossip
+            .publish(IdentTopic::new(inbox_topic(recipient)), data)
+    }
+
+    pub fn publish_signal(
+        &mut self,
+        topic: &str,
+        signal: &Signal,
+    ) -> Result<MessageId, PublishError> {
+        let data = serde_json::to_vec(signal).unwrap();
+        self.gossip
+            .publish(IdentTopic::new(signal_topic(topic)), data)
     }
 }
@@ -0,0 +1,239 @@
+use crate::mailbox;
+use libp2p::PeerId;
+use libp2p::kad::store::{MemoryStore, RecordStore, Result};
+use libp2p::kad::{ProviderRecord, Record, RecordKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Kademlia record store keeping every record on disk as well, one file per
+/// key, so records held for other peers survive a restart.
+///
+/// Provider records are short lived and republished by their providers, so
+/// they stay in memory only.
+pub struct PersistentStore {
+    records: MemoryStore,
+    directory: Option<PathBuf>,
+}
+
+/// On disk form of a [`Record`]; the expiry becomes a unix timestamp since
+/// an [`Instant`] means nothing after a restart.
+#[derive(Serialize, Deserialize)]
+struct StoredRecord {
+    key: String,
+    value: String,
+    publisher: Option<String>,
+    expires_at: Option<u64>,
+}
+
+impl PersistentStore {
+    /// A store that lives in memory only.
+    pub fn new(local_peer_id: PeerId) -> Self {
+        Self {
+            records: MemoryStore::new(local_peer_id),
+            directory: None,
+        }
+    }
+
+    /// A store backed by `directory`, loaded with the unexpired records
+    /// found there.
+    pub fn open(local_peer_id: PeerId, directory: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&directory)?;
+        let mut records = MemoryStore::new(local_peer_id);
+        for entry in fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+            match load(&path) {
+                Ok(Some(record)) => {
+                    if let Err(e) = records.put(record) {
+                        log::warn!("Dropping stored record {path:?}: {e}");
+                    }
+                }
+                Ok(None) => {
+                    let _ = fs::remove_file(&path);
+                }
+                Err(e) => {
+                    log::warn!("Skipping unreadable record {path:?}: {e}")
+                }
+            }
+        }
+        Ok(Self {
+            records,
+            directory: Some(directory),
+        })
+    }
+
+    fn path(&self, key: &RecordKey) -> Option<PathBuf> {
+        let name = hex::encode(Sha256::digest(key.as_ref()));
+        self.directory
+            .as_ref()
+            .map(|directory| directory.join(format!("{name}.json")))
+    }
+
+    fn save(&self, record: &Record) -> io::Result<()> {
+        let Some(path) = self.path(&record.key) else {
+            return Ok(());
+        };
+        let stored = StoredRecord {
+            key: hex::encode(record.key.as_ref()),
+            value: hex::encode(&record.value),
+            publisher: record.publisher.map(|p| p.to_string()),
+            expires_at: record.expires.map(|expires| {
+                unix_time(
+                    SystemTime::now()
+                        + expires.saturating_duration_since(Instant::now()),
+                )
+            }),
+        };
+        // written aside first so a crash never leaves half a record behind
+        let partial = path.with_extension("part");
+        fs::write(&partial, serde_json::to_vec(&stored)?)?;
+        fs::rename(partial, path)
+    }
+}
+
+/// Reads a record file, answering `None` once the record expired.
+fn load(path: &Path) -> io::Result<Option<Record>> {
+    let stored: StoredRecord = serde_json::from_slice(&fs::read(path)?)?;
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    let now = unix_time(SystemTime::now());
+    let expires = match stored.expires_at {
+        Some(expires_at) if expires_at <= now => return Ok(None),
+        Some(expires_at) => {
+            Some(Instant::now() + Duration::from_secs(expires_at - now))
+        }
+        None => None,
+    };
+    Ok(Some(Record {
+        key: RecordKey::from(hex::decode(stored.key).map_err(invalid)?),
+        value: hex::decode(stored.value).map_err(invalid)?,
+        publisher: stored.publisher.and_then(|p| p.parse().ok()),
+        expires,
+    }))
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl RecordStore for PersistentStore {
+    type RecordsIter<'a> = <MemoryStore as RecordStore>::RecordsIter<'a>;
+    type ProvidedIter<'a> = <MemoryStore as RecordStore>::ProvidedIter<'a>;
+
+    fn get(&self, k: &RecordKey) -> Option<Cow<'_, Record>> {
+        self.records.get(k)
+    }
+
+    fn put(&mut self, mut r: Record) -> Result<()> {
+        if mailbox::is_mailbox_key(&r.key)
+            && let Some(stored) = self.records.get(&r.key)
+        {
+            let now = unix_time(SystemTime::now());
+            r.value = mailbox::merge(&stored.value, &r.value, now);
+        }
+        // only what the memory store accepted goes to disk, so its limits
+        // hold across restarts
+        self.records.put(r.clone())?;
+        // a failing disk should not cost us the record while we run
+        if let Err(e) = self.save(&r) {
+            log::warn!("Failed to persist a record: {e}");
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, k: &RecordKey) {
+        if let Some(path) = self.path(k) {
+            let _ = fs::remove_file(path);
+        }
+        self.records.remove(k)
+    }
+
+    fn records(&self) -> Self::RecordsIter<'_> {
+        self.records.records()
+    }
+
+    fn add_provider(&mut self, record: ProviderRecord) -> Result<()> {
+        self.records.add_provider(record)
+    }
+
+    fn providers(&self, key: &RecordKey) -> Vec<ProviderRecord> {
+        self.records.providers(key)
+    }
+
+    fn provided(&self) -> Self::ProvidedIter<'_> {
+        self.records.provided()
+    }
+
+    fn remove_provider(&mut self, k: &RecordKey, p: &PeerId) {
+        self.records.remove_provider(k, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_records_survive_reopen() {
+        let directory = temp_dir();
+        let peer_id = PeerId::random();
+        let key = RecordKey::new(&"mailbox");
+        let expired = RecordKey::new(&"expired");
+
+        let mut store =
+            PersistentStore::open(peer_id, directory.clone()).unwrap();
+        let mut record = Record::new(key.clone(), b"hello".to_vec());
+        record.expires = Some(Instant::now() + Duration::from_secs(60));
+        store.put(record).unwrap();
+        let mut record = Record::new(expired.clone(), b"old".to_vec());
+        record.expires = Some(Instant::now());
+        store.put(record).unwrap();
+        drop(store);
+
+        let mut store =
+            PersistentStore::open(peer_id, directory.clone()).unwrap();
+        let record = store.get(&key).unwrap();
+        assert_eq!(record.value, b"hello");
+        assert!(record.expires.is_some());
+        assert!(store.get(&expired).is_none());
+
+        store.remove(&key);
+        drop(store);
+        let store = PersistentStore::open(peer_id, directory.clone()).unwrap();
+        assert!(store.get(&key).is_none());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_rejected_records_are_not_saved() {
+        let directory = temp_dir();
+        let peer_id = PeerId::random();
+        let key = RecordKey::new(&"huge");
+
+        let mut store =
+            PersistentStore::open(peer_id, directory.clone()).unwrap();
+        let record = Record::new(key.clone(), vec![0; 1024 * 1024]);
+        assert!(store.put(record).is_err());
+        assert!(store.get(&key).is_none());
+        drop(store);
+
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 0);
+        let store = PersistentStore::open(peer_id, directory.clone()).unwrap();
+        assert!(store.get(&key).is_none());
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+use bon::Builder;
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const SIGNAL_TOPIC_SUFFIX: &str = "#signals";
+
+/// Minimum time between two signals of the same kind we send to a room.
+const SEND_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Minimum time between two signals accepted from the same peer in a room.
+const RECEIVE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Ephemeral state a peer announces in a room. Never stored in history.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignalKind {
+    Typing,
+    StoppedTyping,
+    Idle,
+    Away,
+    Active,
+}
+
+impl SignalKind {
+    /// How long the state lasts without being refreshed, `None` for kinds
+    /// that clear the current state.
+    fn ttl(&self) -> Option<Duration> {
+        match self {
+            SignalKind::Typing => Some(Duration::from_secs(6)),
+            SignalKind::Idle | SignalKind::Away => {
+                Some(Duration::from_secs(10 * 60))
+            }
+            SignalKind::StoppedTyping | SignalKind::Active => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Builder, Clone, Getters)]
+pub struct Signal {
+    kind: SignalKind,
+    timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nickname: Option<String>,
+}
+
+/// Side channel topic carrying the signals of a room.
+pub fn signal_topic(topic: &str) -> String {
+    format!("{topic}{SIGNAL_TOPIC_SUFFIX}")
+}
+
+/// The room a side channel topic belongs to, if it is one.
+pub fn room_of_signal_topic(topic: &str) -> Option<&str> {
+    topic.strip_suffix(SIGNAL_TOPIC_SUFFIX)
+}
+
+/// Tracks the signals of remote peers per room, expiring them once their
+/// time to live elapses, and rate limits both directions.
+#[derive(Debug, Default)]
+pub struct Presence {
+    /// When the current state of each `(topic, peer)` expires.
+    expires_at: HashMap<(String, String), Instant>,
+    last_sent: HashMap<(String, SignalKind), Instant>,
+    last_received: HashMap<(String, String), Instant>,
+}
+
+impl Presence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a signal may be sent now, recording it if so.
+    pub fn should_send(
+        &mut self,
+        topic: &str,
+        kind: SignalKind,
+        now: Instant,
+    ) -> bool {
+        let key = (topic.to_owned(), kind);
+        if self
+            .last_sent
+            .get(&key)
+            .is_some_and(|last| now.duration_since(*last) < SEND_INTERVAL)
+        {
+            return false;
+        }
+        self.last_sent.insert(key, now);
+        true
+    }
+
+    /// Applies a received signal, returning the peer's new state when the
+    /// signal was accepted.
+    pub fn receive(
+        &mut self,
+        topic: &str,
+        peer_id: &str,
+        kind: SignalKind,
+        now: Instant,
+    ) -> Option<Option<SignalKind>> {
+        let key = (topic.to_owned(), peer_id.to_owned());
+        // clearing an existing state is always let through so a quick
+        // typing/stopped pair does not leave a stale indicator behind
+        let clears_state =
+            kind.ttl().is_none() && self.expires_at.contains_key(&key);
+        if !clears_state
+            && self.last_received.get(&key).is_some_and(|last| {
+                now.duration_since(*last) < RECEIVE_INTERVAL
+            })
+        {
+            return None;
+        }
+        self.last_received.insert(key.clone(), now);
+
+        match kind.ttl() {
+            Some(ttl) => {
+                self.expires_at.insert(key, now + ttl);
+                Some(Some(kind))
+            }
+            None => {
+                self.expires_at.remove(&key);
+                Some(None)
+            }
+        }
+    }
+
+    /// Drops every expired state, returning the `(topic, peer)` pairs that
+    /// went back to no state.
+    pub fn expire(&mut self, now: Instant) -> Vec<(String, String)> {
+        let expired = self
+            .expires_at
+            .iter()
+            .filter(|(_, expires_at)| **expires_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        for key in &expired {
+            self.expires_at.remove(key);
+        }
+        self.last_received
+            .retain(|_, last| now.duration_since(*last) < RECEIVE_INTERVAL);
+        expired
+    }
+
+    /// Forgets everything about a room, e.g. after leaving it.
+    pub fn clear(&mut self, topic: &str) {
+        self.expires_at.retain(|(t, _), _| t != topic);
+        self.last_sent.retain(|(t, _), _| t != topic);
+        self.last_received.retain(|(t, _), _| t != topic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_topic_roundtrip() {
+        let topic = signal_topic("room");
+        assert_eq!(room_of_signal_topic(&topic), Some("room"));
+        assert_eq!(room_of_signal_topic("room"), None);
+    }
+
+    #[test]
+    fn test_send_rate_limit() {
+        let mut presence = Presence::new();
+        let now = Instant::now();
+        assert!(presence.should_send("room", SignalKind::Typing, now));
+        assert!(!presence.should_send("room", SignalKind::Typing, now));
+        assert!(presence.should_send("room", SignalKind::StoppedTyping, now));
+        assert!(presence.should_send(
+            "room",
+            SignalKind::Typing,
+            now + SEND_INTERVAL
+        ));
+    }
+
+    #[test]
+    fn test_receive_and_expire() {
+        let mut presence = Presence::new();
+        let now = Instant::now();
+        assert_eq!(
+            presence.receive("room", "bob", SignalKind::Typing, now),
+            Some(Some(SignalKind::Typing))
+        );
+        // flooding within the receive interval is ignored
+        assert_eq!(
+            presence.receive("room", "bob", SignalKind::Away, now),
+            None
+        );
+        assert!(presence.expire(now + Duration::from_secs(1)).is_empty());
+        assert_eq!(
+            presence.expire(now + Duration::from_secs(6)),
+            vec![("room".to_owned(), "bob".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_stopped_typing_clears_state() {
+        let mut presence = Presence::new();
+        let now = Instant::now();
+        presence.receive("room", "bob", SignalKind::Typing, now);
+        assert_eq!(
+            presence.receive("room", "bob", SignalKind::StoppedTyping, now),
+            Some(None)
+        );
+        assert!(presence.expire(now + Duration::from_secs(60)).is_empty());
+    }
+}
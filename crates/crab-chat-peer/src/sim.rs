@@ -0,0 +1,352 @@
+use bon::Builder;
+use derive_getters::Getters;
+use futures::io::{ReadHalf, WriteHalf};
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::core::ConnectedPoint;
+use libp2p::{Multiaddr, PeerId};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::time::Instant;
+
+/// Least time a lost frame takes to be sent again.
+const MIN_RETRANSMIT: Duration = Duration::from_millis(200);
+
+/// How a link between two simulated peers behaves.
+///
+/// Connections are streams, so a lost frame is not gone: like TCP, it is
+/// sent again after a retransmission timeout, holding back everything sent
+/// after it on that connection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Getters, Builder)]
+pub struct LinkConditions {
+    /// One way delay of every frame.
+    #[builder(default)]
+    latency: Duration,
+    /// Up to this much is added at random to the latency of a frame.
+    #[builder(default)]
+    jitter: Duration,
+    /// Probability, from 0 to 1, that a frame has to be sent again.
+    #[builder(default)]
+    loss: f64,
+}
+
+/// Shared control of simulated links, handed to every peer that should
+/// take part with [`TransportKind::Simulated`](crate::TransportKind).
+///
+/// Peers reach each other on `/memory/<port>` addresses. Delays are drawn
+/// from a generator seeded at creation, so a run can be replayed.
+#[derive(Clone, Debug)]
+pub struct SimNetwork {
+    state: Arc<Mutex<SimState>>,
+    /// Bumped on every partition change, to close cut connections.
+    changes: watch::Sender<u64>,
+}
+
+#[derive(Debug)]
+struct SimState {
+    rng: u64,
+    default: LinkConditions,
+    links: HashMap<(PeerId, PeerId), LinkConditions>,
+    partitions: HashSet<(PeerId, PeerId)>,
+    addresses: HashMap<Multiaddr, PeerId>,
+}
+
+impl SimNetwork {
+    pub fn new(seed: u64) -> Self {
+        let (changes, _) = watch::channel(0);
+        Self {
+            state: Arc::new(Mutex::new(SimState {
+                rng: seed,
+                default: LinkConditions::default(),
+                links: HashMap::new(),
+                partitions: HashSet::new(),
+                addresses: HashMap::new(),
+            })),
+            changes,
+        }
+    }
+
+    /// Conditions of the links without conditions of their own.
+    pub fn set_default(&self, conditions: LinkConditions) {
+        self.state.lock().unwrap().default = conditions;
+    }
+
+    /// Conditions of the link between `a` and `b`, in both directions.
+    pub fn set_link(&self, a: PeerId, b: PeerId, conditions: LinkConditions) {
+        self.state
+            .lock()
+            .unwrap()
+            .links
+            .insert(link(a, b), conditions);
+    }
+
+    /// Cuts every peer of `side` from every peer of `other`, closing their
+    /// connections and refusing new ones until healed.
+    pub fn partition(&self, side: &[PeerId], other: &[PeerId]) {
+        let mut state = self.state.lock().unwrap();
+        for &a in side {
+            for &b in other {
+                state.partitions.insert(link(a, b));
+            }
+        }
+        drop(state);
+        self.changes.send_modify(|generation| *generation += 1);
+    }
+
+    /// Lifts every partition; peers have to dial each other again.
+    pub fn heal(&self) {
+        self.state.lock().unwrap().partitions.clear();
+        self.changes.send_modify(|generation| *generation += 1);
+    }
+
+    pub fn is_partitioned(&self, a: PeerId, b: PeerId) -> bool {
+        self.state.lock().unwrap().partitions.contains(&link(a, b))
+    }
+
+    /// Records who listens on `address`, so dialers know whom they reach.
+    pub(crate) fn register(&self, address: Multiaddr, peer_id: PeerId) {
+        self.state
+            .lock()
+            .unwrap()
+            .addresses
+            .insert(address, peer_id);
+    }
+
+    /// How long a frame written now from `from` to `to` takes to arrive.
+    fn delay(&self, from: PeerId, to: PeerId) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let conditions = state
+            .links
+            .get(&link(from, to))
+            .copied()
+            .unwrap_or(state.default);
+        let mut delay = conditions.latency
+            + conditions.jitter.mul_f64(next_unit(&mut state.rng));
+        if next_unit(&mut state.rng) < conditions.loss {
+            delay += (conditions.latency * 2).max(MIN_RETRANSMIT);
+        }
+        delay
+    }
+
+    /// Wraps a fresh connection of `local`, refusing it across a partition.
+    ///
+    /// The dialer announces itself first, as nothing else tells the
+    /// listener where a memory connection comes from.
+    pub(crate) async fn connect<S>(
+        self,
+        mut stream: S,
+        endpoint: ConnectedPoint,
+        local: PeerId,
+    ) -> io::Result<SimStream<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let remote = match &endpoint {
+            ConnectedPoint::Dialer { address, .. } => {
+                let remote =
+                    self.state.lock().unwrap().addresses.get(address).copied();
+                let remote = remote.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("nobody simulated listens on {address}"),
+                    )
+                })?;
+                let id = local.to_bytes();
+                stream.write_all(&[id.len() as u8]).await?;
+                stream.write_all(&id).await?;
+                stream.flush().await?;
+                remote
+            }
+            ConnectedPoint::Listener { .. } => {
+                let mut len = [0u8];
+                stream.read_exact(&mut len).await?;
+                let mut id = vec![0u8; len[0] as usize];
+                stream.read_exact(&mut id).await?;
+                PeerId::from_bytes(&id).map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidData, e)
+                })?
+            }
+        };
+        if self.is_partitioned(local, remote) {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("{local} is partitioned from {remote}"),
+            ));
+        }
+        Ok(SimStream::new(stream, self, local, remote))
+    }
+}
+
+/// A connection whose writes arrive as the link conditions dictate.
+pub struct SimStream<S> {
+    reader: ReadHalf<S>,
+    frames: mpsc::UnboundedSender<(Instant, Vec<u8>)>,
+    network: SimNetwork,
+    local: PeerId,
+    remote: PeerId,
+}
+
+impl<S> SimStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    fn new(
+        stream: S,
+        network: SimNetwork,
+        local: PeerId,
+        remote: PeerId,
+    ) -> Self {
+        let (reader, writer) = stream.split();
+        let (frames, frames_rx) = mpsc::unbounded_channel();
+        tokio::spawn(forward(
+            writer,
+            frames_rx,
+            network.clone(),
+            local,
+            remote,
+        ));
+        Self {
+            reader,
+            frames,
+            network,
+            local,
+            remote,
+        }
+    }
+}
+
+/// Writes the frames of a connection once they are due, in order, until
+/// the connection is closed or cut.
+async fn forward<S: AsyncWrite>(
+    mut writer: WriteHalf<S>,
+    mut frames: mpsc::UnboundedReceiver<(Instant, Vec<u8>)>,
+    network: SimNetwork,
+    local: PeerId,
+    remote: PeerId,
+) {
+    let mut changes = network.changes.subscribe();
+    let cut = async {
+        loop {
+            if network.is_partitioned(local, remote) {
+                return;
+            }
+            if changes.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    let frames = async {
+        while let Some((arrival, frame)) = frames.recv().await {
+            tokio::time::sleep_until(arrival).await;
+            if network.is_partitioned(local, remote)
+                || writer.write_all(&frame).await.is_err()
+                || writer.flush().await.is_err()
+            {
+                return;
+            }
+        }
+    };
+    tokio::select! {
+        _ = cut => {}
+        _ = frames => {}
+    }
+    // the other side reads to the end and drops the connection
+    let _ = writer.close().await;
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for SimStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.reader).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for SimStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let arrival =
+            Instant::now() + self.network.delay(self.local, self.remote);
+        match self.frames.send((arrival, buf.to_vec())) {
+            Ok(()) => Poll::Ready(Ok(buf.len())),
+            Err(_) => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    // frames in flight are the link's business, not the writer's
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The same key for a link whichever way it is looked at.
+fn link(a: PeerId, b: PeerId) -> (PeerId, PeerId) {
+    if a <= b { (a, b) } else { (b, a) }
+}
+
+/// Next number of a splitmix64 sequence, scaled to `[0, 1)`.
+fn next_unit(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arrivals_are_reproducible() {
+        let a = PeerId::random();
+        let b = PeerId::random();
+        let conditions = LinkConditions::builder()
+            .latency(Duration::from_millis(50))
+            .jitter(Duration::from_millis(50))
+            .loss(0.5)
+            .build();
+        let delays = |network: SimNetwork| {
+            network.set_link(a, b, conditions);
+            (0..20)
+                .map(|_| network.delay(a, b).as_millis() / 10)
+                .collect::<Vec<_>>()
+        };
+        let first = delays(SimNetwork::new(7));
+        assert_eq!(first, delays(SimNetwork::new(7)));
+        assert!(first.iter().all(|&d| (5..=30).contains(&d)));
+        // some frames were lost and sent again
+        assert!(first.iter().any(|&d| d >= 25));
+    }
+
+    #[test]
+    fn test_partitions_are_symmetric() {
+        let network = SimNetwork::new(0);
+        let a = PeerId::random();
+        let b = PeerId::random();
+        network.partition(&[a], &[b]);
+        assert!(network.is_partitioned(b, a));
+        network.heal();
+        assert!(!network.is_partitioned(a, b));
+    }
+}
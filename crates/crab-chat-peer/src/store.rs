@@ -0,0 +1,504 @@
+use bon::Builder;
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// Name of the history file in the data directory.
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// How many messages of each room the history file keeps by default.
+pub const DEFAULT_HISTORY_LIMIT: usize = 10_000;
+
+/// Reactors of a message grouped by emoji shortcode.
+pub type Reactions = BTreeMap<String, Vec<String>>;
+
+/// Latest `(timestamp, active)` reaction per `(emoji, reactor)`.
+type ReactionLog = HashMap<(String, String), (u64, bool)>;
+
+#[derive(Clone, Debug, Getters, Builder, Serialize, Deserialize)]
+pub struct StoredMessage {
+    message_id: String,
+    peer_id: String,
+    nickname: Option<String>,
+    topic: String,
+    data: String,
+    timestamp: u64,
+    parent_id: Option<String>,
+    #[serde(default)]
+    #[builder(default)]
+    mentions: Vec<String>,
+    #[builder(default)]
+    reactions: Reactions,
+    /// Whether the author edited or deleted the message. Nothing sets them
+    /// on this peer yet, but imported histories keep them and every export
+    /// format shows them.
+    #[serde(default)]
+    #[builder(default)]
+    edited: bool,
+    #[serde(default)]
+    #[builder(default)]
+    deleted: bool,
+}
+
+/// A line of the history file.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Record {
+    Message(StoredMessage),
+    Reaction {
+        message_id: String,
+        emoji: String,
+        peer_id: String,
+        timestamp: u64,
+        active: bool,
+    },
+}
+
+/// History of the messages seen by the local peer, indexed by topic and by
+/// reply parent so threads can be rebuilt cheaply. Opened on a file, every
+/// message and reaction is also appended to it.
+#[derive(Debug, Default)]
+pub struct MessageStore {
+    messages: HashMap<String, StoredMessage>,
+    topics: HashMap<String, Vec<String>>,
+    replies: HashMap<String, Vec<String>>,
+    /// Reactions of each message, keeping only the newest per reactor so
+    /// out-of-order add/remove pairs settle on the last one.
+    reactions: HashMap<String, ReactionLog>,
+    log: Option<File>,
+}
+
+impl MessageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the history kept at `path`, skipping the lines it cannot read,
+    /// and keeps appending to it.
+    ///
+    /// Only the newest `limit` messages of each room are kept, and the file
+    /// is rewritten without what was dropped or superseded.
+    pub fn open(path: &Path, limit: usize) -> Self {
+        let mut store = Self::new();
+        let mut lines = 0;
+        if let Ok(data) = fs::read_to_string(path) {
+            for (i, line) in data.lines().enumerate() {
+                lines += 1;
+                match serde_json::from_str(line) {
+                    Ok(record) => store.replay(record),
+                    Err(e) => log::warn!(
+                        "Skipping line {} of {}: {e}",
+                        i + 1,
+                        path.display()
+                    ),
+                }
+            }
+        }
+        store.prune(limit);
+        let records = store.records();
+        if records.len() != lines
+            && let Err(e) = compact(path, &records)
+        {
+            log::warn!("Failed to compact {}: {e}", path.display());
+        }
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        store.log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .inspect_err(|e| {
+                log::warn!("History will not be kept: {e}");
+            })
+            .ok();
+        store
+    }
+
+    /// Stores a message, returning `false` if it was already known.
+    pub fn insert(&mut self, message: StoredMessage) -> bool {
+        if self.messages.contains_key(&message.message_id) {
+            return false;
+        }
+        self.append(|| Record::Message(message.clone()));
+
+        // kept in time order, whatever order imports and late deliveries
+        // come in
+        let ids = self.topics.entry(message.topic.clone()).or_default();
+        let key = (message.timestamp, &message.message_id);
+        let position = ids.partition_point(|id| {
+            let other = &self.messages[id];
+            (other.timestamp, &other.message_id) < key
+        });
+        ids.insert(position, message.message_id.clone());
+        if let Some(parent_id) = &message.parent_id {
+            self.replies
+                .entry(parent_id.clone())
+                .or_default()
+                .push(message.message_id.clone());
+        }
+        self.messages.insert(message.message_id.clone(), message);
+        true
+    }
+
+    /// Messages of a topic oldest first, keeping only the last `limit`.
+    pub fn history(
+        &self,
+        topic: &str,
+        limit: Option<usize>,
+    ) -> Vec<StoredMessage> {
+        let ids = self.topics.get(topic).map(Vec::as_slice).unwrap_or(&[]);
+        let skip = limit.map_or(0, |limit| ids.len().saturating_sub(limit));
+        ids.iter()
+            .skip(skip)
+            .filter_map(|id| self.messages.get(id))
+            .map(|m| self.with_reactions(m))
+            .collect()
+    }
+
+    /// Direct replies to a message, oldest first.
+    pub fn replies(&self, message_id: &str) -> Vec<StoredMessage> {
+        let mut replies = self
+            .replies
+            .get(message_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.messages.get(id))
+            .map(|m| self.with_reactions(m))
+            .collect::<Vec<_>>();
+        replies.sort_by_key(|m| m.timestamp);
+        replies
+    }
+
+    /// Walks up the reply chain of a message to the oldest known ancestor.
+    pub fn root(&self, message_id: &str) -> Option<&StoredMessage> {
+        let mut current = self.messages.get(message_id)?;
+        // bounded so a malicious reply cycle cannot loop forever
+        for _ in 0..self.messages.len() {
+            match current
+                .parent_id
+                .as_deref()
+                .and_then(|id| self.messages.get(id))
+            {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+        Some(current)
+    }
+
+    /// The whole thread a message belongs to: its root followed by every
+    /// descendant in depth-first order.
+    pub fn thread(&self, message_id: &str) -> Vec<StoredMessage> {
+        let Some(root) = self.root(message_id) else {
+            return vec![];
+        };
+
+        let mut thread = vec![];
+        let mut pending = vec![self.with_reactions(root)];
+        while let Some(message) = pending.pop() {
+            if thread.len() == self.messages.len() {
+                break;
+            }
+            pending.extend(self.replies(&message.message_id).into_iter().rev());
+            thread.push(message);
+        }
+        thread
+    }
+
+    /// Records a reaction, returning `false` if a newer one from the same
+    /// reactor already superseded it.
+    pub fn react(
+        &mut self,
+        message_id: &str,
+        emoji: &str,
+        peer_id: &str,
+        timestamp: u64,
+        active: bool,
+    ) -> bool {
+        let latest = self
+            .reactions
+            .entry(message_id.to_owned())
+            .or_default()
+            .entry((emoji.to_owned(), peer_id.to_owned()))
+            .or_insert((timestamp, !active));
+        if latest.0 > timestamp {
+            return false;
+        }
+        *latest = (timestamp, active);
+        self.append(|| Record::Reaction {
+            message_id: message_id.to_owned(),
+            emoji: emoji.to_owned(),
+            peer_id: peer_id.to_owned(),
+            timestamp,
+            active,
+        });
+        true
+    }
+
+    /// Adds messages exported elsewhere along with their reactions,
+    /// returning how many were new.
+    pub fn import(&mut self, messages: Vec<StoredMessage>) -> usize {
+        let mut imported = 0;
+        for message in messages {
+            if self.messages.contains_key(&message.message_id) {
+                continue;
+            }
+            // the export only tells who reacted, as of the message
+            for (emoji, reactors) in &message.reactions {
+                for reactor in reactors {
+                    self.react(
+                        &message.message_id,
+                        emoji,
+                        reactor,
+                        message.timestamp,
+                        true,
+                    );
+                }
+            }
+            let message = StoredMessage {
+                reactions: Reactions::new(),
+                ..message
+            };
+            self.insert(message);
+            imported += 1;
+        }
+        imported
+    }
+
+    pub fn has_reacted(
+        &self,
+        message_id: &str,
+        emoji: &str,
+        peer_id: &str,
+    ) -> bool {
+        self.reactions
+            .get(message_id)
+            .and_then(|r| r.get(&(emoji.to_owned(), peer_id.to_owned())))
+            .is_some_and(|(_, active)| *active)
+    }
+
+    pub fn reactions(&self, message_id: &str) -> Reactions {
+        let mut reactions = Reactions::new();
+        for ((emoji, peer_id), _) in self
+            .reactions
+            .get(message_id)
+            .into_iter()
+            .flatten()
+            .filter(|(_, (_, active))| *active)
+        {
+            reactions
+                .entry(emoji.clone())
+                .or_default()
+                .push(peer_id.clone());
+        }
+        reactions.values_mut().for_each(|reactors| reactors.sort());
+        reactions
+    }
+
+    fn replay(&mut self, record: Record) {
+        match record {
+            Record::Message(message) => {
+                self.insert(message);
+            }
+            Record::Reaction {
+                message_id,
+                emoji,
+                peer_id,
+                timestamp,
+                active,
+            } => {
+                self.react(&message_id, &emoji, &peer_id, timestamp, active);
+            }
+        }
+    }
+
+    /// Forgets all but the newest `limit` messages of each topic.
+    fn prune(&mut self, limit: usize) {
+        for ids in self.topics.values_mut() {
+            let excess = ids.len().saturating_sub(limit);
+            for id in ids.drain(..excess) {
+                self.messages.remove(&id);
+                self.reactions.remove(&id);
+            }
+        }
+        for replies in self.replies.values_mut() {
+            replies.retain(|id| self.messages.contains_key(id));
+        }
+        self.replies.retain(|_, replies| !replies.is_empty());
+    }
+
+    /// What the history file holds once compacted: every message, then the
+    /// latest reaction of each reactor.
+    fn records(&self) -> Vec<Record> {
+        let messages = self
+            .topics
+            .values()
+            .flatten()
+            .filter_map(|id| self.messages.get(id))
+            .map(|message| Record::Message(message.clone()));
+        let reactions = self.reactions.iter().flat_map(|(message_id, log)| {
+            log.iter().map(|((emoji, peer_id), (timestamp, active))| {
+                Record::Reaction {
+                    message_id: message_id.clone(),
+                    emoji: emoji.clone(),
+                    peer_id: peer_id.clone(),
+                    timestamp: *timestamp,
+                    active: *active,
+                }
+            })
+        });
+        messages.chain(reactions).collect()
+    }
+
+    fn append(&mut self, record: impl FnOnce() -> Record) {
+        let Some(log) = &mut self.log else {
+            return;
+        };
+        let line = serde_json::to_string(&record()).unwrap();
+        if let Err(e) = writeln!(log, "{line}") {
+            log::warn!("Failed to save history: {e}");
+        }
+    }
+
+    fn with_reactions(&self, message: &StoredMessage) -> StoredMessage {
+        StoredMessage {
+            reactions: self.reactions(&message.message_id),
+            ..message.clone()
+        }
+    }
+}
+
+/// Replaces the history file with `records`, written aside first so a crash
+/// never loses it.
+fn compact(path: &Path, records: &[Record]) -> std::io::Result<()> {
+    let mut data = String::new();
+    for record in records {
+        data.push_str(&serde_json::to_string(record).unwrap());
+        data.push('\n');
+    }
+    let partial = path.with_extension("part");
+    fs::write(&partial, data)?;
+    fs::rename(partial, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, parent_id: Option<&str>, ts: u64) -> StoredMessage {
+        StoredMessage::builder()
+            .message_id(id.to_owned())
+            .peer_id("peer".to_owned())
+            .topic("room".to_owned())
+            .data(id.to_owned())
+            .timestamp(ts)
+            .maybe_parent_id(parent_id.map(str::to_owned))
+            .build()
+    }
+
+    fn ids(messages: &[StoredMessage]) -> Vec<&str> {
+        messages.iter().map(|m| m.message_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_insert_ignores_duplicates() {
+        let mut store = MessageStore::new();
+        assert!(store.insert(message("a", None, 1)));
+        assert!(!store.insert(message("a", None, 1)));
+        assert_eq!(store.history("room", None).len(), 1);
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let mut store = MessageStore::new();
+        for (i, id) in ["a", "b", "c"].into_iter().enumerate() {
+            store.insert(message(id, None, i as u64));
+        }
+        assert_eq!(ids(&store.history("room", Some(2))), vec!["b", "c"]);
+        assert!(store.history("other", None).is_empty());
+    }
+
+    #[test]
+    fn test_thread_from_any_member() {
+        let mut store = MessageStore::new();
+        store.insert(message("root", None, 1));
+        store.insert(message("a", Some("root"), 2));
+        store.insert(message("b", Some("root"), 3));
+        store.insert(message("a1", Some("a"), 4));
+        store.insert(message("other", None, 5));
+
+        let expected = vec!["root", "a", "a1", "b"];
+        assert_eq!(ids(&store.thread("a1")), expected);
+        assert_eq!(ids(&store.thread("root")), expected);
+        assert_eq!(ids(&store.replies("root")), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_reactions_toggle_and_order() {
+        let mut store = MessageStore::new();
+        store.insert(message("a", None, 1));
+        assert!(store.react("a", "tada", "bob", 10, true));
+        assert!(store.react("a", "tada", "alice", 10, true));
+        assert!(store.react("a", "eyes", "bob", 11, true));
+        assert!(store.react("a", "eyes", "bob", 12, false));
+        // an older add arriving late must not resurrect the reaction
+        assert!(!store.react("a", "eyes", "bob", 11, true));
+
+        let reactions = store.reactions("a");
+        assert_eq!(reactions.get("tada").unwrap(), &vec!["alice", "bob"]);
+        assert!(!reactions.contains_key("eyes"));
+        assert!(store.has_reacted("a", "tada", "bob"));
+        assert_eq!(store.history("room", None)[0].reactions, reactions);
+    }
+
+    #[test]
+    fn test_history_file_and_import() {
+        let dir = std::env::temp_dir()
+            .join(format!("crab-chat-history-{}", std::process::id()));
+        let path = dir.join(HISTORY_FILE);
+        let mut store = MessageStore::open(&path, DEFAULT_HISTORY_LIMIT);
+        store.insert(message("a", None, 1));
+        store.react("a", "tada", "bob", 10, true);
+
+        let mut exported = MessageStore::new();
+        let mut b = message("b", Some("a"), 2);
+        b.reactions
+            .insert("eyes".to_owned(), vec!["carol".to_owned()]);
+        exported.import(vec![b]);
+        let b = exported.history("room", None).pop().unwrap();
+        assert_eq!(store.import(vec![message("a", None, 1), b]), 1);
+        // older than everything we have, it still comes first
+        assert_eq!(store.import(vec![message("old", None, 0)]), 1);
+
+        let restored = MessageStore::open(&path, DEFAULT_HISTORY_LIMIT);
+        let history = restored.history("room", None);
+        assert_eq!(ids(&history), vec!["old", "a", "b"]);
+        assert_eq!(history[1].reactions.get("tada").unwrap(), &vec!["bob"]);
+        assert_eq!(history[2].reactions.get("eyes").unwrap(), &vec!["carol"]);
+        assert!(!history[2].edited && !history[2].deleted);
+        drop((store, restored));
+
+        // reopening with a lower limit drops the oldest for good
+        let pruned = MessageStore::open(&path, 2);
+        assert_eq!(ids(&pruned.history("room", None)), vec!["a", "b"]);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 4);
+        drop(pruned);
+        let restored = MessageStore::open(&path, DEFAULT_HISTORY_LIMIT);
+        let history = restored.history("room", None);
+        assert_eq!(ids(&history), vec!["a", "b"]);
+        assert_eq!(history[0].reactions.get("tada").unwrap(), &vec!["bob"]);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_thread_with_unknown_parent() {
+        let mut store = MessageStore::new();
+        store.insert(message("orphan", Some("missing"), 1));
+        assert_eq!(ids(&store.thread("orphan")), vec!["orphan"]);
+        assert!(store.thread("missing").is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+use super::PROTOCOL;
+use libp2p::core::transport::PortUse;
+use libp2p::core::upgrade::ReadyUpgrade;
+use libp2p::core::{Endpoint, Multiaddr};
+use libp2p::swarm::dial_opts::DialOpts;
+use libp2p::swarm::handler::{
+    ConnectionEvent, DialUpgradeError, FullyNegotiatedInbound,
+    FullyNegotiatedOutbound,
+};
+use libp2p::swarm::{
+    ConnectionDenied, ConnectionHandler, ConnectionHandlerEvent, ConnectionId,
+    FromSwarm, NetworkBehaviour, NotifyHandler, Stream, StreamProtocol,
+    SubstreamProtocol, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use libp2p::PeerId;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::task::{Context, Poll};
+
+/// Identifies a stream requested with [`Behaviour::open`].
+pub type StreamId = u64;
+
+#[derive(Debug)]
+pub enum Event {
+    /// A remote peer opened a transfer stream to us.
+    Inbound { peer_id: PeerId, stream: Stream },
+    /// A stream we asked for is ready.
+    Outbound { id: StreamId, stream: Stream },
+    /// A stream we asked for could not be opened.
+    Failed { id: StreamId, error: String },
+}
+
+/// Hands out raw streams of the file transfer protocol, leaving the actual
+/// reading and writing to the caller.
+#[derive(Default)]
+pub struct Behaviour {
+    next_id: StreamId,
+    connected: HashSet<PeerId>,
+    /// Streams waiting for a connection to their peer.
+    dialing: HashMap<PeerId, Vec<StreamId>>,
+    actions: VecDeque<ToSwarm<Event, StreamId>>,
+}
+
+impl Behaviour {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks for a new stream to `peer_id`, dialing it first if needed.
+    pub fn open(&mut self, peer_id: PeerId) -> StreamId {
+        let id = self.next_id;
+        self.next_id += 1;
+        if self.connected.contains(&peer_id) {
+            self.actions.push_back(notify(peer_id, id));
+        } else {
+            let dialing = self.dialing.entry(peer_id).or_default();
+            if dialing.is_empty() {
+                self.actions.push_back(ToSwarm::Dial {
+                    opts: DialOpts::peer_id(peer_id).build(),
+                });
+            }
+            dialing.push(id);
+        }
+        id
+    }
+}
+
+fn notify(peer_id: PeerId, id: StreamId) -> ToSwarm<Event, StreamId> {
+    ToSwarm::NotifyHandler {
+        peer_id,
+        handler: NotifyHandler::Any,
+        event: id,
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = Handler;
+    type ToSwarm = Event;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(Handler::default())
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+        _: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(Handler::default())
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        match event {
+            FromSwarm::ConnectionEstablished(e) => {
+                self.connected.insert(e.peer_id);
+                for id in self.dialing.remove(&e.peer_id).unwrap_or_default() {
+                    self.actions.push_back(notify(e.peer_id, id));
+                }
+            }
+            FromSwarm::ConnectionClosed(e) if e.remaining_established == 0 => {
+                self.connected.remove(&e.peer_id);
+            }
+            FromSwarm::DialFailure(e) => {
+                let Some(peer_id) = e.peer_id else {
+                    return;
+                };
+                for id in self.dialing.remove(&peer_id).unwrap_or_default() {
+                    self.actions.push_back(ToSwarm::GenerateEvent(
+                        Event::Failed {
+                            id,
+                            error: e.error.to_string(),
+                        },
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        _: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        let event = match event {
+            HandlerEvent::Inbound(stream) => Event::Inbound { peer_id, stream },
+            HandlerEvent::Outbound(id, stream) => {
+                Event::Outbound { id, stream }
+            }
+            HandlerEvent::Failed(id, error) => Event::Failed { id, error },
+        };
+        self.actions.push_back(ToSwarm::GenerateEvent(event));
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        match self.actions.pop_front() {
+            Some(action) => Poll::Ready(action),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum HandlerEvent {
+    Inbound(Stream),
+    Outbound(StreamId, Stream),
+    Failed(StreamId, String),
+}
+
+#[derive(Default)]
+pub struct Handler {
+    requested: VecDeque<StreamId>,
+    /// Requested streams being negotiated. Any stream of the protocol does
+    /// for any request, so they are matched in order.
+    opening: VecDeque<StreamId>,
+    events: VecDeque<HandlerEvent>,
+}
+
+impl ConnectionHandler for Handler {
+    type FromBehaviour = StreamId;
+    type ToBehaviour = HandlerEvent;
+    type InboundProtocol = ReadyUpgrade<StreamProtocol>;
+    type OutboundProtocol = ReadyUpgrade<StreamProtocol>;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = ();
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
+        SubstreamProtocol::new(ReadyUpgrade::new(PROTOCOL), ())
+    }
+
+    fn poll(
+        &mut self,
+        _: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<Self::OutboundProtocol, (), Self::ToBehaviour>,
+    > {
+        if let Some(event) = self.events.pop_front() {
+            return Poll::Ready(ConnectionHandlerEvent::NotifyBehaviour(event));
+        }
+        if let Some(id) = self.requested.pop_front() {
+            self.opening.push_back(id);
+            return Poll::Ready(
+                ConnectionHandlerEvent::OutboundSubstreamRequest {
+                    protocol: SubstreamProtocol::new(
+                        ReadyUpgrade::new(PROTOCOL),
+                        (),
+                    ),
+                },
+            );
+        }
+        Poll::Pending
+    }
+
+    fn on_behaviour_event(&mut self, id: StreamId) {
+        self.requested.push_back(id);
+    }
+
+    fn on_connection_event(
+        &mut self,
+        event: ConnectionEvent<Self::InboundProtocol, Self::OutboundProtocol>,
+    ) {
+        match event {
+            ConnectionEvent::FullyNegotiatedInbound(
+                FullyNegotiatedInbound {
+                    protocol: stream, ..
+                },
+            ) => self.events.push_back(HandlerEvent::Inbound(stream)),
+            ConnectionEvent::FullyNegotiatedOutbound(
+                FullyNegotiatedOutbound {
+                    protocol: stream, ..
+                },
+            ) => {
+                if let Some(id) = self.opening.pop_front() {
+                    self.events.push_back(HandlerEvent::Outbound(id, stream));
+                }
+            }
+            ConnectionEvent::DialUpgradeError(DialUpgradeError {
+                error,
+                ..
+            }) => {
+                if let Some(id) = self.opening.pop_front() {
+                    self.events
+                        .push_back(HandlerEvent::Failed(id, error.to_string()));
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,529 @@
+mod behaviour;
+
+pub use behaviour::{Behaviour, Event, StreamId};
+
+use crate::message::FileOffer;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::StreamProtocol;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt as _, AsyncSeekExt, AsyncWriteExt as _};
+
+pub const PROTOCOL: StreamProtocol =
+    StreamProtocol::new("/crab-chat/file/1.0.0");
+
+/// Largest file we offer or accept.
+pub const MAX_FILE_SIZE: u64 = 256 * 1024 * 1024;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Upper bound for a request frame, which only carries a little JSON.
+const MAX_REQUEST_SIZE: usize = 4 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TransferDirection {
+    Upload,
+    Download,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum TransferStatus {
+    Progress {
+        transferred: u64,
+        total: u64,
+    },
+    /// Done; `path` is where a download was saved.
+    Completed {
+        path: Option<PathBuf>,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// A file we offered, looked up by id when a peer asks for it.
+#[derive(Debug, Clone)]
+pub struct SharedFile {
+    pub path: PathBuf,
+    pub offer: FileOffer,
+}
+
+/// Files we offered, shared with the tasks serving them.
+pub type SharedFiles = Arc<Mutex<HashMap<String, SharedFile>>>;
+
+/// Asks for the content of a file starting at `offset`, so an interrupted
+/// download resumes where it stopped.
+#[derive(Debug, Serialize, Deserialize)]
+struct ChunkRequest {
+    file_id: String,
+    offset: u64,
+}
+
+/// Hashes a file and describes it for an offer. Blocking.
+pub fn prepare_offer(path: &Path) -> io::Result<SharedFile> {
+    let metadata = std::fs::metadata(path)?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a regular file",
+        ));
+    }
+    let size = metadata.len();
+    if size > MAX_FILE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("file is larger than {MAX_FILE_SIZE} bytes"),
+        ));
+    }
+    let name =
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "invalid file name")
+            })?;
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    let offer = FileOffer::builder()
+        .file_id(uuid::Uuid::new_v4().to_string())
+        .name(name.to_owned())
+        .size(size)
+        .sha256(hex::encode(hasher.finalize()))
+        .build();
+    Ok(SharedFile {
+        path: path.to_owned(),
+        offer,
+    })
+}
+
+/// Answers the request read from `stream` with the rest of the file,
+/// reporting progress as it goes. Only files we offered are served.
+pub async fn serve<S>(
+    stream: &mut S,
+    files: &SharedFiles,
+    mut report: impl FnMut(&FileOffer, TransferStatus),
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = read_frame(stream, MAX_REQUEST_SIZE).await?;
+    let request: ChunkRequest = serde_json::from_slice(&request)?;
+    let file = files.lock().unwrap().get(&request.file_id).cloned();
+    let Some(SharedFile { path, offer }) = file else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "unknown file"));
+    };
+    if request.offset > *offer.size() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "offset past the end of the file",
+        ));
+    }
+
+    let mut file = tokio::fs::File::open(&path).await?;
+    file.seek(SeekFrom::Start(request.offset)).await?;
+    let mut progress = Progress::new(*offer.size(), request.offset);
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        // never send more than offered, even if the file grew since
+        let left = offer.size() - progress.transferred;
+        let n = file.read(&mut buf[..CHUNK_SIZE.min(left as usize)]).await?;
+        write_frame(stream, &buf[..n]).await?;
+        if n == 0 {
+            break;
+        }
+        if let Some(status) = progress.advance(n) {
+            report(&offer, status);
+        }
+    }
+    stream.close().await?;
+    report(&offer, TransferStatus::Completed { path: None });
+    Ok(())
+}
+
+/// Downloads an offered file into `directory`, resuming a previous partial
+/// download if there is one, and checks it against the offered hash.
+pub async fn download<S>(
+    stream: &mut S,
+    offer: &FileOffer,
+    directory: &Path,
+    mut report: impl FnMut(TransferStatus),
+) -> io::Result<PathBuf>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // the name and id come from a remote peer, keep them inside `directory`
+    let name = Path::new(offer.name())
+        .file_name()
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "invalid file name")
+        })?
+        .to_owned();
+    let part = directory.join(part_name(offer.file_id())?);
+    tokio::fs::create_dir_all(directory).await?;
+
+    let mut hasher = Sha256::new();
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&part)
+        .await?;
+    let mut offset = hash_existing(&mut file, &mut hasher).await?;
+    if offset > *offer.size() {
+        file.set_len(0).await?;
+        file.seek(SeekFrom::Start(0)).await?;
+        hasher = Sha256::new();
+        offset = 0;
+    }
+
+    let request = ChunkRequest {
+        file_id: offer.file_id().clone(),
+        offset,
+    };
+    write_frame(stream, &serde_json::to_vec(&request)?).await?;
+
+    let mut progress = Progress::new(*offer.size(), offset);
+    loop {
+        let chunk = read_frame(stream, CHUNK_SIZE).await?;
+        if chunk.is_empty() {
+            break;
+        }
+        if progress.transferred + chunk.len() as u64 > *offer.size() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "received more than offered",
+            ));
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+        if let Some(status) = progress.advance(chunk.len()) {
+            report(status);
+        }
+    }
+    file.flush().await?;
+
+    if progress.transferred != *offer.size() {
+        // the partial file stays around to resume from
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "transfer interrupted",
+        ));
+    }
+    if hex::encode(hasher.finalize()) != *offer.sha256() {
+        tokio::fs::remove_file(&part).await?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "checksum mismatch",
+        ));
+    }
+    let target = reserve(directory, Path::new(&name)).await?;
+    tokio::fs::rename(&part, &target).await?;
+    Ok(target)
+}
+
+/// Partial download of the offer `file_id`; named after the id rather than
+/// the file so two offers of the same name do not share it.
+fn part_name(file_id: &str) -> io::Result<String> {
+    let id = file_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect::<String>();
+    match id.is_empty() {
+        true => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid file id",
+        )),
+        false => Ok(format!("{id}.part")),
+    }
+}
+
+/// Creates the first free path among `name`, `name (1)`, `name (2)`... in
+/// `directory`, so a download never replaces an existing file.
+async fn reserve(directory: &Path, name: &Path) -> io::Result<PathBuf> {
+    let stem = name
+        .file_stem()
+        .unwrap_or(name.as_os_str())
+        .to_string_lossy();
+    let extension = name
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    for n in 0.. {
+        let target = match n {
+            0 => directory.join(name),
+            n => directory.join(format!("{stem} ({n}){extension}")),
+        };
+        let created = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&target)
+            .await;
+        match created {
+            Ok(_) => return Ok(target),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("the names run out before the integers")
+}
+
+/// Feeds what was already downloaded to the hasher, returning its length.
+async fn hash_existing(
+    file: &mut tokio::fs::File,
+    hasher: &mut Sha256,
+) -> io::Result<u64> {
+    let mut len = 0;
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(len);
+        }
+        hasher.update(&buf[..n]);
+        len += n as u64;
+    }
+}
+
+async fn write_frame<S>(stream: &mut S, data: &[u8]) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    stream.write_all(&(data.len() as u32).to_be_bytes()).await?;
+    stream.write_all(data).await?;
+    stream.flush().await
+}
+
+async fn read_frame<S>(stream: &mut S, max_len: usize) -> io::Result<Vec<u8>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+    let mut data = vec![0; len];
+    stream.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+/// Throttles progress reports to one per percent.
+struct Progress {
+    total: u64,
+    transferred: u64,
+    reported: u64,
+}
+
+impl Progress {
+    fn new(total: u64, transferred: u64) -> Self {
+        Self {
+            total,
+            transferred,
+            reported: percent(transferred, total),
+        }
+    }
+
+    fn advance(&mut self, n: usize) -> Option<TransferStatus> {
+        self.transferred += n as u64;
+        let percent = percent(self.transferred, self.total);
+        if percent == self.reported {
+            return None;
+        }
+        self.reported = percent;
+        Some(TransferStatus::Progress {
+            transferred: self.transferred,
+            total: self.total,
+        })
+    }
+}
+
+fn percent(transferred: u64, total: u64) -> u64 {
+    (transferred * 100).checked_div(total).unwrap_or(100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::io::Cursor;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Replays canned input and records everything written.
+    struct Pipe {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Pipe {
+        fn new(input: Vec<u8>) -> Self {
+            Self {
+                input: Cursor::new(input),
+                output: vec![],
+            }
+        }
+    }
+
+    impl AsyncRead for Pipe {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.input).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for Pipe {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.output).poll_write(cx, buf)
+        }
+
+        fn poll_flush(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Shares `content` and returns the served response to a request for
+    /// it starting at `offset`.
+    async fn served(content: &[u8], offset: u64) -> (FileOffer, Vec<u8>) {
+        let path = temp_dir().join("file.bin");
+        std::fs::write(&path, content).unwrap();
+        let shared = prepare_offer(&path).unwrap();
+        let offer = shared.offer.clone();
+        let files = SharedFiles::default();
+        files
+            .lock()
+            .unwrap()
+            .insert(offer.file_id().clone(), shared);
+
+        let mut request = vec![];
+        let body = ChunkRequest {
+            file_id: offer.file_id().clone(),
+            offset,
+        };
+        write_frame(&mut request, &serde_json::to_vec(&body).unwrap())
+            .await
+            .unwrap();
+        let mut stream = Pipe::new(request);
+        serve(&mut stream, &files, |_, _| {}).await.unwrap();
+        (offer, stream.output)
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes_and_verifies() {
+        let content = (0..200_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let half = content.len() / 2;
+        let (offer, response) = served(&content, half as u64).await;
+
+        let dir = temp_dir();
+        let part = dir.join(format!("{}.part", offer.file_id()));
+        std::fs::write(&part, &content[..half]).unwrap();
+        let mut statuses = vec![];
+        let mut stream = Pipe::new(response);
+        let path = download(&mut stream, &offer, &dir, |s| statuses.push(s))
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(path).unwrap(), content);
+        assert!(!part.exists());
+        assert_eq!(
+            statuses.last(),
+            Some(&TransferStatus::Progress {
+                transferred: content.len() as u64,
+                total: content.len() as u64,
+            })
+        );
+        // the request asked for the missing half only
+        let request = read_frame(&mut Cursor::new(stream.output), 1024)
+            .await
+            .unwrap();
+        let request: ChunkRequest = serde_json::from_slice(&request).unwrap();
+        assert_eq!(request.offset, half as u64);
+    }
+
+    #[tokio::test]
+    async fn test_download_rejects_corrupted_file() {
+        let content = b"hello world".to_vec();
+        let (offer, response) = served(&content, 0).await;
+        let forged = FileOffer::builder()
+            .file_id(offer.file_id().clone())
+            .name(offer.name().clone())
+            .size(*offer.size())
+            .sha256(hex::encode(Sha256::digest(b"something else")))
+            .build();
+
+        let dir = temp_dir();
+        let mut stream = Pipe::new(response);
+        let error = download(&mut stream, &forged, &dir, |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(!dir.join("file.bin").exists());
+    }
+
+    #[tokio::test]
+    async fn test_download_keeps_existing_files() {
+        let content = b"hello world".to_vec();
+        let dir = temp_dir();
+        std::fs::write(dir.join("file.bin"), b"mine").unwrap();
+        std::fs::write(dir.join("file (1).bin"), b"earlier").unwrap();
+
+        let (offer, response) = served(&content, 0).await;
+        let mut stream = Pipe::new(response);
+        let path = download(&mut stream, &offer, &dir, |_| {}).await.unwrap();
+
+        assert_eq!(path, dir.join("file (2).bin"));
+        assert_eq!(std::fs::read(path).unwrap(), content);
+        assert_eq!(std::fs::read(dir.join("file.bin")).unwrap(), b"mine");
+        assert_eq!(
+            std::fs::read(dir.join("file (1).bin")).unwrap(),
+            b"earlier"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_serve_unknown_file() {
+        let mut request = vec![];
+        let body = ChunkRequest {
+            file_id: "missing".to_owned(),
+            offset: 0,
+        };
+        write_frame(&mut request, &serde_json::to_vec(&body).unwrap())
+            .await
+            .unwrap();
+        let mut stream = Pipe::new(request);
+        let error = serve(&mut stream, &SharedFiles::default(), |_, _| {})
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
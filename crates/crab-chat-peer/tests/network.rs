@@ -0,0 +1,194 @@
+mod support;
+
+use crab_chat_peer::{DEFAULT_INVITE_TTL, PeerEvent, SendOutcome};
+use std::time::Duration;
+use support::{TestNetwork, Topology};
+
+#[tokio::test]
+async fn test_message_crosses_a_line() {
+    let mut network = TestNetwork::spawn(3, Topology::Line).await;
+    network.join("rust").await;
+
+    let outcome = network
+        .peer(0)
+        .peer
+        .send_message("hello".to_owned(), "rust".to_owned())
+        .await
+        .unwrap();
+    assert!(matches!(outcome, SendOutcome::Sent(_)));
+
+    let author = network.peer(0).peer_id().to_string();
+    for index in [1, 2] {
+        let event = network
+            .peer(index)
+            .expect("the message", |event| match event {
+                PeerEvent::MessageReceived(event) => Some(event),
+                _ => None,
+            })
+            .await;
+        assert_eq!(event.message(), "hello");
+        assert_eq!(*event.peer_id(), author);
+        assert_eq!(event.nickname().as_deref(), Some("peer-0"));
+    }
+
+    let snapshot = network.peer(1).peer.metrics_snapshot().await.unwrap();
+    assert_eq!(*snapshot.connected_peers(), 2);
+    let rust = &snapshot.topics()[0];
+    assert_eq!(rust.topic(), "rust");
+    assert_eq!((*rust.published(), *rust.received()), (0, 1));
+    assert!(snapshot.text().contains("crab_chat_gossipsub"));
+    assert!(snapshot.text().contains("crab_chat_libp2p_swarm"));
+}
+
+#[tokio::test]
+async fn test_leaving_is_reported() {
+    let mut network = TestNetwork::spawn(3, Topology::Star).await;
+    network.join("rust").await;
+
+    let leaver = network.peer(2).peer_id().to_string();
+    network
+        .peer(2)
+        .peer
+        .unsubscribe_topic("rust".to_owned())
+        .await
+        .unwrap();
+    let topic = network
+        .peer(0)
+        .expect("peer-2 to leave", |event| match event {
+            PeerEvent::PeerLeft(left) if *left.peer_id() == leaver => {
+                Some(left.topic().clone())
+            }
+            _ => None,
+        })
+        .await;
+    assert_eq!(topic, "rust");
+}
+
+#[tokio::test]
+async fn test_queued_message_waits_for_a_peer() {
+    let mut network = TestNetwork::spawn(2, Topology::Isolated).await;
+    network
+        .peer(0)
+        .peer
+        .subscribe_topic("rust".to_owned())
+        .await
+        .unwrap();
+    let outcome = network
+        .peer(0)
+        .peer
+        .send_message("anyone?".to_owned(), "rust".to_owned())
+        .await
+        .unwrap();
+    assert!(matches!(outcome, SendOutcome::Queued(_)));
+
+    network.connect(1, 0).await;
+    network.join("rust").await;
+    let event = network
+        .peer(1)
+        .expect("the queued message", |event| match event {
+            PeerEvent::MessageReceived(event) => Some(event),
+            _ => None,
+        })
+        .await;
+    assert_eq!(event.message(), "anyone?");
+}
+
+#[tokio::test]
+async fn test_network_info_and_disconnect() {
+    let mut network = TestNetwork::spawn(2, Topology::Line).await;
+    network.join("rust").await;
+
+    let remote = network.peer(1).peer_id().to_string();
+    let info = network.peer(0).peer.network_info().await.unwrap();
+    assert_eq!(*info.peer_id(), network.peer(0).peer_id().to_string());
+    assert_eq!(info.listen_addrs().len(), 1);
+    assert_eq!(info.peers().len(), 1);
+    assert_eq!(*info.peers()[0].peer_id(), remote);
+    assert_eq!(info.peers()[0].transport(), "memory");
+    assert_eq!(info.mesh().len(), 1);
+    assert_eq!(info.mesh()[0].topic(), "rust");
+    assert_eq!(*info.mesh()[0].peers(), vec![remote.clone()]);
+
+    network
+        .peer(0)
+        .peer
+        .disconnect(remote.clone())
+        .await
+        .unwrap();
+    tokio::time::timeout(support::TIMEOUT, async {
+        while !network
+            .peer(0)
+            .peer
+            .network_info()
+            .await
+            .unwrap()
+            .peers()
+            .is_empty()
+        {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("the connection to close");
+    assert!(network.peer(0).peer.disconnect(remote).await.is_err());
+}
+
+#[tokio::test]
+async fn test_connection_events() {
+    let mut network = TestNetwork::spawn(2, Topology::Line).await;
+    let local = network.peer(0).peer_id().to_string();
+    let remote = network.peer(1).peer_id().to_string();
+
+    network
+        .peer(0)
+        .peer
+        .disconnect(remote.clone())
+        .await
+        .unwrap();
+    for (index, other) in [(0, &remote), (1, &local)] {
+        let lost = network
+            .peer(index)
+            .expect("the connection to close", |event| match event {
+                PeerEvent::Connection(event) if !event.connected() => {
+                    Some(event)
+                }
+                _ => None,
+            })
+            .await;
+        assert_eq!(lost.peer_id(), other);
+        assert_eq!(*lost.connected_peers(), 0);
+    }
+}
+
+#[tokio::test]
+async fn test_invite_hands_out_the_room_key() {
+    let mut network = TestNetwork::spawn(2, Topology::Line).await;
+    let invite = network
+        .peer(0)
+        .peer
+        .create_invite("rust".to_owned(), Some(vec![7; 32]), DEFAULT_INVITE_TTL)
+        .await
+        .unwrap();
+    let mut addresses = invite.addresses().clone();
+    addresses.sort();
+    addresses.dedup();
+    assert_eq!(addresses.len(), invite.addresses().len());
+
+    let accepted = network
+        .peer(1)
+        .peer
+        .accept_invite(invite.to_url())
+        .await
+        .unwrap();
+    assert_eq!(accepted.room(), "rust");
+    assert_eq!(accepted.key().as_deref(), Some(&[7; 32][..]));
+}
+
+#[test]
+fn test_topologies() {
+    assert_eq!(Topology::Line.links(3), vec![(0, 1), (1, 2)]);
+    assert_eq!(Topology::Ring.links(3), vec![(0, 1), (1, 2), (2, 0)]);
+    assert_eq!(Topology::Star.links(3), vec![(1, 0), (2, 0)]);
+    assert_eq!(Topology::Full.links(3), vec![(0, 1), (0, 2), (1, 2)]);
+    assert_eq!(support::keypair(1).public(), support::keypair(1).public());
+}
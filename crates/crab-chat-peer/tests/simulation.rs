@@ -0,0 +1,104 @@
+mod support;
+
+use crab_chat_peer::{
+    LinkConditions, PeerEvent, SendOutcome, SimNetwork, TransportKind,
+};
+use std::time::{Duration, Instant};
+use support::{TestNetwork, Topology};
+
+async fn simulated(count: usize, network: &SimNetwork) -> TestNetwork {
+    let transport = TransportKind::Simulated(network.clone());
+    let mut network =
+        TestNetwork::spawn_with(count, Topology::Line, transport).await;
+    network.join("rust").await;
+    network
+}
+
+fn text(event: PeerEvent) -> Option<String> {
+    match event {
+        PeerEvent::MessageReceived(event) => Some(event.message().clone()),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn test_latency_delays_messages() {
+    let sim = SimNetwork::new(1);
+    let mut network = simulated(2, &sim).await;
+    sim.set_default(
+        LinkConditions::builder()
+            .latency(Duration::from_millis(300))
+            .build(),
+    );
+
+    let start = Instant::now();
+    network
+        .peer(0)
+        .peer
+        .send_message("slow".to_owned(), "rust".to_owned())
+        .await
+        .unwrap();
+    let received = network.peer(1).expect("the message", text).await;
+    assert_eq!(received, "slow");
+    assert!(start.elapsed() >= Duration::from_millis(300));
+}
+
+#[tokio::test]
+async fn test_order_survives_jitter_and_loss() {
+    let sim = SimNetwork::new(2);
+    let mut network = simulated(3, &sim).await;
+    sim.set_default(
+        LinkConditions::builder()
+            .latency(Duration::from_millis(5))
+            .jitter(Duration::from_millis(20))
+            .loss(0.2)
+            .build(),
+    );
+
+    let sent: Vec<_> = (0..20).map(|i| format!("message {i}")).collect();
+    for message in &sent {
+        network
+            .peer(0)
+            .peer
+            .send_message(message.clone(), "rust".to_owned())
+            .await
+            .unwrap();
+    }
+    for index in [1, 2] {
+        let mut received = vec![];
+        for _ in &sent {
+            received.push(network.peer(index).expect("a message", text).await);
+        }
+        assert_eq!(received, sent);
+    }
+}
+
+#[tokio::test]
+async fn test_rejoin_after_partition() {
+    let sim = SimNetwork::new(3);
+    let mut network = simulated(2, &sim).await;
+    let (a, b) = (network.peer(0).peer_id(), network.peer(1).peer_id());
+
+    sim.partition(&[a], &[b]);
+    // nothing reports the cut but the room turning empty
+    let deadline = Instant::now() + support::TIMEOUT;
+    loop {
+        let outcome = network
+            .peer(0)
+            .peer
+            .send_message("still there?".to_owned(), "rust".to_owned())
+            .await
+            .unwrap();
+        if matches!(outcome, SendOutcome::Queued(_)) {
+            break;
+        }
+        assert!(Instant::now() < deadline, "the partition never took");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    sim.heal();
+    network.connect(1, 0).await;
+    network.join("rust").await;
+    let received = network.peer(1).expect("the queued message", text).await;
+    assert_eq!(received, "still there?");
+}
@@ -0,0 +1,210 @@
+//! Spins up peers inside the test process, on the memory transport, and
+//! waits for what they report.
+
+#![allow(dead_code)]
+
+use crab_chat_peer::{
+    Peer, PeerConfig, PeerEvent, PeerEventListener, TransportKind,
+};
+use libp2p::identity::Keypair;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// How long to wait for an event before failing the test.
+pub const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Memory ports are shared by the whole test binary.
+static NEXT_PORT: AtomicU64 = AtomicU64::new(1);
+
+/// How the peers of a [`TestNetwork`] are connected to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// Nobody is connected.
+    Isolated,
+    /// Each peer to the next one.
+    Line,
+    /// A line whose ends are connected too.
+    Ring,
+    /// Everybody to the first peer.
+    Star,
+    /// Everybody to everybody.
+    Full,
+}
+
+impl Topology {
+    /// Pairs of connected peers; the first of each pair dials.
+    pub fn links(self, count: usize) -> Vec<(usize, usize)> {
+        match self {
+            Topology::Isolated => vec![],
+            Topology::Line => (1..count).map(|i| (i - 1, i)).collect(),
+            Topology::Ring => {
+                let mut links = Topology::Line.links(count);
+                if count > 2 {
+                    links.push((count - 1, 0));
+                }
+                links
+            }
+            Topology::Star => (1..count).map(|i| (i, 0)).collect(),
+            Topology::Full => (0..count)
+                .flat_map(|i| (i + 1..count).map(move |j| (i, j)))
+                .collect(),
+        }
+    }
+}
+
+/// The same key for the same index, so peer ids are stable across runs.
+pub fn keypair(index: usize) -> Keypair {
+    let mut secret = [0u8; 32];
+    secret[..8].copy_from_slice(&(index as u64 + 1).to_be_bytes());
+    Keypair::ed25519_from_bytes(secret).unwrap()
+}
+
+pub struct TestPeer {
+    pub peer: Peer,
+    pub address: Multiaddr,
+    /// Every event of the peer since it started.
+    pub events: PeerEventListener,
+}
+
+impl TestPeer {
+    pub fn peer_id(&self) -> PeerId {
+        *self.peer.peer_id()
+    }
+
+    /// Waits for the first event `pick` accepts, skipping the others.
+    pub async fn expect<T>(
+        &mut self,
+        what: &str,
+        pick: impl FnMut(PeerEvent) -> Option<T>,
+    ) -> T {
+        expect_event(&mut self.events, what, pick).await
+    }
+}
+
+pub struct TestNetwork {
+    pub peers: Vec<TestPeer>,
+    links: Vec<(usize, usize)>,
+}
+
+impl TestNetwork {
+    /// Starts `count` peers, nicknamed `peer-<index>`, and connects them.
+    pub async fn spawn(count: usize, topology: Topology) -> Self {
+        Self::spawn_with(count, topology, TransportKind::Memory).await
+    }
+
+    /// Starts peers on `transport`, one of the memory ones.
+    pub async fn spawn_with(
+        count: usize,
+        topology: Topology,
+        transport: TransportKind,
+    ) -> Self {
+        let mut peers = vec![];
+        for index in 0..count {
+            let port = NEXT_PORT.fetch_add(1, Ordering::Relaxed);
+            let address = Multiaddr::empty().with(Protocol::Memory(port));
+            let config =
+                PeerConfig::new(address.clone(), vec![], keypair(index))
+                    .with_nickname(Some(format!("peer-{index}")))
+                    .with_transport(transport.clone())
+                    .with_mdns(false);
+            let peer = Peer::new(config).unwrap();
+            let events = peer.subscribe();
+            peers.push(TestPeer {
+                peer,
+                address,
+                events,
+            });
+        }
+        let mut network = Self {
+            peers,
+            links: vec![],
+        };
+        for (from, to) in topology.links(count) {
+            network.connect(from, to).await;
+        }
+        network
+    }
+
+    pub fn peer(&mut self, index: usize) -> &mut TestPeer {
+        &mut self.peers[index]
+    }
+
+    /// Dials `to` from `from`, again after a partition; the connection is
+    /// up by the time both see each other join a room.
+    pub async fn connect(&mut self, from: usize, to: usize) {
+        let address = self.peers[to].address.clone();
+        self.peers[from].peer.dial(address).await.unwrap();
+        if !self.neighbours(from).contains(&to) {
+            self.links.push((from, to));
+        }
+    }
+
+    /// The peers directly connected to `index`.
+    pub fn neighbours(&self, index: usize) -> Vec<usize> {
+        self.links
+            .iter()
+            .filter_map(|&(a, b)| match index {
+                _ if a == index => Some(b),
+                _ if b == index => Some(a),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Subscribes every peer to `topic` and waits until each one saw its
+    /// neighbours join it.
+    pub async fn join(&mut self, topic: &str) {
+        for test_peer in &self.peers {
+            test_peer
+                .peer
+                .subscribe_topic(topic.to_owned())
+                .await
+                .unwrap();
+        }
+        for index in 0..self.peers.len() {
+            let mut waiting: Vec<String> = self
+                .neighbours(index)
+                .into_iter()
+                .map(|neighbour| self.peers[neighbour].peer_id().to_string())
+                .collect();
+            while !waiting.is_empty() {
+                let what = format!("{waiting:?} to join {topic}");
+                let joined = self.peers[index]
+                    .expect(&what, |event| match event {
+                        PeerEvent::PeerJoined(joined)
+                            if joined.topic() == topic
+                                && waiting.contains(joined.peer_id()) =>
+                        {
+                            Some(joined.peer_id().clone())
+                        }
+                        _ => None,
+                    })
+                    .await;
+                waiting.retain(|peer_id| *peer_id != joined);
+            }
+        }
+    }
+}
+
+/// Waits up to [`TIMEOUT`] for the first event `pick` accepts, panicking
+/// with `what` when none comes.
+pub async fn expect_event<T>(
+    listener: &mut PeerEventListener,
+    what: &str,
+    mut pick: impl FnMut(PeerEvent) -> Option<T>,
+) -> T {
+    let wait = async {
+        while let Some(event) = listener.recv().await {
+            if let Some(picked) = pick(event) {
+                return picked;
+            }
+        }
+        panic!("the peer stopped while waiting for {what}");
+    };
+    match tokio::time::timeout(TIMEOUT, wait).await {
+        Ok(picked) => picked,
+        Err(_) => panic!("timed out waiting for {what}"),
+    }
+}
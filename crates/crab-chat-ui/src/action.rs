@@ -1,6 +1,14 @@
+use crab_chat_peer::{
+    NetworkInfo, OutboundStatus, Reactions, SignalKind, TransferStatus,
+};
 use serde::{Deserialize, Serialize};
 use strum::Display;
 
+use crate::{
+    app::Mode,
+    components::{home::models::ChatMessage, notifications::Severity},
+};
+
 #[derive(Debug, Clone, PartialEq, Eq, Display, Serialize, Deserialize)]
 pub enum Action {
     Tick,
@@ -11,5 +19,58 @@ pub enum Action {
     Quit,
     ClearScreen,
     Error(String),
+    /// Tells the user something worth knowing, as a toast.
+    Notify(Severity, String),
+    /// Opens or closes the history of notifications.
+    ToggleNotifications,
     Help,
+    /// Opens the help on the keybindings of a mode.
+    ShowHelp(Mode),
+    SwitchMode(Mode),
+    /// Moves the focus to the next pane of the home screen.
+    FocusNext,
+    SelectNext,
+    SelectPrevious,
+    /// Opens the selected room, sends the input or accepts a dialog.
+    Confirm,
+    /// Closes a dialog, the open thread or the reply being written.
+    Cancel,
+    JoinRoom(String),
+    /// Asks for the name of a room to join or create.
+    NewRoom,
+    /// Leaves a room, once confirmed.
+    LeaveRoom,
+    /// Pins a room to the top of the list, or unpins it.
+    TogglePin,
+    Reply,
+    OpenThread,
+    React,
+    JumpToUnread,
+    AcceptFile,
+    RejectFile,
+    /// Shows the selected message as typed, or formatted again.
+    ToggleRaw,
+    MessageReceived(String, Box<ChatMessage>),
+    /// The messages kept of a room, by room.
+    HistoryLoaded(String, Vec<ChatMessage>),
+    ThreadLoaded(String, Vec<ChatMessage>),
+    ReactionsUpdated(String, String, Reactions),
+    PresenceChanged(String, String, Option<String>, Option<SignalKind>),
+    ReadReceipt(String, String, Option<String>, String),
+    TransferUpdated(String, TransferStatus),
+    OutboundUpdated(String, String, OutboundStatus),
+    InviteCreated(String, String),
+    InviteAccepted(String),
+    /// The subscription to a room went through.
+    RoomJoined(String),
+    RoomJoinFailed(String, String),
+    /// Opens a room scrolled to one of its messages, by room and id.
+    JumpToMessage(String, String),
+    MetricsLoaded(String),
+    SetTheme(String),
+    NetworkInfoLoaded(Box<NetworkInfo>),
+    /// Asks for an address for the peer to dial.
+    Dial,
+    /// Closes the connection to the selected peer.
+    Disconnect,
 }
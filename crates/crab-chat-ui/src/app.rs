@@ -8,7 +8,10 @@ use tracing::{debug, info};
 
 use crate::{
     action::Action,
-    components::{home::Home, Component},
+    components::{
+        diagnostics::Diagnostics, help::Help, home::Home,
+        notifications::Notifications, search::Search, Component,
+    },
     config::Config,
     tui::{Event, Tui},
 };
@@ -17,7 +20,10 @@ pub struct App {
     config: Config,
     tick_rate: f64,
     frame_rate: f64,
-    components: Vec<Box<dyn Component>>,
+    /// Every component, with the mode it is shown in.
+    components: Vec<(Mode, Box<dyn Component>)>,
+    /// Drawn over every screen; one with the focus takes all the input.
+    overlays: Vec<Box<dyn Component>>,
     should_quit: bool,
     should_suspend: bool,
     mode: Mode,
@@ -32,20 +38,65 @@ pub struct App {
 pub enum Mode {
     #[default]
     Home,
+    /// The room list of the home screen has the focus.
+    Rooms,
+    /// The chat scrollback of the home screen has the focus.
+    Chat,
+    /// The message input of the home screen has the focus.
+    Input,
+    /// A popup or picker of the home screen waits for an answer.
+    Dialog,
+    Diagnostics,
+    /// The address prompt of the diagnostics screen has the focus.
+    Dial,
+    Search,
+    /// The help overlay is open.
+    Help,
+    /// The notification history is open.
+    Notifications,
+}
+
+impl Mode {
+    /// Screen whose keybindings apply when this focus has none for a key.
+    pub fn parent(self) -> Option<Mode> {
+        match self {
+            Mode::Rooms | Mode::Chat | Mode::Input | Mode::Dialog => {
+                Some(Mode::Home)
+            }
+            Mode::Home
+            | Mode::Diagnostics
+            | Mode::Dial
+            | Mode::Search
+            | Mode::Help
+            | Mode::Notifications => None,
+        }
+    }
 }
 
 impl App {
-    pub fn new(tick_rate: f64, frame_rate: f64, peer: Peer) -> Result<Self> {
-        let peer_command_bus = peer.command_bus().clone();
-        let peer_event_listener = peer.subscribe();
+    pub fn new(
+        config: Config,
+        tick_rate: f64,
+        frame_rate: f64,
+        peer: Peer,
+    ) -> Result<Self> {
         let (action_tx, action_rx) = mpsc::unbounded_channel();
+        let diagnostics = Diagnostics::new(peer.command_bus().clone());
         Ok(Self {
             tick_rate,
             frame_rate,
-            components: vec![Box::new(Home::new(peer))],
+            components: vec![
+                (Mode::Home, Box::new(Home::new(peer))),
+                (Mode::Diagnostics, Box::new(diagnostics)),
+                (Mode::Search, Box::new(Search::new())),
+            ],
+            overlays: vec![
+                Box::new(Notifications::new()),
+                Box::new(Help::new()),
+            ],
             should_quit: false,
             should_suspend: false,
-            config: Config::new()?,
+            config,
             mode: Mode::Home,
             last_tick_key_events: Vec::new(),
             action_tx,
@@ -55,18 +106,16 @@ impl App {
 
     pub async fn run(&mut self) -> Result<()> {
         let mut tui = Tui::new()?
-            // .mouse(true) // uncomment this line to enable mouse support
+            .mouse(self.config.config.mouse)
             .tick_rate(self.tick_rate)
             .frame_rate(self.frame_rate);
         tui.enter()?;
 
-        for component in self.components.iter_mut() {
-            component.register_action_handler(self.action_tx.clone())?;
-        }
-        for component in self.components.iter_mut() {
-            component.register_config_handler(self.config.clone())?;
-        }
-        for component in self.components.iter_mut() {
+        let action_tx = self.action_tx.clone();
+        let config = self.config.clone();
+        for component in self.all_components() {
+            component.register_action_handler(action_tx.clone())?;
+            component.register_config_handler(config.clone())?;
             component.init(tui.size()?)?;
         }
 
@@ -78,7 +127,6 @@ impl App {
                 tui.suspend()?;
                 action_tx.send(Action::Resume)?;
                 action_tx.send(Action::ClearScreen)?;
-                // tui.mouse(true);
                 tui.enter()?;
             } else if self.should_quit {
                 tui.stop()?;
@@ -99,10 +147,24 @@ impl App {
             Event::Tick => action_tx.send(Action::Tick)?,
             Event::Render => action_tx.send(Action::Render)?,
             Event::Resize(x, y) => action_tx.send(Action::Resize(x, y))?,
-            Event::Key(key) => self.handle_key_event(key)?,
+            // a bound key is not seen by the components
+            Event::Key(key) if self.handle_key_event(key)? => return Ok(()),
             _ => {}
         }
-        for component in self.components.iter_mut() {
+        // input goes to what is on top, the rest to everyone
+        let input = matches!(event, Event::Key(_) | Event::Mouse(_));
+        let overlay = self.overlays.iter().any(|o| o.focus().is_some());
+        let screen = self.mode;
+        let screens = self
+            .components
+            .iter_mut()
+            .filter(|(mode, _)| !input || (!overlay && *mode == screen))
+            .map(|(_, component)| component);
+        let overlays = self
+            .overlays
+            .iter_mut()
+            .filter(|overlay| !input || overlay.focus().is_some());
+        for component in screens.chain(overlays) {
             if let Some(action) =
                 component.handle_events(Some(event.clone()))?
             {
@@ -112,29 +174,73 @@ impl App {
         Ok(())
     }
 
-    fn handle_key_event(&mut self, key: KeyEvent) -> Result<()> {
+    fn all_components(
+        &mut self,
+    ) -> impl Iterator<Item = &mut Box<dyn Component>> {
+        self.components
+            .iter_mut()
+            .map(|(_, component)| component)
+            .chain(self.overlays.iter_mut())
+    }
+
+    /// The overlay on top if any, the current screen otherwise.
+    fn focused_component(&mut self) -> Option<&mut Box<dyn Component>> {
+        let mode = self.mode;
+        match self.overlays.iter().position(|o| o.focus().is_some()) {
+            Some(i) => self.overlays.get_mut(i),
+            None => self
+                .components
+                .iter_mut()
+                .find(|(m, _)| *m == mode)
+                .map(|(_, component)| component),
+        }
+    }
+
+    /// Keybinding modes that apply now, the focused one first.
+    fn keymap_modes(&self) -> Vec<Mode> {
+        let focus = self
+            .overlays
+            .iter()
+            .find_map(|overlay| overlay.focus())
+            .or_else(|| {
+                self.components
+                    .iter()
+                    .filter(|(mode, _)| *mode == self.mode)
+                    .find_map(|(_, component)| component.focus())
+            });
+        match focus {
+            Some(focus) => [focus].into_iter().chain(focus.parent()).collect(),
+            None => vec![self.mode],
+        }
+    }
+
+    /// Sends the action bound to `key`, telling whether there was one.
+    fn handle_key_event(&mut self, key: KeyEvent) -> Result<bool> {
         let action_tx = self.action_tx.clone();
-        let Some(keymap) = self.config.keybindings.get(&self.mode) else {
-            return Ok(());
+        let keymaps: Vec<_> = self
+            .keymap_modes()
+            .iter()
+            .filter_map(|mode| self.config.keybindings.get(mode))
+            .collect();
+        let lookup = |keys: &[KeyEvent]| {
+            keymaps.iter().find_map(|keymap| keymap.get(keys))
         };
-        match keymap.get(&vec![key]) {
-            Some(action) => {
-                info!("Got action: {action:?}");
-                action_tx.send(action.clone())?;
-            }
-            _ => {
-                // If the key was not handled as a single key action,
-                // then consider it for multi-key combinations.
-                self.last_tick_key_events.push(key);
-
-                // Check for multi-key combinations
-                if let Some(action) = keymap.get(&self.last_tick_key_events) {
-                    info!("Got action: {action:?}");
-                    action_tx.send(action.clone())?;
-                }
-            }
+        if let Some(action) = lookup(&[key]) {
+            info!("Got action: {action:?}");
+            action_tx.send(action.clone())?;
+            return Ok(true);
         }
-        Ok(())
+        // If the key was not handled as a single key action,
+        // then consider it for multi-key combinations.
+        self.last_tick_key_events.push(key);
+
+        // Check for multi-key combinations
+        if let Some(action) = lookup(&self.last_tick_key_events) {
+            info!("Got action: {action:?}");
+            action_tx.send(action.clone())?;
+            return Ok(true);
+        }
+        Ok(false)
     }
 
     fn handle_actions(&mut self, tui: &mut Tui) -> Result<()> {
@@ -152,11 +258,28 @@ impl App {
                 Action::ClearScreen => tui.terminal.clear()?,
                 Action::Resize(w, h) => self.handle_resize(tui, w, h)?,
                 Action::Render => self.render(tui)?,
+                Action::Help => {
+                    let context = self.keymap_modes()[0];
+                    self.action_tx.send(Action::ShowHelp(context))?;
+                }
+                // focus modes are not screens of their own
+                Action::SwitchMode(mode)
+                    if self.components.iter().any(|(m, _)| *m == mode) =>
+                {
+                    self.mode = mode;
+                    self.last_tick_key_events.clear();
+                    tui.terminal.clear()?;
+                }
                 _ => {}
             }
-            for component in self.components.iter_mut() {
+            let action_tx = self.action_tx.clone();
+            let components: Vec<_> = match is_navigation(&action) {
+                true => self.focused_component().into_iter().collect(),
+                false => self.all_components().collect(),
+            };
+            for component in components {
                 if let Some(action) = component.update(action.clone())? {
-                    self.action_tx.send(action)?
+                    action_tx.send(action)?
                 };
             }
         }
@@ -171,7 +294,11 @@ impl App {
 
     fn render(&mut self, tui: &mut Tui) -> Result<()> {
         tui.draw(|frame| {
-            for component in self.components.iter_mut() {
+            let screen = self
+                .components
+                .iter_mut()
+                .filter_map(|(mode, c)| (*mode == self.mode).then_some(c));
+            for component in screen.chain(self.overlays.iter_mut()) {
                 if let Err(err) = component.draw(frame, frame.area()) {
                     let _ = self.action_tx.send(Action::Error(format!(
                         "Failed to draw: {:?}",
@@ -183,3 +310,15 @@ impl App {
         Ok(())
     }
 }
+
+/// Whether `action` moves around what has the focus, which screens and
+/// overlays share the bindings of; only the focused one gets it.
+fn is_navigation(action: &Action) -> bool {
+    matches!(
+        action,
+        Action::SelectNext
+            | Action::SelectPrevious
+            | Action::Confirm
+            | Action::Cancel
+    )
+}
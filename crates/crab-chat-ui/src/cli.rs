@@ -1,17 +1,209 @@
-use clap::Parser;
+use std::{net::SocketAddr, path::PathBuf};
 
-use crate::config::{get_config_dir, get_data_dir};
+use clap::{Args, Parser, Subcommand};
+use color_eyre::{eyre::eyre, Result};
+use crab_chat_peer::{
+    load_or_generate_keypair, BootstrapAddress, Multiaddr, Peer, PeerConfig,
+    DEFAULT_HISTORY_LIMIT,
+};
+
+use crate::{
+    config::{get_config_dir, get_data_dir, AppConfig},
+    export::Format,
+};
+
+/// Address the peer listens on unless told otherwise.
+const DEFAULT_LISTEN_ADDR: &str = "/ip4/0.0.0.0/tcp/0";
+
+const DEFAULT_TICK_RATE: f64 = 4.0;
+const DEFAULT_FRAME_RATE: f64 = 60.0;
 
 #[derive(Parser, Debug)]
-#[command(author, version = version(), about)]
+#[command(name = "crab-chat", author, version = version(), about)]
 pub struct Cli {
+    #[command(flatten)]
+    pub network: NetworkArgs,
+
+    /// What to do, opening the chat if missing
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// How the peer joins the network, shared by every subcommand. A flag left
+/// out falls back to the config file, then to the default.
+#[derive(Args, Debug, Default)]
+pub struct NetworkArgs {
+    /// Name shown to other peers, defaults to the current user
+    #[arg(short, long, value_name = "NAME", global = true)]
+    pub nickname: Option<String>,
+
+    /// Address to listen on, every interface on a random port by default
+    #[arg(short, long, value_name = "MULTIADDR", global = true)]
+    pub listen: Option<Multiaddr>,
+
+    /// Peer to reach the network through, as <peer_id>:<multiaddr>; may be
+    /// repeated
+    #[arg(short, long, value_name = "PEER", global = true)]
+    pub bootstrap: Vec<String>,
+
+    /// Do not look for peers on the local network
+    #[arg(long, global = true)]
+    pub no_mdns: bool,
+
+    /// Serve Prometheus metrics over HTTP on this address
+    #[arg(long, value_name = "ADDR", global = true)]
+    pub metrics_addr: Option<SocketAddr>,
+}
+
+/// How the chat is drawn.
+#[derive(Args, Debug, Default)]
+pub struct UiArgs {
     /// Tick rate, i.e. number of ticks per second
-    #[arg(short, long, value_name = "FLOAT", default_value_t = 4.0)]
-    pub tick_rate: f64,
+    #[arg(short, long, value_name = "FLOAT")]
+    pub tick_rate: Option<f64>,
 
     /// Frame rate, i.e. number of frames per second
-    #[arg(short, long, value_name = "FLOAT", default_value_t = 60.0)]
-    pub frame_rate: f64,
+    #[arg(short, long, value_name = "FLOAT")]
+    pub frame_rate: Option<f64>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Opens the chat
+    Tui(UiArgs),
+    /// Runs a peer without the chat, staying in the saved rooms until
+    /// interrupted
+    Daemon {
+        /// Room to join besides the saved ones; may be repeated
+        #[arg(short, long, value_name = "ROOM")]
+        room: Vec<String>,
+    },
+    /// Sends a message and exits once it is out
+    Send {
+        /// Room to send to, or @peer_id for a direct message
+        room: String,
+
+        /// Text of the message
+        #[arg(required = true, num_args = 1..)]
+        message: Vec<String>,
+
+        /// Seconds to wait for peers to receive it
+        #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+        timeout: u64,
+    },
+    /// Prints the messages of rooms as they arrive, until interrupted
+    Listen {
+        /// Rooms to listen to, every joined one and our inbox if missing
+        rooms: Vec<String>,
+
+        /// One JSON object per message instead of a line of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Shows the peer id of this machine, creating it the first time
+    Identity,
+    /// Lists the saved rooms
+    Rooms,
+    /// Writes the history of a room
+    Export {
+        /// Room to export, or @peer_id for a direct conversation
+        room: String,
+
+        /// File to write, standard output if missing
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Format of the export, guessed from the output extension if
+        /// missing, text otherwise
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+    },
+    /// Adds the messages of a JSON Lines export to the history
+    Import {
+        /// File written by an export in the jsonl format
+        path: PathBuf,
+    },
+}
+
+impl NetworkArgs {
+    /// Starts the peer these flags and `config` describe, keeping its
+    /// identity and history in the data dir.
+    pub fn peer(&self, config: &AppConfig) -> Result<Peer> {
+        let keypair = load_or_generate_keypair(&identity_path(config))?;
+        let peer_config = PeerConfig::new(
+            self.listen_addr(config)?,
+            self.bootstrap(config)?,
+            keypair,
+        )
+        .with_nickname(self.nickname(config))
+        .with_data_dir(Some(config.data_dir.clone()))
+        .with_mdns(self.mdns(config))
+        .with_metrics_addr(self.metrics_addr.or(config.metrics_addr))
+        .with_keep_history(config.keep_history.unwrap_or(true))
+        .with_history_limit(history_limit(config));
+        Ok(Peer::new(peer_config)?)
+    }
+
+    fn listen_addr(&self, config: &AppConfig) -> Result<Multiaddr> {
+        match (&self.listen, &config.listen_addr) {
+            (Some(addr), _) => Ok(addr.clone()),
+            (None, Some(addr)) => addr
+                .parse()
+                .map_err(|e| eyre!("Invalid listen_addr {addr}: {e}")),
+            (None, None) => Ok(DEFAULT_LISTEN_ADDR.parse().unwrap()),
+        }
+    }
+
+    /// The bootstrap flags replace the configured peers rather than adding
+    /// to them.
+    fn bootstrap(&self, config: &AppConfig) -> Result<Vec<BootstrapAddress>> {
+        match self.bootstrap.is_empty() {
+            true => &config.bootstrap,
+            false => &self.bootstrap,
+        }
+        .iter()
+        .map(|peer| {
+            peer.parse::<BootstrapAddress>()
+                .map_err(|e| eyre!("Invalid bootstrap peer {peer}: {e}"))
+        })
+        .collect()
+    }
+
+    fn nickname(&self, config: &AppConfig) -> Option<String> {
+        self.nickname
+            .clone()
+            .or_else(|| config.nickname.clone())
+            .or_else(|| std::env::var("USER").ok())
+    }
+
+    fn mdns(&self, config: &AppConfig) -> bool {
+        !self.no_mdns && config.mdns.unwrap_or(true)
+    }
+}
+
+impl UiArgs {
+    /// Ticks and frames per second, the flags taking over the config file.
+    pub fn rates(&self, config: &AppConfig) -> (f64, f64) {
+        let tick_rate = self
+            .tick_rate
+            .or(config.tick_rate)
+            .unwrap_or(DEFAULT_TICK_RATE);
+        let frame_rate = self
+            .frame_rate
+            .or(config.frame_rate)
+            .unwrap_or(DEFAULT_FRAME_RATE);
+        (tick_rate, frame_rate)
+    }
+}
+
+/// How many messages of each room the history keeps.
+pub fn history_limit(config: &AppConfig) -> usize {
+    config.history_limit.unwrap_or(DEFAULT_HISTORY_LIMIT)
+}
+
+/// Where the key behind our peer id is kept.
+pub fn identity_path(config: &AppConfig) -> PathBuf {
+    config.data_dir.join("identity.key")
 }
 
 const VERSION_MESSAGE: &str = concat!(
@@ -40,3 +232,92 @@ Config directory: {config_dir_path}
 Data directory: {data_dir_path}"
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    const PEER: &str = "12D3KooWD3eckifWpRn9wQpMG9R9hX3sD158z7EqHWmweQAJU5SA";
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+
+        // the network flags go anywhere on the line
+        let cli = Cli::try_parse_from([
+            "crab-chat",
+            "send",
+            "rust",
+            "hi",
+            "there",
+            "--no-mdns",
+            "-n",
+            "al",
+        ])
+        .unwrap();
+        assert!(cli.network.no_mdns);
+        assert_eq!(cli.network.nickname.as_deref(), Some("al"));
+        assert!(matches!(cli.command, Some(Command::Send { message, .. })
+            if message == ["hi", "there"]));
+    }
+
+    #[test]
+    fn test_flags_take_over_config() {
+        let config = AppConfig {
+            listen_addr: Some("/ip4/127.0.0.1/tcp/4001".to_owned()),
+            bootstrap: vec![format!("{PEER}:/ip4/10.0.0.1/tcp/4001")],
+            mdns: Some(false),
+            tick_rate: Some(2.0),
+            ..AppConfig::default()
+        };
+        let none = NetworkArgs::default();
+        let flags = NetworkArgs {
+            listen: Some("/ip4/0.0.0.0/tcp/5001".parse().unwrap()),
+            bootstrap: vec![
+                format!("{PEER}:/ip4/10.0.0.2/tcp/4001"),
+                format!("{PEER}:/ip4/10.0.0.3/tcp/4001"),
+            ],
+            no_mdns: true,
+            ..NetworkArgs::default()
+        };
+
+        let listen = |args: &NetworkArgs, config| {
+            args.listen_addr(config).unwrap().to_string()
+        };
+        assert_eq!(listen(&flags, &config), "/ip4/0.0.0.0/tcp/5001");
+        assert_eq!(listen(&none, &config), "/ip4/127.0.0.1/tcp/4001");
+        assert_eq!(listen(&none, &AppConfig::default()), DEFAULT_LISTEN_ADDR);
+        let broken = AppConfig {
+            listen_addr: Some("nowhere".to_owned()),
+            ..AppConfig::default()
+        };
+        assert!(none.listen_addr(&broken).is_err());
+        assert!(flags.listen_addr(&broken).is_ok());
+
+        assert_eq!(flags.bootstrap(&config).unwrap().len(), 2);
+        assert_eq!(none.bootstrap(&config).unwrap().len(), 1);
+        assert!(none.bootstrap(&AppConfig::default()).unwrap().is_empty());
+        let broken = AppConfig {
+            bootstrap: vec!["nobody".to_owned()],
+            ..AppConfig::default()
+        };
+        assert!(none.bootstrap(&broken).is_err());
+
+        // mdns is on unless the flag or the config turns it off
+        assert!(none.mdns(&AppConfig::default()));
+        assert!(!none.mdns(&config));
+        assert!(!flags.mdns(&AppConfig::default()));
+
+        let ui = UiArgs {
+            tick_rate: None,
+            frame_rate: Some(30.0),
+        };
+        assert_eq!(ui.rates(&config), (2.0, 30.0));
+        assert_eq!(
+            UiArgs::default().rates(&AppConfig::default()),
+            (DEFAULT_TICK_RATE, DEFAULT_FRAME_RATE)
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::{path::Path, path::PathBuf, time::Duration};
+
+use chrono::{DateTime, Local};
+use color_eyre::{eyre::eyre, Result};
+use crab_chat_peer::{
+    is_inbox_topic, load_or_generate_keypair, Delivery, DirectMessageCommand,
+    EventFilter, EventKind, MessageReceivedEvent, MessageStore, OutboundStatus,
+    Peer, PeerEvent, SendOutcome, HISTORY_FILE,
+};
+
+use crate::{
+    cli::{history_limit, identity_path},
+    components::home::room_list::{RoomList, ROOMS_FILE},
+    config::AppConfig,
+    export::{self, Format},
+};
+
+/// Time left to the swarm to flush what it was asked to publish before the
+/// process exits.
+const LINGER: Duration = Duration::from_secs(1);
+
+/// Stays in `rooms` and the saved ones until interrupted, printing how to
+/// reach us and who comes and goes.
+pub async fn daemon(
+    peer: Peer,
+    config: &AppConfig,
+    rooms: Vec<String>,
+) -> Result<()> {
+    let mut events = peer.subscribe();
+    for room in joined_rooms(config, rooms) {
+        peer.subscribe_topic(room.clone()).await?;
+        println!("Joined {room}");
+    }
+    // the listeners are set up by the time the first command is answered
+    for addr in peer.network_info().await?.listen_addrs() {
+        println!("Listening as {}:{addr}", peer.peer_id());
+    }
+    loop {
+        let event = tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            event = events.recv() => event,
+        };
+        match event {
+            Some(PeerEvent::Connection(event)) => println!(
+                "{} {}, {} connected",
+                event.peer_id(),
+                match event.connected() {
+                    true => "connected",
+                    false => "disconnected",
+                },
+                event.connected_peers()
+            ),
+            // everyone is in its own inbox
+            Some(PeerEvent::PeerJoined(event))
+                if !is_inbox_topic(event.topic()) =>
+            {
+                println!("{} joined {}", event.peer_id(), event.topic())
+            }
+            Some(PeerEvent::PeerLeft(event))
+                if !is_inbox_topic(event.topic()) =>
+            {
+                println!("{} left {}", event.peer_id(), event.topic())
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// Sends `text` to `room`, or directly to a peer for an `@peer_id` room,
+/// waiting at most `timeout` for it to go out.
+pub async fn send(
+    peer: Peer,
+    room: String,
+    text: String,
+    timeout: Duration,
+) -> Result<()> {
+    let mut events = peer.subscribe();
+    if let Some(peer_id) = room.strip_prefix('@') {
+        // a direct message goes to a mailbox when nobody is reachable, give
+        // the peer a chance to connect first
+        let connected = async {
+            while let Some(event) = events.recv().await {
+                if matches!(event, PeerEvent::Connection(e) if *e.connected()) {
+                    break;
+                }
+            }
+        };
+        let _ = tokio::time::timeout(timeout, connected).await;
+        let command = DirectMessageCommand::builder()
+            .peer_id(peer_id.to_owned())
+            .message(text)
+            .build();
+        let (id, delivery) = peer.command_bus().send(command).await?;
+        match delivery {
+            Delivery::Direct => println!("Sent {id}"),
+            Delivery::Mailbox => println!("Left {id} in the mailbox of {room}"),
+        }
+        tokio::time::sleep(LINGER).await;
+        return Ok(());
+    }
+
+    peer.subscribe_topic(room.clone()).await?;
+    let queued = match peer.send_message(text, room).await? {
+        SendOutcome::Sent(id) => {
+            println!("Sent {id}");
+            tokio::time::sleep(LINGER).await;
+            return Ok(());
+        }
+        SendOutcome::Queued(id) => id,
+    };
+    let outcome = async {
+        while let Some(event) = events.recv().await {
+            let PeerEvent::Outbound(event) = event else {
+                continue;
+            };
+            if *event.id() != queued {
+                continue;
+            }
+            match event.status() {
+                OutboundStatus::Queued => {}
+                OutboundStatus::Sent { message_id } => {
+                    return Ok(message_id.clone())
+                }
+                OutboundStatus::Failed { error } => return Err(error.clone()),
+            }
+        }
+        Err("the peer stopped".to_owned())
+    };
+    match tokio::time::timeout(timeout, outcome).await {
+        Ok(Ok(id)) => {
+            println!("Sent {id}");
+            tokio::time::sleep(LINGER).await;
+            Ok(())
+        }
+        Ok(Err(error)) => Err(eyre!("Not sent: {error}")),
+        Err(_) => Err(eyre!("Not sent: nobody joined within {timeout:?}")),
+    }
+}
+
+/// Prints the messages of `rooms` as they arrive, those of every saved
+/// room and direct conversation without any.
+pub async fn listen(
+    peer: Peer,
+    config: &AppConfig,
+    rooms: Vec<String>,
+    json: bool,
+) -> Result<()> {
+    let filter = EventFilter::builder()
+        .kinds(vec![EventKind::MessageReceived])
+        .topics(rooms.clone())
+        .build();
+    let mut messages = peer.subscribe_with(filter, false).messages();
+    let rooms = match rooms.is_empty() {
+        true => joined_rooms(config, vec![]),
+        false => rooms,
+    };
+    for room in rooms.into_iter().filter(|room| !is_inbox_topic(room)) {
+        peer.subscribe_topic(room).await?;
+    }
+    loop {
+        let message = tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            message = futures::StreamExt::next(&mut messages) => message,
+        };
+        let Some(message) = message else {
+            break;
+        };
+        match json {
+            true => println!("{}", message_json(&message)),
+            false => println!("{}", message_line(&message)),
+        }
+    }
+    Ok(())
+}
+
+/// Prints our peer id, and where its key is to stderr.
+pub fn identity(config: &AppConfig) -> Result<()> {
+    let path = identity_path(config);
+    let keypair = load_or_generate_keypair(&path)?;
+    println!("{}", keypair.public().to_peer_id());
+    eprintln!("Key kept in {}", path.display());
+    Ok(())
+}
+
+/// Prints the saved rooms in the order of the rooms pane, a `*` marking the
+/// pinned ones and our name for a room following it.
+pub fn rooms(config: &AppConfig) {
+    let list = RoomList::load(config.data_dir.join(ROOMS_FILE));
+    for entry in list.entries() {
+        let pin = if entry.pinned { '*' } else { ' ' };
+        match &entry.alias {
+            Some(alias) => println!("{pin} {} ({alias})", entry.name),
+            None => println!("{pin} {}", entry.name),
+        }
+    }
+}
+
+pub fn export(
+    config: &AppConfig,
+    room: &str,
+    output: Option<PathBuf>,
+    format: Option<Format>,
+) -> Result<()> {
+    let format = format
+        .or_else(|| output.as_deref().and_then(Format::from_path))
+        .unwrap_or(Format::Text);
+    let path = config.data_dir.join(HISTORY_FILE);
+    let store = MessageStore::open(&path, history_limit(config));
+    let history = store.history(room, None);
+    let text = export::render(room, &history, format);
+    match output {
+        Some(path) => {
+            std::fs::write(&path, text)?;
+            eprintln!(
+                "Exported {} messages to {}",
+                history.len(),
+                path.display()
+            );
+        }
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+pub fn import(config: &AppConfig, path: &Path) -> Result<()> {
+    let text = std::fs::read_to_string(path)?;
+    let messages = export::parse_jsonl(&text)
+        .map_err(|e| eyre!("{}: {e}", path.display()))?;
+    let path = config.data_dir.join(HISTORY_FILE);
+    let mut store = MessageStore::open(&path, history_limit(config));
+    let imported = store.import(messages);
+    eprintln!("Imported {imported} new messages");
+    Ok(())
+}
+
+/// The saved rooms followed by `extra`, without our direct conversations
+/// which need no joining.
+fn joined_rooms(config: &AppConfig, extra: Vec<String>) -> Vec<String> {
+    let list = RoomList::load(config.data_dir.join(ROOMS_FILE));
+    let mut rooms: Vec<String> = list
+        .entries()
+        .iter()
+        .map(|entry| entry.name.clone())
+        .filter(|room| !is_inbox_topic(room))
+        .collect();
+    for room in extra {
+        if !rooms.contains(&room) {
+            rooms.push(room);
+        }
+    }
+    rooms
+}
+
+fn message_line(message: &MessageReceivedEvent) -> String {
+    let time = DateTime::from_timestamp(*message.timestamp() as i64, 0)
+        .map(|time| time.with_timezone(&Local).format("%Y-%m-%d %H:%M"))
+        .map(|time| time.to_string())
+        .unwrap_or_default();
+    let author = message.nickname().as_ref().unwrap_or(message.peer_id());
+    format!(
+        "[{time}] {} <{author}> {}",
+        message.topic(),
+        message.message().replace('\n', "\n    ")
+    )
+}
+
+fn message_json(message: &MessageReceivedEvent) -> serde_json::Value {
+    serde_json::json!({
+        "message_id": message.message_id(),
+        "topic": message.topic(),
+        "peer_id": message.peer_id(),
+        "nickname": message.nickname(),
+        "timestamp": message.timestamp(),
+        "parent_id": message.parent_id(),
+        "data": message.message(),
+    })
+}
@@ -5,9 +5,14 @@ use ratatui::{
     Frame,
 };
 use tokio::sync::mpsc::UnboundedSender;
-use crate::{action::Action, config::Config, tui::Event};
+use crate::{action::Action, app::Mode, config::Config, tui::Event};
 
+mod common;
+pub mod diagnostics;
+pub mod help;
 pub mod home;
+pub mod notifications;
+pub mod search;
 
 /// `Component` is a trait that represents a visual and interactive element of the user interface.
 ///
@@ -107,6 +112,15 @@ pub trait Component {
         let _ = mouse; // to appease clippy
         Ok(None)
     }
+    /// Tell which part of the component has the focus, if its keybindings
+    /// differ from those of the app mode.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Mode>` - A mode whose keybindings take precedence or none.
+    fn focus(&self) -> Option<Mode> {
+        None
+    }
     /// Update the state of the component based on a received action. (REQUIRED)
     ///
     /// # Arguments
@@ -0,0 +1,23 @@
+//! Helpers shared by the screens.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::action::Action;
+
+/// Logs `error` and shows it to the user.
+pub fn send_error(action_tx: Option<UnboundedSender<Action>>, error: String) {
+    tracing::error!("{error}");
+    if let Some(tx) = action_tx {
+        let _ = tx.send(Action::Error(error));
+    }
+}
+
+/// Seconds since the unix epoch, the clock peers stamp messages with.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
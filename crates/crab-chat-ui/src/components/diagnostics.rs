@@ -0,0 +1,372 @@
+use std::time::{Duration, Instant};
+
+use color_eyre::Result;
+use crab_chat_peer::{
+    DialCommand, DisconnectCommand, Multiaddr, NetworkInfo, NetworkInfoCommand,
+    PeerCommandBus,
+};
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{prelude::*, widgets::*};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{
+    common::{now, send_error},
+    Component,
+};
+use crate::{action::Action, app::Mode, config::Config, theme::Theme};
+
+/// How often the network info is asked for while on screen.
+const REFRESH: Duration = Duration::from_secs(1);
+
+/// Where the peer stands on the network: its addresses, the peers it is
+/// connected to and how fast they answer, the mesh of every room and the
+/// dials that failed.
+pub struct Diagnostics {
+    command_bus: PeerCommandBus,
+    command_tx: Option<UnboundedSender<Action>>,
+    active: bool,
+    info: Option<NetworkInfo>,
+    last_refresh: Option<Instant>,
+    peers_state: TableState,
+    /// Address being typed, while the dial prompt is open.
+    dial: Option<String>,
+    config: Config,
+    theme: Theme,
+}
+
+impl Diagnostics {
+    pub fn new(command_bus: PeerCommandBus) -> Self {
+        Self {
+            command_bus,
+            command_tx: None,
+            active: false,
+            info: None,
+            last_refresh: None,
+            peers_state: TableState::default(),
+            dial: None,
+            config: Config::default(),
+            theme: Theme::default(),
+        }
+    }
+
+    fn block<'a>(&self, title: impl Into<Line<'a>>) -> Block<'a> {
+        Block::bordered()
+            .border_style(self.theme.border)
+            .style(self.theme.background)
+            .title(title)
+            .title_style(self.theme.title)
+    }
+
+    fn refresh(&mut self) {
+        if self
+            .last_refresh
+            .is_some_and(|last| last.elapsed() < REFRESH)
+        {
+            return;
+        }
+        self.last_refresh = Some(Instant::now());
+        let command = NetworkInfoCommand::builder().build();
+        let command_bus = self.command_bus.clone();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            match command_bus.send(command).await {
+                Ok(info) => {
+                    if let Some(tx) = action_tx {
+                        let _ =
+                            tx.send(Action::NetworkInfoLoaded(Box::new(info)));
+                    }
+                }
+                Err(e) => send_error(
+                    action_tx,
+                    format!("Failed to load network info: {e}"),
+                ),
+            }
+        });
+    }
+
+    fn dial(&mut self) {
+        let Some(address) = self.dial.take() else {
+            return;
+        };
+        let address = match address.trim().parse::<Multiaddr>() {
+            Ok(address) => address,
+            Err(e) => {
+                send_error(
+                    self.command_tx.clone(),
+                    format!("Invalid address {address}: {e}"),
+                );
+                return;
+            }
+        };
+        let command = DialCommand::builder().address(address).build();
+        let command_bus = self.command_bus.clone();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = command_bus.send(command).await {
+                send_error(action_tx, format!("Failed to dial: {e}"));
+            }
+        });
+        self.last_refresh = None;
+    }
+
+    fn disconnect(&mut self) {
+        let Some(peer) = self.selected_peer() else {
+            return;
+        };
+        let command = DisconnectCommand::builder().peer_id(peer).build();
+        let command_bus = self.command_bus.clone();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = command_bus.send(command).await {
+                send_error(action_tx, format!("Failed to disconnect: {e}"));
+            }
+        });
+        self.last_refresh = None;
+    }
+
+    fn selected_peer(&self) -> Option<String> {
+        let peers = self.info.as_ref()?.peers();
+        let peer = peers.get(self.peers_state.selected()?)?;
+        Some(peer.peer_id().clone())
+    }
+
+    fn peer_count(&self) -> usize {
+        self.info.as_ref().map_or(0, |info| info.peers().len())
+    }
+
+    fn navigate(&mut self, up: bool) {
+        let count = self.peer_count();
+        if count == 0 {
+            return;
+        }
+        let selected = match (self.peers_state.selected(), up) {
+            (Some(i), true) => i.saturating_sub(1),
+            (Some(i), false) => (i + 1).min(count - 1),
+            (None, _) => 0,
+        };
+        self.peers_state.select(Some(selected));
+    }
+
+    fn handle_dial_key(&mut self, key: KeyEvent) {
+        let Some(address) = self.dial.as_mut() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Backspace => {
+                address.pop();
+            }
+            KeyCode::Char(c) => address.push(c),
+            _ => {}
+        }
+    }
+
+    fn info_loaded(&mut self, info: NetworkInfo) {
+        self.info = Some(info);
+        let count = self.peer_count();
+        match self.peers_state.selected() {
+            _ if count == 0 => self.peers_state.select(None),
+            Some(i) if i >= count => self.peers_state.select(Some(count - 1)),
+            None => self.peers_state.select(Some(0)),
+            Some(_) => {}
+        }
+    }
+
+    fn draw_overview(&self, frame: &mut Frame, area: Rect, info: &NetworkInfo) {
+        let mut lines = vec![
+            Line::from(vec![
+                "Peer id: ".bold(),
+                info.peer_id().as_str().into(),
+            ]),
+            Line::from(vec![
+                "Routing table: ".bold(),
+                format!("{} peers", info.routing_table_size()).into(),
+            ]),
+            Line::from("Listening on:".bold()),
+        ];
+        lines.extend(
+            info.listen_addrs()
+                .iter()
+                .map(|address| Line::from(format!("  {address}"))),
+        );
+        let block = self.block("Network");
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn draw_peers(&mut self, frame: &mut Frame, area: Rect) {
+        let Some(info) = &self.info else {
+            return;
+        };
+        let rows = info.peers().iter().map(|peer| {
+            let rtt = peer
+                .rtt()
+                .map(|rtt| format!("{} ms", rtt.as_millis()))
+                .unwrap_or_else(|| "-".to_owned());
+            Row::new(vec![
+                peer.peer_id().clone(),
+                peer.transport().clone(),
+                rtt,
+                peer.address().clone(),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(54),
+                Constraint::Length(10),
+                Constraint::Length(8),
+                Constraint::Min(1),
+            ],
+        )
+        .header(Row::new(["peer", "transport", "rtt", "address"]).bold())
+        .row_highlight_style(self.theme.highlight)
+        .block(self.block(format!("Connected peers ({})", info.peers().len())));
+        frame.render_stateful_widget(table, area, &mut self.peers_state);
+    }
+
+    fn draw_mesh(&self, frame: &mut Frame, area: Rect, info: &NetworkInfo) {
+        let lines: Vec<Line> = info
+            .mesh()
+            .iter()
+            .flat_map(|mesh| {
+                let title = Line::from(vec![
+                    mesh.topic().as_str().bold(),
+                    format!(" ({} peers)", mesh.peers().len()).into(),
+                ]);
+                let peers = mesh
+                    .peers()
+                    .iter()
+                    .map(|peer| Line::from(format!("  {peer}")));
+                std::iter::once(title).chain(peers)
+            })
+            .collect();
+        let block = self.block("Mesh");
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn draw_dial_errors(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        info: &NetworkInfo,
+    ) {
+        let now = now();
+        let lines: Vec<Line> = info
+            .dial_errors()
+            .iter()
+            .rev()
+            .map(|failure| {
+                let peer = failure.peer_id().as_deref().unwrap_or("unknown");
+                let age = now.saturating_sub(*failure.timestamp());
+                Line::from(vec![
+                    Span::styled(format!("{age}s ago "), self.theme.timestamp),
+                    peer.bold(),
+                    format!(": {}", failure.error()).into(),
+                ])
+            })
+            .collect();
+        let block = self.block("Dial errors");
+        frame.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .block(block),
+            area,
+        );
+    }
+
+    fn draw_footer(&self, frame: &mut Frame, area: Rect) {
+        let line = match &self.dial {
+            Some(address) => Line::from(vec![
+                "Dial: ".bold(),
+                address.as_str().into(),
+                "█".slow_blink(),
+            ]),
+            None => Line::from(
+                "↑/↓ select peer · x disconnect · d dial an address · Esc back",
+            )
+            .dim(),
+        };
+        frame.render_widget(Paragraph::new(line).block(self.block("")), area);
+    }
+}
+
+impl Component for Diagnostics {
+    fn register_action_handler(
+        &mut self,
+        tx: UnboundedSender<Action>,
+    ) -> Result<()> {
+        self.command_tx = Some(tx);
+        Ok(())
+    }
+
+    fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.theme = Theme::configured(&config, Mode::Diagnostics);
+        self.config = config;
+        Ok(())
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) -> Result<Option<Action>> {
+        self.handle_dial_key(key);
+        Ok(None)
+    }
+
+    fn focus(&self) -> Option<Mode> {
+        self.dial.as_ref().map(|_| Mode::Dial)
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        match action {
+            Action::SwitchMode(mode) => {
+                self.active = mode == Mode::Diagnostics;
+                self.last_refresh = None;
+                self.dial = None;
+                if self.active {
+                    self.refresh();
+                }
+            }
+            Action::Tick if self.active => self.refresh(),
+            Action::Dial if self.active => self.dial = Some(String::new()),
+            Action::Disconnect if self.active => self.disconnect(),
+            Action::Confirm if self.dial.is_some() => self.dial(),
+            Action::Cancel if self.dial.is_some() => self.dial = None,
+            Action::Cancel => return Ok(Some(Action::SwitchMode(Mode::Home))),
+            Action::SelectNext => self.navigate(false),
+            Action::SelectPrevious => self.navigate(true),
+            Action::NetworkInfoLoaded(info) => self.info_loaded(*info),
+            Action::SetTheme(name) => {
+                if let Some(theme) =
+                    Theme::load(&self.config, &name, Mode::Diagnostics)
+                {
+                    self.theme = theme;
+                }
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rect) -> Result<()> {
+        let [overview, peers, bottom, footer] = Layout::vertical([
+            Constraint::Length(6),
+            Constraint::Percentage(50),
+            Constraint::Min(1),
+            Constraint::Length(3),
+        ])
+        .areas(area);
+        let [mesh, dial_errors] =
+            Layout::horizontal([Constraint::Percentage(50); 2]).areas(bottom);
+        self.draw_footer(frame, footer);
+        let Some(info) = self.info.clone() else {
+            let block = self.block("Network");
+            frame.render_widget(
+                Paragraph::new("Loading…").block(block),
+                overview,
+            );
+            return Ok(());
+        };
+        self.draw_overview(frame, overview, &info);
+        self.draw_peers(frame, peers);
+        self.draw_mesh(frame, mesh, &info);
+        self.draw_dial_errors(frame, dial_errors, &info);
+        Ok(())
+    }
+}
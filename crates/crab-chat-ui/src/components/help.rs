@@ -0,0 +1,277 @@
+use color_eyre::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{layout::Flex, prelude::*, widgets::*};
+
+use super::{home::commands::COMMANDS, Component};
+use crate::{
+    action::Action,
+    app::Mode,
+    config::{key_sequence_to_string, Config, KeyBindings},
+    theme::Theme,
+};
+
+/// Every keybinding mode, in the order they are listed after the focused one.
+const MODES: [Mode; 10] = [
+    Mode::Home,
+    Mode::Rooms,
+    Mode::Chat,
+    Mode::Input,
+    Mode::Dialog,
+    Mode::Diagnostics,
+    Mode::Dial,
+    Mode::Search,
+    Mode::Help,
+    Mode::Notifications,
+];
+
+/// Overlay listing what every key does, and the slash commands, for the
+/// keybindings actually in use.
+pub struct Help {
+    config: Config,
+    theme: Theme,
+    /// Keybinding mode the help was opened from, while it is open.
+    context: Option<Mode>,
+    search: String,
+    scroll: usize,
+}
+
+#[derive(Debug, PartialEq)]
+enum Entry {
+    Group(String),
+    /// Keys, or a command, and what they do.
+    Item(String, String),
+}
+
+impl Help {
+    pub fn new() -> Self {
+        Self {
+            config: Config::default(),
+            theme: Theme::default(),
+            context: None,
+            search: String::new(),
+            scroll: 0,
+        }
+    }
+
+    fn close(&mut self) {
+        self.context = None;
+        self.search.clear();
+        self.scroll = 0;
+    }
+
+    fn draw_entries(&self, entries: &[Entry]) -> Vec<Line<'static>> {
+        entries
+            .iter()
+            .map(|entry| match entry {
+                Entry::Group(title) => {
+                    Line::styled(title.clone(), self.theme.title)
+                }
+                Entry::Item(keys, description) => Line::from(vec![
+                    Span::raw(format!("  {keys:<28}")).bold(),
+                    Span::raw(description.clone()),
+                ]),
+            })
+            .collect()
+    }
+}
+
+/// What the help lists from `context`, the focused bindings first, keeping
+/// the entries matching `search`.
+fn entries(
+    keybindings: &KeyBindings,
+    context: Mode,
+    search: &str,
+) -> Vec<Entry> {
+    let mut modes = vec![context];
+    modes.extend(context.parent());
+    let focused = modes.len();
+    let rest: Vec<_> = MODES
+        .into_iter()
+        .filter(|mode| !modes.contains(mode))
+        .collect();
+    modes.extend(rest);
+
+    let mut groups: Vec<(String, Vec<(String, String)>)> = modes
+        .iter()
+        .enumerate()
+        .map(|(i, mode)| {
+            let title = match i < focused {
+                true => format!("{mode:?} (here)"),
+                false => format!("{mode:?}"),
+            };
+            let mut items: Vec<_> = keybindings
+                .get(mode)
+                .into_iter()
+                .flatten()
+                .map(|(keys, action)| {
+                    (key_sequence_to_string(keys), format!("{action:?}"))
+                })
+                .collect();
+            items.sort();
+            (title, items)
+        })
+        .collect();
+    let commands = COMMANDS
+        .iter()
+        .map(|(usage, what)| (usage.to_string(), what.to_string()))
+        .collect();
+    groups.insert(focused, ("Commands".to_owned(), commands));
+
+    let search = search.to_lowercase();
+    let matches = |text: &str| text.to_lowercase().contains(&search);
+    let mut entries = vec![];
+    for (title, items) in groups {
+        let items: Vec<_> = match matches(&title) {
+            true => items,
+            false => items
+                .into_iter()
+                .filter(|(keys, what)| matches(keys) || matches(what))
+                .collect(),
+        };
+        if items.is_empty() {
+            continue;
+        }
+        entries.push(Entry::Group(title));
+        entries.extend(
+            items
+                .into_iter()
+                .map(|(keys, what)| Entry::Item(keys, what)),
+        );
+    }
+    entries
+}
+
+impl Component for Help {
+    fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.theme = Theme::configured(&config, Mode::Home);
+        self.config = config;
+        Ok(())
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) -> Result<Option<Action>> {
+        match (key.code, key.modifiers) {
+            (KeyCode::Esc, _) => self.close(),
+            (KeyCode::Up, _) => self.scroll = self.scroll.saturating_sub(1),
+            (KeyCode::Down, _) => self.scroll += 1,
+            (KeyCode::PageUp, _) => {
+                self.scroll = self.scroll.saturating_sub(10)
+            }
+            (KeyCode::PageDown, _) => self.scroll += 10,
+            (KeyCode::Backspace, _) => {
+                self.search.pop();
+                self.scroll = 0;
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                self.search.push(c);
+                self.scroll = 0;
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn focus(&self) -> Option<Mode> {
+        self.context.map(|_| Mode::Help)
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        match action {
+            Action::ShowHelp(context) => self.context = Some(context),
+            Action::SetTheme(name) => {
+                if let Some(theme) =
+                    Theme::load(&self.config, &name, Mode::Home)
+                {
+                    self.theme = theme;
+                }
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rect) -> Result<()> {
+        let Some(context) = self.context else {
+            return Ok(());
+        };
+        let [area] = Layout::horizontal([Constraint::Percentage(80)])
+            .flex(Flex::Center)
+            .areas(area);
+        let [area] = Layout::vertical([Constraint::Percentage(80)])
+            .flex(Flex::Center)
+            .areas(area);
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.focused_border)
+            .style(self.theme.background)
+            .title("Help")
+            .title_alignment(Alignment::Center)
+            .title_style(self.theme.title)
+            .title_bottom("type to search · ↑/↓ to scroll · Esc to close");
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+
+        let [search, list] =
+            Layout::vertical([Constraint::Length(2), Constraint::Min(1)])
+                .areas(inner);
+        let prompt = Line::from(vec![
+            "Search: ".bold(),
+            self.search.as_str().into(),
+            "█".slow_blink(),
+        ]);
+        frame.render_widget(Paragraph::new(prompt), search);
+
+        let entries = entries(&self.config.keybindings, context, &self.search);
+        let lines = self.draw_entries(&entries);
+        let max_scroll = lines.len().saturating_sub(list.height as usize);
+        self.scroll = self.scroll.min(max_scroll);
+        let text = match lines.is_empty() {
+            true => vec![Line::from("Nothing matches").italic()],
+            false => lines,
+        };
+        frame.render_widget(
+            Paragraph::new(text).scroll((self.scroll as u16, 0)),
+            list,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_key_sequence;
+
+    #[test]
+    fn test_entries() {
+        let mut keybindings = KeyBindings::default();
+        let key = |raw| parse_key_sequence(raw).unwrap();
+        keybindings.insert(
+            Mode::Home,
+            [(key("<Ctrl-c>"), Action::Quit)].into_iter().collect(),
+        );
+        keybindings.insert(
+            Mode::Chat,
+            [(key("<r>"), Action::Reply), (key("<q>"), Action::Quit)]
+                .into_iter()
+                .collect(),
+        );
+
+        let all = entries(&keybindings, Mode::Chat, "");
+        assert_eq!(all[0], Entry::Group("Chat (here)".to_owned()));
+        assert_eq!(all[1], Entry::Item("<q>".to_owned(), "Quit".to_owned()));
+        assert_eq!(all[3], Entry::Group("Home (here)".to_owned()));
+        assert_eq!(all[5], Entry::Group("Commands".to_owned()));
+
+        let found = entries(&keybindings, Mode::Rooms, "REPLY");
+        assert_eq!(
+            found,
+            vec![
+                Entry::Group("Chat".to_owned()),
+                Entry::Item("<r>".to_owned(), "Reply".to_owned()),
+            ]
+        );
+        let found = entries(&keybindings, Mode::Rooms, "/invite");
+        assert_eq!(found.len(), 2);
+    }
+}
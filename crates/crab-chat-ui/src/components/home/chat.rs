@@ -1,11 +1,24 @@
+use std::path::Path;
+
 use ratatui::{
     buffer::Buffer,
     layout::{Alignment, Rect},
-    style::{Color, Style, Stylize},
-    widgets::{Block, BorderType, Borders, StatefulWidget, Widget},
+    style::{Style, Stylize},
+    text::{Line, Span, Text},
+    widgets::{
+        Block, BorderType, Borders, Clear, List, ListItem, Paragraph,
+        StatefulWidget, Widget,
+    },
 };
 
-use super::{Home, Mode};
+use super::{
+    emoji::{glyph, EMOJIS},
+    markdown,
+    models::{format_size, format_time, Chat, ChatMessage, FileState},
+    Home, Mode,
+};
+use crate::theme::Theme;
+use crab_chat_peer::{OutboundStatus, TransferStatus};
 
 pub struct ChatWidget;
 
@@ -13,18 +26,234 @@ impl StatefulWidget for ChatWidget {
     type State = Home;
 
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
-        let border = match state.mode {
-            Mode::Chat => BorderType::Thick,
-            _ => BorderType::Plain,
+        let focused = matches!(state.mode, Mode::Chat);
+        let border = match focused {
+            true => BorderType::Thick,
+            false => BorderType::Plain,
         };
+        let theme = &state.theme;
 
-        Block::default()
+        let title = state
+            .actual_room
+            .as_ref()
+            .map(|room| state.room_title(room))
+            .unwrap_or_else(|| "Chat".to_owned());
+        let block = Block::default()
             .borders(Borders::ALL)
             .border_type(border)
-            .bg(Color::Black)
-            .title(state.actual_room.as_deref().unwrap_or("Chat"))
+            .border_style(theme.border_style(focused))
+            .style(theme.background)
+            .title(title)
             .title_alignment(Alignment::Center)
-            .title_style(Style::default().fg(Color::Green))
-            .render(area, buf);
+            .title_style(theme.title);
+        let Some(room) = state
+            .actual_room
+            .as_ref()
+            .and_then(|room| state.rooms.get_mut(room))
+        else {
+            Widget::render(block, area, buf);
+            return;
+        };
+
+        let me = state.peer.peer_id().to_string();
+        let raw_messages = state.config.config.raw_messages;
+        let items = room
+            .chat
+            .messages
+            .iter()
+            .map(|message| {
+                let mut lines = vec![];
+                if room.first_unread.as_ref() == Some(&message.id) {
+                    lines.push(
+                        Line::from("── new messages ──")
+                            .style(theme.system_notice),
+                    );
+                }
+                let raw = raw_messages || room.chat.raw.contains(&message.id);
+                lines.extend(message_lines(
+                    &room.chat, message, &me, raw, theme,
+                ));
+                if let Some(offer) = &message.file {
+                    lines.push(file_line(
+                        state.files.get(offer.file_id()),
+                        message.author == me,
+                    ));
+                }
+                let seen_by = room.seen_by(&message.id);
+                if !seen_by.is_empty() {
+                    lines.push(
+                        Line::from(format!(
+                            "  ✓ seen by {}",
+                            seen_by.join(", ")
+                        ))
+                        .dark_gray(),
+                    );
+                }
+                let item = ListItem::new(Text::from(lines));
+                match message.author != me && message.mentions(&me) {
+                    true => item.style(theme.mention),
+                    false => item,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let inner = block.inner(area);
+        StatefulWidget::render(
+            List::new(items)
+                .highlight_style(theme.highlight)
+                .block(block),
+            area,
+            buf,
+            &mut room.chat.state,
+        );
+
+        if let Some(selected) = state.picker {
+            render_picker(selected, inner, buf, theme);
+        }
+    }
+}
+
+/// One-line emoji bar pinned to the bottom of the chat pane.
+fn render_picker(selected: usize, area: Rect, buf: &mut Buffer, theme: &Theme) {
+    let area = Rect {
+        y: area.bottom().saturating_sub(1),
+        height: area.height.min(1),
+        ..area
+    };
+    let spans = EMOJIS
+        .iter()
+        .enumerate()
+        .flat_map(|(i, (_, glyph))| {
+            let style = match i == selected {
+                true => theme.highlight,
+                false => Style::new(),
+            };
+            [Span::styled(format!(" {glyph} "), style), Span::raw(" ")]
+        })
+        .collect::<Vec<_>>();
+
+    Clear.render(area, buf);
+    Paragraph::new(Line::from(spans))
+        .style(theme.background)
+        .render(area, buf);
+}
+
+fn message_lines<'a>(
+    chat: &Chat,
+    message: &'a ChatMessage,
+    me: &str,
+    raw: bool,
+    theme: &Theme,
+) -> Vec<Line<'a>> {
+    let mut lines = vec![];
+    if let Some(parent_id) = &message.parent_id {
+        lines.push(quote_line(chat.find(parent_id)));
     }
+    let mut text = match &message.file {
+        Some(offer) => vec![Line::from(Span::styled(
+            format!("📎 {} ({})", offer.name(), format_size(*offer.size())),
+            Style::new().cyan(),
+        ))],
+        None if raw => markdown::raw(&message.text),
+        None => markdown::render(&message.text, theme),
+    }
+    .into_iter();
+    let author = match message.author == me {
+        true => theme.own_message,
+        false => theme.author,
+    };
+    let mut spans = vec![
+        Span::styled(
+            format!("{} ", format_time(message.timestamp)),
+            theme.timestamp,
+        ),
+        Span::styled(message.display_name(), author),
+        Span::raw(": "),
+    ];
+    // the first line goes after the author, the others below
+    spans.extend(text.next().map(|line| line.spans).unwrap_or_default());
+    match &message.outbound {
+        Some(OutboundStatus::Queued) => {
+            spans.push(Span::raw("  ⏳ waiting for peers").dark_gray().italic())
+        }
+        Some(OutboundStatus::Failed { error }) => spans
+            .push(Span::raw(format!("  ✗ not sent: {error}")).red().italic()),
+        _ => {}
+    }
+    lines.push(Line::from(spans));
+    lines.extend(text.map(|line| {
+        let mut spans = vec![Span::raw("  ")];
+        spans.extend(line.spans);
+        Line::from(spans)
+    }));
+    if !message.reactions.is_empty() {
+        lines.push(reactions_line(message, me));
+    }
+    lines
+}
+
+/// What happens to an offered file, with the keys to act on it.
+fn file_line(state: Option<&FileState>, own: bool) -> Line<'static> {
+    let (text, style) = match state {
+        _ if own => ("  shared with the room".to_owned(), Style::new()),
+        None => ("  [a] accept  [x] reject".to_owned(), Style::new()),
+        Some(FileState::Rejected) => ("  rejected".to_owned(), Style::new()),
+        Some(FileState::Downloading(TransferStatus::Progress {
+            transferred,
+            total,
+        })) => (
+            format!(
+                "  downloading {} / {}",
+                format_size(*transferred),
+                format_size(*total)
+            ),
+            Style::new().yellow(),
+        ),
+        Some(FileState::Downloading(TransferStatus::Completed { path })) => (
+            format!(
+                "  saved to {}",
+                path.as_deref().unwrap_or(Path::new("?")).display()
+            ),
+            Style::new().green(),
+        ),
+        Some(FileState::Downloading(TransferStatus::Failed { error })) => (
+            format!("  failed: {error}, [a] to resume"),
+            Style::new().red(),
+        ),
+    };
+    Line::from(Span::styled(text, style.italic()))
+}
+
+/// Reaction counters shown beneath a message; ours are highlighted.
+fn reactions_line(message: &ChatMessage, me: &str) -> Line<'static> {
+    let mut spans = vec![Span::raw("  ")];
+    for (emoji, reactors) in &message.reactions {
+        let style = match reactors.iter().any(|r| r == me) {
+            true => Style::new().yellow().bold(),
+            false => Style::new().gray(),
+        };
+        spans.push(Span::styled(
+            format!("{} {}", glyph(emoji), reactors.len()),
+            style,
+        ));
+        spans.push(Span::raw("  "));
+    }
+    Line::from(spans)
+}
+
+/// Preview of the message being replied to, shown above the reply.
+pub(super) fn quote_line(parent: Option<&ChatMessage>) -> Line<'static> {
+    let preview = match parent {
+        Some(parent) => {
+            let text = parent.text.chars().take(40).collect::<String>();
+            let ellipsis = if parent.text.chars().count() > 40 {
+                "…"
+            } else {
+                ""
+            };
+            format!("↪ {}: {text}{ellipsis}", parent.display_name())
+        }
+        None => "↪ (message not available)".to_owned(),
+    };
+    Line::from(Span::styled(preview, Style::new().dark_gray().italic()))
 }
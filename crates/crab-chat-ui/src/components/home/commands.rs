@@ -0,0 +1,166 @@
+use std::path::PathBuf;
+
+/// Usage and purpose of every command, as listed in the help.
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("/send <path>", "Offers a file to the room"),
+    (
+        "/msg <nickname> [message]",
+        "Opens a direct conversation with a peer",
+    ),
+    ("/invite", "Issues an invite token to the current room"),
+    ("/accept <token>", "Joins the room of an invite"),
+    ("/metrics", "Shows how the mesh is doing"),
+    ("/theme [name]", "Switches to a theme, or lists them"),
+    (
+        "/join <room>",
+        "Joins a room, creating it if nobody is in it yet",
+    ),
+    ("/leave", "Leaves the room, after asking"),
+    (
+        "/rename [name]",
+        "Names the room for us only, or takes its name back",
+    ),
+    ("/pin", "Pins the room to the top of the list, or unpins it"),
+    (
+        "/export <path>",
+        "Saves the room's history as .txt, .jsonl or .html",
+    ),
+    ("/import <path>", "Adds the messages of a .jsonl export"),
+];
+
+/// A line typed into the input box starting with `/`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SlashCommand {
+    Send(PathBuf),
+    /// Opens a direct conversation with a peer, by nickname or peer id,
+    /// optionally sending a first message.
+    Message(String, String),
+    /// Issues an invite token to the current room.
+    Invite,
+    Accept(String),
+    /// Shows how the mesh is doing.
+    Metrics,
+    /// Switches to the named theme, or lists them without a name.
+    Theme(Option<String>),
+    Join(String),
+    /// Leaves the current room, once confirmed.
+    Leave,
+    /// Names the current room locally, or takes its own name back.
+    Rename(Option<String>),
+    /// Pins or unpins the current room.
+    Pin,
+    /// Saves the history of the current room, in the format the file
+    /// extension names.
+    Export(PathBuf),
+    /// Adds the messages of a JSON Lines export to the local history.
+    Import(PathBuf),
+}
+
+impl SlashCommand {
+    /// Parses `input` if it is a command; the error describes a malformed
+    /// or unknown one.
+    pub fn parse(input: &str) -> Option<Result<Self, String>> {
+        let input = input.trim().strip_prefix('/')?;
+        let (name, args) = match input.split_once(char::is_whitespace) {
+            Some((name, args)) => (name, args.trim()),
+            None => (input, ""),
+        };
+        let command = match (name, args) {
+            ("send", "") => Err("Usage: /send <path>".to_owned()),
+            ("send", path) => Ok(Self::Send(expand_home(path))),
+            ("invite", "") => Ok(Self::Invite),
+            ("metrics", "") => Ok(Self::Metrics),
+            ("theme", "") => Ok(Self::Theme(None)),
+            ("theme", name) => Ok(Self::Theme(Some(name.to_owned()))),
+            ("join", "") => Err("Usage: /join <room>".to_owned()),
+            ("join", room) if room.starts_with('@') => {
+                Err("Use /msg to talk to a peer directly".to_owned())
+            }
+            ("join", room) => Ok(Self::Join(room.to_owned())),
+            ("leave", "") => Ok(Self::Leave),
+            ("rename", "") => Ok(Self::Rename(None)),
+            ("rename", name) => Ok(Self::Rename(Some(name.to_owned()))),
+            ("pin", "") => Ok(Self::Pin),
+            ("export", "") => Err("Usage: /export <path>".to_owned()),
+            ("export", path) => Ok(Self::Export(expand_home(path))),
+            ("import", "") => Err("Usage: /import <path>".to_owned()),
+            ("import", path) => Ok(Self::Import(expand_home(path))),
+            ("accept", "") => Err("Usage: /accept <token>".to_owned()),
+            ("accept", token) => Ok(Self::Accept(token.to_owned())),
+            ("msg", "") => Err("Usage: /msg <nickname> [message]".to_owned()),
+            ("msg", args) => {
+                let (peer, text) = match args.split_once(char::is_whitespace) {
+                    Some((peer, text)) => (peer, text.trim()),
+                    None => (args, ""),
+                };
+                let peer = peer.strip_prefix('@').unwrap_or(peer);
+                Ok(Self::Message(peer.to_owned(), text.to_owned()))
+            }
+            _ => Err(format!("Unknown command: /{name}")),
+        };
+        Some(command)
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(SlashCommand::parse("hello"), None);
+        assert_eq!(
+            SlashCommand::parse("/send  notes.txt "),
+            Some(Ok(SlashCommand::Send("notes.txt".into())))
+        );
+        assert!(matches!(SlashCommand::parse("/send"), Some(Err(_))));
+        assert_eq!(
+            SlashCommand::parse("/msg @bob see you  later"),
+            Some(Ok(SlashCommand::Message(
+                "bob".to_owned(),
+                "see you  later".to_owned()
+            )))
+        );
+        assert!(matches!(SlashCommand::parse("/msg"), Some(Err(_))));
+        assert_eq!(
+            SlashCommand::parse("/accept crab-chat://invite/abc"),
+            Some(Ok(SlashCommand::Accept(
+                "crab-chat://invite/abc".to_owned()
+            )))
+        );
+        assert_eq!(
+            SlashCommand::parse("/metrics"),
+            Some(Ok(SlashCommand::Metrics))
+        );
+        assert_eq!(
+            SlashCommand::parse("/theme high-contrast"),
+            Some(Ok(SlashCommand::Theme(Some("high-contrast".to_owned()))))
+        );
+        assert_eq!(
+            SlashCommand::parse("/join rust"),
+            Some(Ok(SlashCommand::Join("rust".to_owned())))
+        );
+        assert!(matches!(SlashCommand::parse("/join @bob"), Some(Err(_))));
+        assert_eq!(
+            SlashCommand::parse("/rename  the crabs "),
+            Some(Ok(SlashCommand::Rename(Some("the crabs".to_owned()))))
+        );
+        assert_eq!(
+            SlashCommand::parse("/rename"),
+            Some(Ok(SlashCommand::Rename(None)))
+        );
+        assert_eq!(
+            SlashCommand::parse("/export rust.html"),
+            Some(Ok(SlashCommand::Export("rust.html".into())))
+        );
+        assert!(matches!(SlashCommand::parse("/import"), Some(Err(_))));
+        assert!(matches!(SlashCommand::parse("/nope x"), Some(Err(_))));
+    }
+}
@@ -0,0 +1,22 @@
+/// Shortcodes offered by the reaction picker with the glyph they render as.
+pub const EMOJIS: &[(&str, &str)] = &[
+    ("thumbsup", "👍"),
+    ("thumbsdown", "👎"),
+    ("heart", "❤️"),
+    ("joy", "😂"),
+    ("tada", "🎉"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("rocket", "🚀"),
+    ("thinking", "🤔"),
+    ("crab", "🦀"),
+];
+
+/// Glyph for a known shortcode, or the `:shortcode:` itself otherwise.
+pub fn glyph(shortcode: &str) -> String {
+    EMOJIS
+        .iter()
+        .find(|(code, _)| *code == shortcode)
+        .map(|(_, glyph)| glyph.to_string())
+        .unwrap_or_else(|| format!(":{shortcode}:"))
+}
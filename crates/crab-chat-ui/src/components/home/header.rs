@@ -1,11 +1,13 @@
 use ratatui::{
     buffer::Buffer,
     layout::{Alignment, Rect},
-    style::{Color, Stylize},
+    style::Style,
     widgets::{Block, Paragraph, Widget},
 };
 
-pub struct HeaderWidget;
+pub struct HeaderWidget {
+    pub style: Style,
+}
 
 impl Widget for HeaderWidget {
     fn render(self, area: Rect, buf: &mut Buffer)
@@ -14,10 +16,8 @@ impl Widget for HeaderWidget {
     {
         Paragraph::new("Crab Chat")
             .alignment(Alignment::Center)
-            .bold()
             .block(Block::bordered())
-            .black()
-            .bg(Color::Green)
+            .style(self.style)
             .render(area, buf);
     }
 }
@@ -0,0 +1,42 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    widgets::{Block, BorderType, Borders, Paragraph, StatefulWidget, Widget},
+};
+
+use super::{Home, Mode};
+
+pub struct InputWidget;
+
+impl StatefulWidget for InputWidget {
+    type State = Home;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        let focused = matches!(state.mode, Mode::Input);
+        let border = match focused {
+            true => BorderType::Thick,
+            false => BorderType::Plain,
+        };
+
+        let chat = state.chat();
+        let title = match chat.and_then(|c| c.reply_to.as_ref()) {
+            Some(id) => match chat.and_then(|c| c.find(id)) {
+                Some(parent) => format!("Reply to {}", parent.display_name()),
+                None => "Reply".to_owned(),
+            },
+            None => "Message".to_owned(),
+        };
+
+        Paragraph::new(chat.map(|c| c.input.as_str()).unwrap_or_default())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(border)
+                    .border_style(state.theme.border_style(focused))
+                    .style(state.theme.background)
+                    .title(title)
+                    .title_style(state.theme.title),
+            )
+            .render(area, buf);
+    }
+}
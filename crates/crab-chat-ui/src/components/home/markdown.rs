@@ -0,0 +1,288 @@
+use ratatui::{
+    style::{Modifier, Style},
+    text::{Line, Span},
+};
+
+use crate::theme::Theme;
+
+/// Words highlighted in code blocks, common to the usual languages.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "class", "const", "continue", "def",
+    "else", "enum", "except", "false", "fn", "for", "from", "func", "function",
+    "if", "impl", "import", "in", "let", "loop", "match", "mod", "mut", "new",
+    "None", "null", "pub", "return", "self", "Self", "static", "struct",
+    "trait", "true", "True", "False", "try", "type", "use", "var", "where",
+    "while",
+];
+
+/// Characters trailing a bare link that rather end the sentence.
+const LINK_TRAILERS: &[char] = &['.', ',', ')', '!', '?', ';', ':'];
+
+/// Renders `text` with the subset of markdown messages may use: `**bold**`,
+/// `*italic*` or `_italic_`, `` `code` ``, fenced code blocks, `[links](url)`,
+/// bare links and `>` quotes. Anything else is shown as typed.
+pub fn render(text: &str, theme: &Theme) -> Vec<Line<'static>> {
+    let text = sanitize(text);
+    let mut lines = vec![];
+    // the language of a code block is not looked at, the highlighting
+    // suits most of them
+    let mut in_code = false;
+    for line in text.lines() {
+        let fence = line.trim_start().starts_with("```");
+        match (in_code, fence) {
+            (_, true) => in_code = !in_code,
+            (true, false) => lines.push(highlight(line, theme)),
+            (false, false) => match line.strip_prefix('>') {
+                Some(quoted) => {
+                    let quoted = quoted.strip_prefix(' ').unwrap_or(quoted);
+                    let style = theme.quote.add_modifier(Modifier::ITALIC);
+                    let mut spans = vec![Span::styled("│ ", theme.quote)];
+                    spans.extend(inline(quoted, style, theme));
+                    lines.push(Line::from(spans));
+                }
+                None => lines.push(Line::from(inline(
+                    line,
+                    Style::default(),
+                    theme,
+                ))),
+            },
+        }
+    }
+    lines
+}
+
+/// `text` as typed, line by line.
+pub fn raw(text: &str) -> Vec<Line<'static>> {
+    sanitize(text)
+        .lines()
+        .map(|line| Line::raw(line.to_owned()))
+        .collect()
+}
+
+/// Drops the control characters a peer could use to mess with the terminal.
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || *c == '\n')
+        .collect()
+}
+
+fn inline(text: &str, base: Style, theme: &Theme) -> Vec<Span<'static>> {
+    let mut spans = vec![];
+    let mut plain = String::new();
+    let mut prev = None;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        match token(rest, prev, base, theme) {
+            Some((token, len)) => {
+                if !plain.is_empty() {
+                    spans.push(Span::styled(std::mem::take(&mut plain), base));
+                }
+                spans.extend(token);
+                prev = rest[..len].chars().last();
+                rest = &rest[len..];
+            }
+            None => {
+                plain.push(c);
+                prev = Some(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if !plain.is_empty() {
+        spans.push(Span::styled(plain, base));
+    }
+    spans
+}
+
+/// The formatted span `rest` starts with, if any, and its length in the
+/// text; `prev` is the character before it.
+fn token(
+    rest: &str,
+    prev: Option<char>,
+    base: Style,
+    theme: &Theme,
+) -> Option<(Vec<Span<'static>>, usize)> {
+    let in_word = prev.is_some_and(char::is_alphanumeric);
+    if let Some(after) = rest.strip_prefix('`') {
+        let end = after.find('`').filter(|end| *end > 0)?;
+        let code =
+            Span::styled(after[..end].to_owned(), base.patch(theme.code));
+        return Some((vec![code], end + 2));
+    }
+    if let Some(after) = rest.strip_prefix("**") {
+        let end = after.find("**")?;
+        let inner = emphasized(&after[..end])?;
+        let bold = base.add_modifier(Modifier::BOLD);
+        return Some((inline(inner, bold, theme), end + 4));
+    }
+    let marker = rest.chars().next()?;
+    if marker == '*' || (marker == '_' && !in_word) {
+        let after = &rest[1..];
+        let end = after.find(marker)?;
+        let inner = emphasized(&after[..end])?;
+        // snake_case words are no emphasis
+        let next = after[end + 1..].chars().next();
+        if marker == '_' && next.is_some_and(char::is_alphanumeric) {
+            return None;
+        }
+        let italic = base.add_modifier(Modifier::ITALIC);
+        return Some((inline(inner, italic, theme), end + 2));
+    }
+    if marker == '[' {
+        let close = rest.find("](")?;
+        let label = &rest[1..close];
+        let url_len = rest[close + 2..].find(')')?;
+        let url = &rest[close + 2..close + 2 + url_len];
+        if label.is_empty() || url.is_empty() || url.contains(' ') {
+            return None;
+        }
+        let spans = vec![
+            Span::styled(label.to_owned(), base.patch(theme.link)),
+            Span::styled(format!(" ({url})"), base.patch(theme.timestamp)),
+        ];
+        return Some((spans, close + 3 + url_len));
+    }
+    if !in_word && (rest.starts_with("https://") || rest.starts_with("http://"))
+    {
+        let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let url = rest[..len].trim_end_matches(LINK_TRAILERS);
+        let link = Span::styled(url.to_owned(), base.patch(theme.link));
+        return Some((vec![link], url.len()));
+    }
+    None
+}
+
+/// The text between emphasis markers, unless it is empty or padded, as in
+/// `2 * 3 * 4`.
+fn emphasized(inner: &str) -> Option<&str> {
+    let padded = inner.starts_with(char::is_whitespace)
+        || inner.ends_with(char::is_whitespace);
+    (!inner.is_empty() && !padded).then_some(inner)
+}
+
+/// A line of a code block, with keywords, strings and comments picked out.
+fn highlight(line: &str, theme: &Theme) -> Line<'static> {
+    let mut spans = vec![Span::raw("  ")];
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let (style, len) = if rest.starts_with("//") || c == '#' {
+            (theme.code_comment, rest.len())
+        } else if c == '"' {
+            let end = string_end(&rest[1..]).map_or(rest.len(), |end| end + 2);
+            (theme.code_string, end)
+        } else if c.is_alphanumeric() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            match KEYWORDS.contains(&&rest[..len]) {
+                true => (theme.code_keyword, len),
+                false => (theme.code, len),
+            }
+        } else {
+            (theme.code, c.len_utf8())
+        };
+        spans.push(Span::styled(rest[..len].to_owned(), style));
+        rest = &rest[len..];
+    }
+    Line::from(spans)
+}
+
+/// Index of the quote closing a string, skipping escaped ones.
+fn string_end(text: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some(i),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Stylize;
+
+    use super::*;
+
+    fn theme() -> Theme {
+        Theme {
+            code: Style::new().yellow(),
+            code_keyword: Style::new().magenta(),
+            code_string: Style::new().green(),
+            code_comment: Style::new().gray(),
+            link: Style::new().cyan(),
+            quote: Style::new().blue(),
+            ..Theme::default()
+        }
+    }
+
+    fn spans(line: &Line) -> Vec<(String, Style)> {
+        line.spans
+            .iter()
+            .map(|span| (span.content.to_string(), span.style))
+            .collect()
+    }
+
+    #[test]
+    fn test_inline() {
+        let theme = theme();
+        let lines = render(
+            "**bold _and italic_** `x * y` snake_case 2 * 3 * 4 \x1b[2J",
+            &theme,
+        );
+        let bold = Style::new().bold();
+        assert_eq!(
+            spans(&lines[0]),
+            vec![
+                ("bold ".to_owned(), bold),
+                ("and italic".to_owned(), bold.italic()),
+                (" ".to_owned(), Style::new()),
+                ("x * y".to_owned(), theme.code),
+                (" snake_case 2 * 3 * 4 [2J".to_owned(), Style::new()),
+            ]
+        );
+
+        let lines = render("see [docs](https://a.b/c) or https://x.y.", &theme);
+        assert_eq!(
+            spans(&lines[0]),
+            vec![
+                ("see ".to_owned(), Style::new()),
+                ("docs".to_owned(), theme.link),
+                (" (https://a.b/c)".to_owned(), theme.timestamp),
+                (" or ".to_owned(), Style::new()),
+                ("https://x.y".to_owned(), theme.link),
+                (".".to_owned(), Style::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        let theme = theme();
+        let lines = render(
+            "> quoted *text*\n```rust\nlet s = \"a\\\"b\"; // done\n```\n**",
+            &theme,
+        );
+        assert_eq!(lines.len(), 3);
+        let quote = theme.quote.italic();
+        assert_eq!(
+            spans(&lines[0]),
+            vec![
+                ("│ ".to_owned(), theme.quote),
+                ("quoted ".to_owned(), quote),
+                ("text".to_owned(), quote.italic()),
+            ]
+        );
+        let code = spans(&lines[1]);
+        assert_eq!(code[1], ("let".to_owned(), theme.code_keyword));
+        assert!(code.contains(&("\"a\\\"b\"".to_owned(), theme.code_string)));
+        assert_eq!(
+            code.last().unwrap(),
+            &("// done".to_owned(), theme.code_comment)
+        );
+        assert_eq!(spans(&lines[2]), vec![("**".to_owned(), Style::new())]);
+        assert_eq!(raw("**a**\nb").len(), 2);
+    }
+}
@@ -1,39 +1,260 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 use chat::ChatWidget;
 use color_eyre::Result;
+use commands::SlashCommand;
 use crab_chat_peer::{
-    Peer, PeerEventListener, SendMessageCommand, SubscribeCommand,
-    UnsubscribeCommand,
+    is_inbox_topic, AcceptInviteCommand, CreateInviteCommand, Delivery,
+    DirectMessageCommand, DownloadFileCommand, HistoryCommand, ImportCommand,
+    MetricsSnapshot, MetricsSnapshotCommand, OfferFileCommand, OutboundStatus,
+    Peer, PeerCommandBus, PeerEvent, PeerEventListener, ReactCommand,
+    Reactions, ReceiptCommand, SendMessageCommand, SendOutcome, SignalCommand,
+    SignalKind, SubscribeCommand, ThreadCommand, TransferDirection,
+    TransferStatus, UnsubscribeCommand,
+};
+use crossterm::event::{
+    KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
 };
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use header::HeaderWidget;
-use models::Room;
+use input::InputWidget;
+use notice::NoticeWidget;
+use participants::ParticipantsWidget;
+use models::{
+    display_name, resolve_mentions, ChatMessage, FileState, PeerPresence,
+    ReadMarker, Room, Thread,
+};
 use ratatui::{prelude::*, widgets::*};
+use room_list::{RoomList, Subscription, ROOMS_FILE};
 use rooms::RoomsWidget;
-use tokio::{runtime::Handle, sync::mpsc::UnboundedSender};
-use super::Component;
-use crate::{action::Action, config::Config};
+use status::StatusWidget;
+use thread::ThreadWidget;
+use tokio::sync::mpsc::UnboundedSender;
+use super::{
+    common::{now, send_error},
+    notifications::Severity,
+    Component,
+};
+use crate::{
+    action::Action,
+    app,
+    config::Config,
+    export::{self, Format},
+    theme::Theme,
+    tui::Event,
+};
 
 mod chat;
+pub mod commands;
+mod emoji;
 mod header;
-mod models;
+mod input;
+mod markdown;
+pub mod models;
+mod notice;
+mod participants;
+pub mod room_list;
 mod rooms;
+mod status;
+mod thread;
+
+/// Messages moved by one turn of the mouse wheel.
+const SCROLL_LINES: u16 = 3;
+
+/// Inactivity after which we announce ourselves as idle.
+const IDLE_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// How often an idle or away state is announced again so it does not expire
+/// on the other peers.
+const PRESENCE_REFRESH: Duration = Duration::from_secs(5 * 60);
 
 async fn handle_peer_events(
     mut event_listener: PeerEventListener,
     command_tx: UnboundedSender<Action>,
 ) {
-    loop {
-        match event_listener.recv().await {
-            Ok(x) => tracing::info!("event: {:?}", x),
-            Err(_) => todo!(),
+    while let Some(event) = event_listener.recv().await {
+        match event {
+            PeerEvent::MessageReceived(event) => {
+                let action = Action::MessageReceived(
+                    event.topic().clone(),
+                    Box::new(ChatMessage::from(&event)),
+                );
+                if command_tx.send(action).is_err() {
+                    break;
+                }
+            }
+            PeerEvent::PresenceChanged(event) => {
+                let action = Action::PresenceChanged(
+                    event.topic().clone(),
+                    event.peer_id().clone(),
+                    event.nickname().clone(),
+                    *event.state(),
+                );
+                if command_tx.send(action).is_err() {
+                    break;
+                }
+            }
+            PeerEvent::FileOffered(event) => {
+                let action = Action::MessageReceived(
+                    event.topic().clone(),
+                    Box::new(ChatMessage::from(&event)),
+                );
+                if command_tx.send(action).is_err() {
+                    break;
+                }
+            }
+            PeerEvent::FileTransfer(event)
+                if *event.direction() == TransferDirection::Download =>
+            {
+                let action = Action::TransferUpdated(
+                    event.file_id().clone(),
+                    event.status().clone(),
+                );
+                if command_tx.send(action).is_err() {
+                    break;
+                }
+            }
+            PeerEvent::ReadReceipt(event) => {
+                let action = Action::ReadReceipt(
+                    event.topic().clone(),
+                    event.peer_id().clone(),
+                    event.nickname().clone(),
+                    event.message_id().clone(),
+                );
+                if command_tx.send(action).is_err() {
+                    break;
+                }
+            }
+            PeerEvent::Outbound(event) => {
+                if let OutboundStatus::Failed { error } = event.status() {
+                    let text = format!(
+                        "A message to {} was not sent: {error}",
+                        event.topic()
+                    );
+                    let _ = command_tx
+                        .send(Action::Notify(Severity::Warning, text));
+                }
+                let action = Action::OutboundUpdated(
+                    event.topic().clone(),
+                    event.id().clone(),
+                    event.status().clone(),
+                );
+                if command_tx.send(action).is_err() {
+                    break;
+                }
+            }
+            PeerEvent::ReactionsChanged(event) => {
+                let action = Action::ReactionsUpdated(
+                    event.topic().clone(),
+                    event.message_id().clone(),
+                    event.reactions().clone(),
+                );
+                if command_tx.send(action).is_err() {
+                    break;
+                }
+            }
+            PeerEvent::PeerJoined(event) if !is_inbox_topic(event.topic()) => {
+                let text = format!(
+                    "{} joined {}",
+                    display_name(event.peer_id(), None),
+                    event.topic()
+                );
+                if command_tx
+                    .send(Action::Notify(Severity::Info, text))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            PeerEvent::PeerLeft(event) if !is_inbox_topic(event.topic()) => {
+                let text = format!(
+                    "{} left {}",
+                    display_name(event.peer_id(), None),
+                    event.topic()
+                );
+                if command_tx
+                    .send(Action::Notify(Severity::Info, text))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            PeerEvent::Connection(event) => {
+                let notification = match event.connected() {
+                    true if *event.connected_peers() == 1 => Some((
+                        Severity::Info,
+                        "Connected to the network".to_owned(),
+                    )),
+                    false if *event.connected_peers() == 0 => Some((
+                        Severity::Warning,
+                        "Connection lost, no peers left".to_owned(),
+                    )),
+                    _ => None,
+                };
+                if let Some((severity, text)) = notification {
+                    if command_tx.send(Action::Notify(severity, text)).is_err()
+                    {
+                        break;
+                    }
+                }
+            }
+            PeerEvent::Lagged(event) => {
+                tracing::warn!("missed {} peer events", event.missed());
+                let text = format!(
+                    "Missed {} peer events, catching up",
+                    event.missed()
+                );
+                if command_tx
+                    .send(Action::Notify(Severity::Warning, text))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            x => tracing::info!("event: {:?}", x),
         }
     }
 }
 
+/// The mesh at a glance, one line per room.
+fn metrics_text(snapshot: &MetricsSnapshot) -> String {
+    let mut text = format!(
+        "Connected peers: {}\nRouting table: {} peers\n\n",
+        snapshot.connected_peers(),
+        snapshot.routing_table_size()
+    );
+    text.push_str(&format!(
+        "{:<20} {:>6} {:>6} {:>9} {:>9}\n",
+        "room", "mesh", "sent", "received", "rejected"
+    ));
+    for topic in snapshot.topics() {
+        text.push_str(&format!(
+            "{:<20} {:>6} {:>6} {:>9} {:>9}\n",
+            topic.topic(),
+            topic.mesh_peers(),
+            topic.published(),
+            topic.received(),
+            topic.rejected()
+        ));
+    }
+    text
+}
+
+/// Where the panes were last drawn, to find what a click lands on.
+#[derive(Clone, Copy, Default)]
+struct Areas {
+    rooms: Rect,
+    chat: Rect,
+    input: Rect,
+    participants: Rect,
+}
+
 #[derive(Default)]
 pub enum Mode {
     Chat,
+    Input,
     #[default]
     Rooms,
 }
@@ -43,9 +264,28 @@ pub struct Home {
     command_tx: Option<UnboundedSender<Action>>,
     config: Config,
     rooms: HashMap<String, Room>,
+    /// Order, names and subscriptions of the rooms, kept between runs.
+    room_list: RoomList,
     actual_room: Option<String>,
     mode: Mode,
     rooms_state: ListState,
+    thread: Option<Thread>,
+    /// Selected entry of the reaction picker, when it is open.
+    picker: Option<usize>,
+    last_activity: Instant,
+    /// Idle or away state we announced and when, if any.
+    announced: Option<(SignalKind, Instant)>,
+    suspended: bool,
+    /// Offered files we accepted or rejected, by file id.
+    files: HashMap<String, FileState>,
+    /// Title and text of the popup on screen, if any.
+    notice: Option<(String, String)>,
+    /// Name of the room being typed, while the join prompt is open.
+    join_prompt: Option<String>,
+    /// Room waiting for us to confirm we leave it.
+    leaving: Option<String>,
+    theme: Theme,
+    areas: Areas,
 }
 
 impl Home {
@@ -54,53 +294,974 @@ impl Home {
             command_tx: None,
             config: Config::default(),
             rooms: HashMap::new(),
+            room_list: RoomList::default(),
             actual_room: None,
             mode: Default::default(),
             rooms_state: ListState::default(),
+            thread: None,
+            picker: None,
+            last_activity: Instant::now(),
+            announced: None,
+            suspended: false,
+            files: HashMap::new(),
+            notice: None,
+            join_prompt: None,
+            leaving: None,
+            theme: Theme::default(),
+            areas: Areas::default(),
             peer,
         }
     }
 
-    fn enter_room(&self, room: String) {
-        let f = self.peer.command_bus().clone();
-        let room = room.clone();
+    fn enter_room(&mut self, room: String) {
+        self.add_room(&room);
+        if self.room_list.get(&room).is_some_and(|entry| {
+            !matches!(entry.subscription, Subscription::Joined)
+        }) {
+            self.subscribe(room.clone());
+        }
+        self.actual_room = Some(room.clone());
+        self.thread = None;
+        self.rooms_state.select(self.room_list.position(&room));
+    }
+
+    /// Puts `room` in the list, if it is not there yet, along with the
+    /// messages we kept of it.
+    fn add_room(&mut self, room: &str) {
+        self.room_list.add(room);
+        if !self.rooms.contains_key(room) {
+            self.rooms
+                .insert(room.to_owned(), Room::new(room.to_owned()));
+            self.load_history(room.to_owned());
+        }
+    }
+
+    fn load_history(&self, room: String) {
+        let command_bus = self.peer.command_bus().clone();
+        tokio::spawn(load_history(command_bus, self.command_tx.clone(), room));
+    }
+
+    /// Merges the stored messages of an open room into its chat.
+    fn history_loaded(&mut self, room: &str, messages: Vec<ChatMessage>) {
+        if let Some(room) = self.rooms.get_mut(room) {
+            room.chat.merge(messages);
+        }
+    }
 
-        let x = Handle::current().block_on(async move {
-            f.send(SubscribeCommand::builder().topic(room).build())
-                .await
+    fn subscribe(&mut self, room: String) {
+        self.room_list
+            .set_subscription(&room, Subscription::Joining);
+        let command_bus = self.peer.command_bus().clone();
+        let command = SubscribeCommand::builder().topic(room.clone()).build();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            match command_bus.send(command).await {
+                Ok(_) => {
+                    if let Some(tx) = &action_tx {
+                        let _ = tx.send(Action::RoomJoined(room));
+                    }
+                }
+                Err(e) => {
+                    let error = e.to_string();
+                    if let Some(tx) = &action_tx {
+                        let _ = tx
+                            .send(Action::RoomJoinFailed(room, error.clone()));
+                    }
+                    send_error(
+                        action_tx,
+                        format!("Failed to join room: {error}"),
+                    );
+                }
+            }
         });
     }
 
-    async fn leave_room(&self, room: &str) {
+    /// Brings back the rooms of the last run, subscribing to them again.
+    fn restore_rooms(&mut self) {
+        let names: Vec<_> = self
+            .room_list
+            .entries()
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect();
+        for room in names {
+            self.add_room(&room);
+            if !is_inbox_topic(&room) {
+                self.subscribe(room);
+            }
+        }
+        if self.room_list.len() > 0 {
+            self.rooms_state.select(Some(0));
+        }
+    }
+
+    /// Room the room operations apply to: the selected one in the rooms
+    /// pane, the open one elsewhere.
+    fn target_room(&self) -> Option<String> {
+        match self.mode {
+            Mode::Rooms => self.selected_room(),
+            _ => self.actual_room.clone(),
+        }
+    }
+
+    fn selected_room(&self) -> Option<String> {
+        let i = self.rooms_state.selected()?;
+        Some(self.room_list.entries().get(i)?.name.clone())
+    }
+
+    /// Name the room goes by, ours if we gave it one.
+    fn room_title(&self, room: &str) -> String {
+        let alias = self.room_list.get(room).and_then(|e| e.alias.clone());
+        alias
+            .or_else(|| self.rooms.get(room).map(|r| r.title()))
+            .unwrap_or_else(|| room.to_owned())
+    }
+
+    fn toggle_pin(&mut self, room: &str) {
+        let selected = self.selected_room();
+        self.room_list.toggle_pin(room);
+        // the selection stays on the same room
+        if let Some(selected) = selected {
+            self.rooms_state.select(self.room_list.position(&selected));
+        }
+    }
+
+    fn rename_room(&mut self, room: &str, alias: Option<String>) {
+        self.room_list.rename(room, alias);
+    }
+
+    /// Leaves the room we confirmed leaving.
+    fn confirm_leave(&mut self) {
+        let Some(room) = self.leaving.take() else {
+            return;
+        };
+        if !is_inbox_topic(&room) {
+            self.leave_room(&room);
+        }
+        self.rooms.remove(&room);
+        self.room_list.remove(&room);
+        if self.actual_room.as_ref() == Some(&room) {
+            self.actual_room = None;
+            self.thread = None;
+        }
+        let last = self.room_list.len().checked_sub(1);
+        let selected = self.rooms_state.selected().zip(last);
+        self.rooms_state
+            .select(selected.map(|(i, last)| i.min(last)));
+    }
+
+    fn join_prompt_key(&mut self, key: KeyEvent) {
+        let Some(name) = self.join_prompt.as_mut() else {
+            return;
+        };
+        match (key.code, key.modifiers) {
+            (KeyCode::Backspace, _) => {
+                name.pop();
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                name.push(c)
+            }
+            _ => {}
+        }
+    }
+
+    fn leave_room(&self, room: &str) {
         let command_bus = self.peer.command_bus().clone();
-        let room = room.to_owned();
-        let result = Handle::current().block_on(async move {
-            command_bus
-                .send(
-                    UnsubscribeCommand::builder()
-                        .topic(room.to_string())
-                        .build(),
-                )
-                .await
+        let command =
+            UnsubscribeCommand::builder().topic(room.to_owned()).build();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = command_bus.send(command).await {
+                send_error(action_tx, format!("Failed to leave room: {e}"));
+            }
         });
     }
 
-    async fn send_message(&self, room: &str, message: &str) {
+    fn send_message(&mut self) {
+        let Some(room) = self.actual_room.clone() else {
+            return;
+        };
+        let Some(chat) = self.rooms.get_mut(&room).map(|r| &mut r.chat) else {
+            return;
+        };
+        if chat.input.trim().is_empty() {
+            return;
+        }
+
+        let text = std::mem::take(&mut chat.input);
+        if let Some(command) = SlashCommand::parse(&text) {
+            self.signal(room.clone(), SignalKind::StoppedTyping);
+            match command {
+                Ok(command) => self.run_command(room, command),
+                Err(e) => send_error(self.command_tx.clone(), e),
+            }
+            return;
+        }
+        if is_inbox_topic(&room) {
+            // direct messages carry no threads
+            chat.reply_to = None;
+            self.send_direct(room, text);
+            return;
+        }
+        let parent_id = chat.reply_to.take();
+        let mentions = self
+            .rooms
+            .get(&room)
+            .map(|r| resolve_mentions(&text, &r.known_peers()))
+            .unwrap_or_default();
+        self.signal(room.clone(), SignalKind::StoppedTyping);
+        let command = SendMessageCommand::builder()
+            .topic(room.clone())
+            .message(text.clone())
+            .maybe_parent_id(parent_id.clone())
+            .mentions(mentions.clone())
+            .build();
         let command_bus = self.peer.command_bus().clone();
-        let room = room.to_owned();
-        let message = message.to_owned();
-        let result = Handle::current().block_on(async move {
-            command_bus
-                .send(
-                    SendMessageCommand::builder()
-                        .topic(room)
-                        .message(message)
-                        .build(),
-                )
-                .await
+        let author = self.peer.peer_id().to_string();
+        let nickname = self.peer.nickname().map(str::to_owned);
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            match command_bus.send(command).await {
+                Ok(outcome) => {
+                    let (id, outbound) = match outcome {
+                        SendOutcome::Sent(id) => (id.to_string(), None),
+                        SendOutcome::Queued(id) => {
+                            (id, Some(OutboundStatus::Queued))
+                        }
+                    };
+                    let message = ChatMessage {
+                        id,
+                        author,
+                        nickname,
+                        text,
+                        timestamp: now(),
+                        parent_id,
+                        mentions,
+                        reactions: Reactions::new(),
+                        file: None,
+                        outbound,
+                    };
+                    if let Some(tx) = action_tx {
+                        let action =
+                            Action::MessageReceived(room, Box::new(message));
+                        let _ = tx.send(action);
+                    }
+                }
+                Err(e) => send_error(action_tx, format!("Failed to send: {e}")),
+            }
         });
     }
 
+    /// Tracks a queued message of ours until it is sent or given up on.
+    fn outbound_updated(
+        &mut self,
+        room: &str,
+        id: &str,
+        status: OutboundStatus,
+    ) {
+        let Some(message) =
+            self.rooms.get_mut(room).and_then(|r| r.chat.find_mut(id))
+        else {
+            return;
+        };
+        match status {
+            OutboundStatus::Sent { message_id } => {
+                message.id = message_id;
+                message.outbound = None;
+            }
+            status => message.outbound = Some(status),
+        }
+    }
+
+    /// Sends a message in a direct conversation, which is left in the
+    /// peer's mailbox when it is offline.
+    fn send_direct(&self, room: String, text: String) {
+        let Some(peer_id) = room.strip_prefix('@') else {
+            return;
+        };
+        let command = DirectMessageCommand::builder()
+            .peer_id(peer_id.to_owned())
+            .message(text.clone())
+            .build();
+        let command_bus = self.peer.command_bus().clone();
+        let author = self.peer.peer_id().to_string();
+        let nickname = self.peer.nickname().map(str::to_owned);
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            match command_bus.send(command).await {
+                Ok((id, delivery)) => {
+                    let left = (delivery == Delivery::Mailbox).then(|| {
+                        format!(
+                            "{room} is offline, the message waits in its \
+                             mailbox"
+                        )
+                    });
+                    let message = ChatMessage {
+                        id,
+                        author,
+                        nickname,
+                        text,
+                        timestamp: now(),
+                        parent_id: None,
+                        mentions: vec![],
+                        reactions: Reactions::new(),
+                        file: None,
+                        outbound: None,
+                    };
+                    if let Some(tx) = action_tx {
+                        let action =
+                            Action::MessageReceived(room, Box::new(message));
+                        let _ = tx.send(action);
+                        if let Some(text) = left {
+                            let _ =
+                                tx.send(Action::Notify(Severity::Info, text));
+                        }
+                    }
+                }
+                Err(e) => send_error(action_tx, format!("Failed to send: {e}")),
+            }
+        });
+    }
+
+    /// Opens the direct conversation with `peer`, a nickname seen in any
+    /// room or a peer id.
+    fn open_direct(&mut self, peer: String, text: String) {
+        let peer_id = self
+            .rooms
+            .values()
+            .find_map(|room| room.known_peers().get(&peer).cloned())
+            .unwrap_or(peer);
+        if peer_id == self.peer.peer_id().to_string() {
+            send_error(self.command_tx.clone(), "That is you".to_owned());
+            return;
+        }
+        let room = format!("@{peer_id}");
+        self.add_room(&room);
+        self.rooms_state.select(self.room_list.position(&room));
+        self.actual_room = Some(room.clone());
+        self.thread = None;
+        self.mode = Mode::Input;
+        if !text.is_empty() {
+            self.send_direct(room, text);
+        }
+    }
+
+    fn run_command(&mut self, room: String, command: SlashCommand) {
+        match command {
+            SlashCommand::Send(path) => self.offer_file(room, path),
+            SlashCommand::Message(peer, text) => self.open_direct(peer, text),
+            SlashCommand::Invite => self.create_invite(room),
+            SlashCommand::Accept(token) => self.accept_invite(token),
+            SlashCommand::Metrics => self.load_metrics(),
+            SlashCommand::Theme(name) => self.switch_theme(name),
+            SlashCommand::Join(room) => {
+                self.enter_room(room);
+                self.mode = Mode::Input;
+            }
+            SlashCommand::Leave => self.leaving = Some(room),
+            SlashCommand::Rename(alias) => self.rename_room(&room, alias),
+            SlashCommand::Pin => self.toggle_pin(&room),
+            SlashCommand::Export(path) => self.export_history(room, path),
+            SlashCommand::Import(path) => self.import_history(path),
+        }
+    }
+
+    fn export_history(&self, room: String, path: PathBuf) {
+        let Some(format) = Format::from_path(&path) else {
+            let error = format!(
+                "Cannot tell the format of {}, name it .txt, .jsonl or .html",
+                path.display()
+            );
+            send_error(self.command_tx.clone(), error);
+            return;
+        };
+        let command = HistoryCommand::builder().topic(room.clone()).build();
+        let command_bus = self.peer.command_bus().clone();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            let history = match command_bus.send(command).await {
+                Ok(history) => history,
+                Err(e) => {
+                    send_error(action_tx, format!("Failed to export: {e}"));
+                    return;
+                }
+            };
+            let text = export::render(&room, &history, format);
+            match tokio::fs::write(&path, text).await {
+                Ok(()) => {
+                    if let Some(tx) = action_tx {
+                        let text = format!(
+                            "Exported {} messages to {}",
+                            history.len(),
+                            path.display()
+                        );
+                        let _ = tx.send(Action::Notify(Severity::Info, text));
+                    }
+                }
+                Err(e) => send_error(
+                    action_tx,
+                    format!("Failed to write {}: {e}", path.display()),
+                ),
+            }
+        });
+    }
+
+    fn import_history(&self, path: PathBuf) {
+        let command_bus = self.peer.command_bus().clone();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            let messages = match tokio::fs::read_to_string(&path).await {
+                Ok(text) => export::parse_jsonl(&text),
+                Err(e) => Err(e.to_string()),
+            };
+            let messages = match messages {
+                Ok(messages) => messages,
+                Err(e) => {
+                    let error =
+                        format!("Failed to import {}: {e}", path.display());
+                    send_error(action_tx, error);
+                    return;
+                }
+            };
+            let mut rooms = messages
+                .iter()
+                .map(|m| m.topic().clone())
+                .collect::<Vec<_>>();
+            rooms.sort();
+            rooms.dedup();
+            let command = ImportCommand::builder().messages(messages).build();
+            match command_bus.send(command).await {
+                Ok(imported) => {
+                    if let Some(tx) = &action_tx {
+                        let text = format!("Imported {imported} new messages");
+                        let _ = tx.send(Action::Notify(Severity::Info, text));
+                    }
+                    for room in rooms {
+                        load_history(
+                            command_bus.clone(),
+                            action_tx.clone(),
+                            room,
+                        )
+                        .await;
+                    }
+                }
+                Err(e) => {
+                    send_error(action_tx, format!("Failed to import: {e}"))
+                }
+            }
+        });
+    }
+
+    fn switch_theme(&mut self, name: Option<String>) {
+        let names = Theme::names(&self.config);
+        match name {
+            Some(name) if names.contains(&name) => {
+                if let Some(tx) = &self.command_tx {
+                    let _ = tx.send(Action::SetTheme(name));
+                }
+            }
+            Some(name) => send_error(
+                self.command_tx.clone(),
+                format!(
+                    "Unknown theme {name}, try one of {}",
+                    names.join(", ")
+                ),
+            ),
+            None => {
+                let text = names
+                    .iter()
+                    .map(|name| match *name == self.theme.name {
+                        true => format!("* {name}"),
+                        false => format!("  {name}"),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                self.notice = Some(("Themes".to_owned(), text));
+            }
+        }
+    }
+
+    fn create_invite(&self, room: String) {
+        if is_inbox_topic(&room) {
+            let error = "Invites can only be issued for rooms".to_owned();
+            send_error(self.command_tx.clone(), error);
+            return;
+        }
+        let command =
+            CreateInviteCommand::builder().topic(room.clone()).build();
+        let command_bus = self.peer.command_bus().clone();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            match command_bus.send(command).await {
+                Ok(invite) => {
+                    if let Some(tx) = action_tx {
+                        let action =
+                            Action::InviteCreated(room, invite.to_url());
+                        let _ = tx.send(action);
+                    }
+                }
+                Err(e) => {
+                    send_error(action_tx, format!("Failed to invite: {e}"))
+                }
+            }
+        });
+    }
+
+    fn load_metrics(&self) {
+        let command = MetricsSnapshotCommand::builder().build();
+        let command_bus = self.peer.command_bus().clone();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            match command_bus.send(command).await {
+                Ok(snapshot) => {
+                    if let Some(tx) = action_tx {
+                        let text = metrics_text(&snapshot);
+                        let _ = tx.send(Action::MetricsLoaded(text));
+                    }
+                }
+                Err(e) => send_error(
+                    action_tx,
+                    format!("Failed to load metrics: {e}"),
+                ),
+            }
+        });
+    }
+
+    fn accept_invite(&self, token: String) {
+        let command = AcceptInviteCommand::builder().token(token).build();
+        let command_bus = self.peer.command_bus().clone();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            match command_bus.send(command).await {
+                Ok(invite) => {
+                    if let Some(tx) = action_tx {
+                        let room = invite.room().clone();
+                        let _ = tx.send(Action::InviteAccepted(room));
+                    }
+                }
+                Err(e) => {
+                    send_error(action_tx, format!("Failed to accept: {e}"))
+                }
+            }
+        });
+    }
+
+    fn invite_accepted(&mut self, room: String) {
+        // accepting the invite subscribed us
+        self.add_room(&room);
+        self.room_list.set_subscription(&room, Subscription::Joined);
+        self.rooms_state.select(self.room_list.position(&room));
+        self.actual_room = Some(room);
+        self.thread = None;
+        self.mode = Mode::Chat;
+    }
+
+    fn offer_file(&self, room: String, path: PathBuf) {
+        if is_inbox_topic(&room) {
+            let error = "Files can only be offered in rooms".to_owned();
+            send_error(self.command_tx.clone(), error);
+            return;
+        }
+        let command =
+            OfferFileCommand::builder().topic(room).path(path).build();
+        let command_bus = self.peer.command_bus().clone();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            // the offer shows up in the chat as a peer event
+            if let Err(e) = command_bus.send(command).await {
+                send_error(action_tx, format!("Failed to offer file: {e}"));
+            }
+        });
+    }
+
+    fn download_dir(&self) -> PathBuf {
+        let config = &self.config.config;
+        config
+            .download_dir
+            .clone()
+            .unwrap_or_else(|| config.data_dir.join("downloads"))
+    }
+
+    fn accept_file(&mut self) {
+        let Some(message) = self.chat().and_then(|c| c.selected()) else {
+            return;
+        };
+        let Some(offer) = message.file.clone() else {
+            return;
+        };
+        if message.author == self.peer.peer_id().to_string() {
+            return;
+        }
+        if let Some(FileState::Downloading(status)) =
+            self.files.get(offer.file_id())
+        {
+            // only a failed download is worth starting again, it resumes
+            if !matches!(status, TransferStatus::Failed { .. }) {
+                return;
+            }
+        }
+
+        let command = DownloadFileCommand::builder()
+            .peer_id(message.author.clone())
+            .offer(offer.clone())
+            .directory(self.download_dir())
+            .build();
+        self.files.insert(
+            offer.file_id().clone(),
+            FileState::Downloading(TransferStatus::Progress {
+                transferred: 0,
+                total: *offer.size(),
+            }),
+        );
+        let command_bus = self.peer.command_bus().clone();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = command_bus.send(command).await {
+                let status = TransferStatus::Failed {
+                    error: e.to_string(),
+                };
+                if let Some(tx) = action_tx {
+                    let file_id = offer.file_id().clone();
+                    let _ = tx.send(Action::TransferUpdated(file_id, status));
+                }
+            }
+        });
+    }
+
+    fn reject_file(&mut self) {
+        let Some(offer) = self
+            .chat()
+            .and_then(|c| c.selected())
+            .and_then(|m| m.file.as_ref())
+        else {
+            return;
+        };
+        if !self.files.contains_key(offer.file_id()) {
+            self.files
+                .insert(offer.file_id().clone(), FileState::Rejected);
+        }
+    }
+
+    /// Sends an ephemeral signal; dropped signals are not worth reporting.
+    fn signal(&self, room: String, kind: SignalKind) {
+        if is_inbox_topic(&room) {
+            return;
+        }
+        let command_bus = self.peer.command_bus().clone();
+        let command = SignalCommand::builder().topic(room).kind(kind).build();
+        tokio::spawn(async move {
+            if let Err(e) = command_bus.send(command).await {
+                tracing::debug!("Failed to send {kind:?} signal: {e}");
+            }
+        });
+    }
+
+    fn announce(&mut self, kind: SignalKind) {
+        for room in self.rooms.keys() {
+            self.signal(room.clone(), kind);
+        }
+        self.announced = match kind {
+            SignalKind::Idle | SignalKind::Away => Some((kind, Instant::now())),
+            _ => None,
+        };
+    }
+
+    fn activity(&mut self) {
+        self.last_activity = Instant::now();
+        if self.announced.is_some() {
+            self.announce(SignalKind::Active);
+        }
+    }
+
+    fn check_idle(&mut self) {
+        match self.announced {
+            None if self.last_activity.elapsed() >= IDLE_AFTER => {
+                self.announce(SignalKind::Idle)
+            }
+            Some((kind, at)) if at.elapsed() >= PRESENCE_REFRESH => {
+                self.announce(kind)
+            }
+            _ => {}
+        }
+    }
+
+    fn presence_changed(
+        &mut self,
+        room: &str,
+        peer_id: String,
+        nickname: Option<String>,
+        state: Option<SignalKind>,
+    ) {
+        let Some(room) = self.rooms.get_mut(room) else {
+            return;
+        };
+        match state {
+            Some(state) => {
+                // expiry events carry no nickname, keep the one we know
+                let nickname = nickname.or_else(|| {
+                    room.presence.get(&peer_id).and_then(|p| p.nickname.clone())
+                });
+                room.presence
+                    .insert(peer_id, PeerPresence { nickname, state });
+            }
+            None => {
+                room.presence.remove(&peer_id);
+            }
+        }
+    }
+
+    /// Runs the configured notification command for a mention we may miss.
+    fn notify(&self, room: &str, message: &ChatMessage) {
+        let Some((program, args)) =
+            self.config.config.notify_command.split_first()
+        else {
+            return;
+        };
+        let mut command = tokio::process::Command::new(program);
+        command
+            .args(args)
+            .arg(format!("{} in {room}", message.display_name()))
+            .arg(&message.text);
+        tokio::spawn(async move {
+            match command.status().await {
+                Ok(status) if !status.success() => {
+                    tracing::warn!("Notification command failed: {status}")
+                }
+                Err(e) => tracing::warn!("Failed to run notification: {e}"),
+                Ok(_) => {}
+            }
+        });
+    }
+
+    fn focused(&self) -> bool {
+        !matches!(self.announced, Some((SignalKind::Away, _)))
+    }
+
+    /// Marks the current room as read, broadcasting a receipt if enabled.
+    fn mark_read(&mut self) {
+        let Some(room) = self.actual_room.clone() else {
+            return;
+        };
+        let Some(message_id) =
+            self.rooms.get_mut(&room).and_then(|r| r.mark_read())
+        else {
+            return;
+        };
+        if !self.config.config.read_receipts || is_inbox_topic(&room) {
+            return;
+        }
+
+        let command = ReceiptCommand::builder()
+            .topic(room)
+            .message_id(message_id)
+            .build();
+        let command_bus = self.peer.command_bus().clone();
+        tokio::spawn(async move {
+            if let Err(e) = command_bus.send(command).await {
+                tracing::debug!("Failed to send read receipt: {e}");
+            }
+        });
+    }
+
+    fn read_receipt(
+        &mut self,
+        room: &str,
+        peer_id: String,
+        nickname: Option<String>,
+        message_id: String,
+    ) {
+        if let Some(room) = self.rooms.get_mut(room) {
+            room.receipts.insert(
+                peer_id,
+                ReadMarker {
+                    nickname,
+                    message_id,
+                },
+            );
+        }
+    }
+
+    fn jump_to_unread(&mut self) {
+        let Some(room) = self.actual_room.as_ref() else {
+            return;
+        };
+        let Some(room) = self.rooms.get_mut(room) else {
+            return;
+        };
+        let Some(index) = room
+            .first_unread
+            .as_ref()
+            .and_then(|id| room.chat.messages.iter().position(|m| &m.id == id))
+        else {
+            return;
+        };
+        room.chat.state.select(Some(index));
+    }
+
+    fn open_thread(&mut self) {
+        let Some(message) = self.chat().and_then(|c| c.selected()) else {
+            return;
+        };
+        self.load_thread(message.id.clone());
+    }
+
+    fn load_thread(&self, message_id: String) {
+        let command_bus = self.peer.command_bus().clone();
+        let command = ThreadCommand::builder()
+            .message_id(message_id.clone())
+            .build();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            match command_bus.send(command).await {
+                Ok(thread) => {
+                    let thread = thread.into_iter().map(Into::into).collect();
+                    if let Some(tx) = action_tx {
+                        let _ =
+                            tx.send(Action::ThreadLoaded(message_id, thread));
+                    }
+                }
+                Err(e) => {
+                    send_error(action_tx, format!("Failed to load thread: {e}"))
+                }
+            }
+        });
+    }
+
+    fn toggle_raw(&mut self) {
+        let Some(chat) = self.chat_mut() else {
+            return;
+        };
+        if let Some(id) = chat.selected().map(|m| m.id.clone()) {
+            if !chat.raw.remove(&id) {
+                chat.raw.insert(id);
+            }
+        }
+    }
+
+    fn start_reply(&mut self) {
+        let Some(chat) = self.chat_mut() else {
+            return;
+        };
+        if let Some(id) = chat.selected().map(|m| m.id.clone()) {
+            chat.reply_to = Some(id);
+            self.mode = Mode::Input;
+        }
+    }
+
+    fn open_picker(&mut self) {
+        if self.chat().and_then(|c| c.selected()).is_some() {
+            self.picker = Some(0);
+        }
+    }
+
+    fn picker_navigate(&mut self, back: bool) {
+        let Some(selected) = self.picker else {
+            return;
+        };
+        let selected = match back {
+            true => selected.saturating_sub(1),
+            false => (selected + 1).min(emoji::EMOJIS.len() - 1),
+        };
+        self.picker = Some(selected);
+    }
+
+    fn pick_reaction(&mut self) {
+        if let Some(selected) = self.picker.take() {
+            self.toggle_reaction(emoji::EMOJIS[selected].0);
+        }
+    }
+
+    fn toggle_reaction(&self, emoji: &str) {
+        let Some(room) = self.actual_room.clone() else {
+            return;
+        };
+        if is_inbox_topic(&room) {
+            let error = "Reactions are only available in rooms".to_owned();
+            send_error(self.command_tx.clone(), error);
+            return;
+        }
+        let Some(message_id) =
+            self.chat().and_then(|c| c.selected()).map(|m| m.id.clone())
+        else {
+            return;
+        };
+
+        let command = ReactCommand::builder()
+            .topic(room)
+            .message_id(message_id)
+            .emoji(emoji.to_owned())
+            .build();
+        let command_bus = self.peer.command_bus().clone();
+        let action_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            // the resulting reactions come back as a peer event
+            if let Err(e) = command_bus.send(command).await {
+                send_error(action_tx, format!("Failed to react: {e}"));
+            }
+        });
+    }
+
+    fn reactions_updated(
+        &mut self,
+        room: &str,
+        message_id: &str,
+        reactions: Reactions,
+    ) {
+        if let Some(thread) = &mut self.thread {
+            if let Some(m) =
+                thread.messages.iter_mut().find(|m| m.id == message_id)
+            {
+                m.reactions = reactions.clone();
+            }
+        }
+        if let Some(message) = self
+            .rooms
+            .get_mut(room)
+            .and_then(|r| r.chat.find_mut(message_id))
+        {
+            message.reactions = reactions;
+        }
+    }
+
+    fn message_received(&mut self, room: String, message: ChatMessage) {
+        let me = self.peer.peer_id().to_string();
+        if message.author != me
+            && message.mentions(&me)
+            && (self.suspended || !self.focused())
+        {
+            self.notify(&room, &message);
+        }
+
+        let refresh_thread = self.thread.as_ref().is_some_and(|thread| {
+            message
+                .parent_id
+                .as_ref()
+                .is_some_and(|id| thread.messages.iter().any(|m| &m.id == id))
+        });
+
+        self.add_room(&room);
+        if let Some(r) = self.rooms.get_mut(&room) {
+            r.chat.push(message);
+        }
+        if self.actual_room.as_ref() == Some(&room) && self.focused() {
+            self.mark_read();
+        }
+
+        if let Some(thread) = self.thread.as_ref().filter(|_| refresh_thread) {
+            self.load_thread(thread.root_id.clone());
+        }
+    }
+
+    fn chat(&self) -> Option<&models::Chat> {
+        let room = self.actual_room.as_ref()?;
+        self.rooms.get(room).map(|r| &r.chat)
+    }
+
+    fn chat_mut(&mut self) -> Option<&mut models::Chat> {
+        let room = self.actual_room.as_ref()?;
+        self.rooms.get_mut(room).map(|r| &mut r.chat)
+    }
+
     fn room_navigate(&mut self, up: bool) {
         match up {
             true => self.rooms_state.select_previous(),
@@ -108,12 +1269,211 @@ impl Home {
         }
     }
 
+    fn select_room(&mut self) {
+        let Some(selected) = self.selected_room() else {
+            return;
+        };
+        // try again a room we could not join
+        if let Some(Subscription::Failed(_)) =
+            self.room_list.get(&selected).map(|e| &e.subscription)
+        {
+            self.subscribe(selected.clone());
+        }
+        let me = self.peer.peer_id().to_string();
+        if let Some(room) = self.rooms.get_mut(&selected) {
+            let first_unread = room.unread(&me).next().map(|m| m.id.clone());
+            room.first_unread = first_unread;
+        }
+        self.actual_room = Some(selected);
+        self.thread = None;
+        self.mode = Mode::Chat;
+        self.mark_read();
+    }
+
+    /// Opens `room` with the message `id` selected.
+    fn jump_to_message(&mut self, room: String, id: &str) {
+        let Some(chat) = self.rooms.get_mut(&room).map(|r| &mut r.chat) else {
+            return;
+        };
+        let position = chat.messages.iter().position(|m| m.id == id);
+        chat.state.select(position);
+        self.rooms_state.select(self.room_list.position(&room));
+        self.actual_room = Some(room);
+        self.thread = None;
+        self.mode = Mode::Chat;
+        self.mark_read();
+    }
+
+    fn message_navigate(&mut self, up: bool) {
+        if let Some(chat) = self.chat_mut() {
+            match up {
+                true => chat.state.select_previous(),
+                false => chat.state.select_next(),
+            }
+        }
+    }
+
+    fn dialog_open(&self) -> bool {
+        self.picker.is_some()
+            || self.notice.is_some()
+            || self.join_prompt.is_some()
+            || self.leaving.is_some()
+    }
+
+    /// Runs an operation bound to a key, in the pane that has the focus.
+    fn perform(&mut self, action: &Action) {
+        let dialog = self.dialog_open();
+        match (action, &self.mode) {
+            (Action::SelectNext | Action::SelectPrevious, _) if dialog => {
+                self.picker_navigate(*action == Action::SelectPrevious)
+            }
+            (Action::Confirm, _) if dialog => {
+                if self.picker.is_some() {
+                    self.pick_reaction();
+                } else if self.leaving.is_some() {
+                    self.confirm_leave();
+                } else if let Some(room) = self.join_prompt.take() {
+                    let room = room.trim().to_owned();
+                    if !room.is_empty() {
+                        self.enter_room(room);
+                        self.mode = Mode::Input;
+                    }
+                } else {
+                    self.notice = None;
+                }
+            }
+            (Action::Cancel, _) if dialog => {
+                self.picker = None;
+                self.notice = None;
+                self.join_prompt = None;
+                self.leaving = None;
+            }
+            // a dialog waits for its answer
+            _ if dialog => return,
+            (Action::FocusNext, _) => self.chnage_focus(),
+            (Action::JoinRoom(room), _) => self.enter_room(room.clone()),
+            (Action::NewRoom, _) => self.join_prompt = Some(String::new()),
+            (Action::LeaveRoom, _) => self.leaving = self.target_room(),
+            (Action::TogglePin, _) => {
+                if let Some(room) = self.target_room() {
+                    self.toggle_pin(&room);
+                }
+            }
+            (Action::SelectNext, Mode::Rooms) => self.room_navigate(false),
+            (Action::SelectPrevious, Mode::Rooms) => self.room_navigate(true),
+            (Action::SelectNext, Mode::Chat) => self.message_navigate(false),
+            (Action::SelectPrevious, Mode::Chat) => self.message_navigate(true),
+            (Action::Confirm, Mode::Rooms) => self.select_room(),
+            (Action::Confirm, Mode::Input) => {
+                let was_typing = self.typing();
+                self.send_message();
+                self.typing_changed(was_typing);
+            }
+            (Action::Cancel, Mode::Input) => {
+                if let Some(chat) = self.chat_mut() {
+                    chat.reply_to = None;
+                }
+                self.mode = Mode::Chat;
+            }
+            (Action::Cancel, Mode::Chat) => self.thread = None,
+            (Action::Reply, _) => self.start_reply(),
+            (Action::OpenThread, _) => self.open_thread(),
+            (Action::React, _) => self.open_picker(),
+            (Action::JumpToUnread, _) => self.jump_to_unread(),
+            (Action::AcceptFile, _) => self.accept_file(),
+            (Action::RejectFile, _) => self.reject_file(),
+            (Action::ToggleRaw, _) => self.toggle_raw(),
+            _ => return,
+        }
+        self.activity();
+    }
+
     fn chnage_focus(&mut self) {
         match &self.mode {
-            Mode::Chat => self.mode = Mode::Rooms,
+            Mode::Chat => self.mode = Mode::Input,
+            Mode::Input => self.mode = Mode::Rooms,
             Mode::Rooms => self.mode = Mode::Chat,
         }
     }
+
+    fn handle_input_key(&mut self, key: KeyEvent) {
+        let was_typing = self.typing();
+        match (key.code, key.modifiers) {
+            (KeyCode::Backspace, _) => {
+                if let Some(chat) = self.chat_mut() {
+                    chat.input.pop();
+                }
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                if let Some(chat) = self.chat_mut() {
+                    chat.input.push(c);
+                }
+            }
+            _ => {}
+        }
+        self.typing_changed(was_typing);
+    }
+
+    /// Focuses the pane under `position`, selecting the room or opening
+    /// the direct conversation with the participant clicked.
+    fn click(&mut self, position: Position) {
+        let areas = self.areas;
+        if areas.rooms.contains(position) {
+            self.mode = Mode::Rooms;
+            let offset = self.rooms_state.offset();
+            let row = list_row(areas.rooms, offset, position.y)
+                .filter(|i| *i < self.room_list.len());
+            if let Some(row) = row {
+                self.rooms_state.select(Some(row));
+                self.select_room();
+            }
+        } else if areas.participants.contains(position) {
+            let me = self.peer.peer_id().to_string();
+            let participant = self
+                .actual_room
+                .as_ref()
+                .and_then(|room| self.rooms.get(room))
+                .zip(list_row(areas.participants, 0, position.y))
+                .and_then(|(room, i)| room.participants(&me).get(i).cloned());
+            if let Some((peer_id, _)) = participant {
+                self.open_direct(peer_id, String::new());
+            }
+        } else if areas.chat.contains(position) {
+            self.mode = Mode::Chat;
+        } else if areas.input.contains(position) {
+            self.mode = Mode::Input;
+        }
+    }
+
+    fn scroll(&mut self, position: Position, up: bool) {
+        if self.areas.rooms.contains(position) {
+            self.room_navigate(up);
+        } else if self.areas.chat.contains(position) {
+            if let Some(chat) = self.chat_mut() {
+                match up {
+                    true => chat.state.scroll_up_by(SCROLL_LINES),
+                    false => chat.state.scroll_down_by(SCROLL_LINES),
+                }
+            }
+        }
+    }
+
+    fn typing(&self) -> bool {
+        self.chat().is_some_and(|c| !c.input.is_empty())
+    }
+
+    /// Tells the room when we start or stop typing.
+    fn typing_changed(&self, was_typing: bool) {
+        let Some(room) = self.actual_room.clone() else {
+            return;
+        };
+        let typing = self.typing();
+        match (was_typing, typing) {
+            (_, true) => self.signal(room, SignalKind::Typing),
+            (true, false) => self.signal(room, SignalKind::StoppedTyping),
+            (false, false) => {}
+        }
+    }
 }
 
 impl Component for Home {
@@ -122,6 +1482,7 @@ impl Component for Home {
             self.peer.subscribe(),
             self.command_tx.clone().unwrap(),
         ));
+        self.restore_rooms();
         Ok(())
     }
 
@@ -134,24 +1495,132 @@ impl Component for Home {
     }
 
     fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.theme = Theme::configured(&config, app::Mode::Home);
+        self.room_list =
+            RoomList::load(config.config.data_dir.join(ROOMS_FILE));
         self.config = config;
         Ok(())
     }
 
+    fn handle_events(
+        &mut self,
+        event: Option<Event>,
+    ) -> Result<Option<Action>> {
+        match event {
+            Some(Event::Key(key_event)) => self.handle_key_event(key_event),
+            Some(Event::Mouse(mouse_event)) => {
+                self.handle_mouse_event(mouse_event)
+            }
+            Some(Event::FocusLost) => {
+                self.announce(SignalKind::Away);
+                Ok(None)
+            }
+            Some(Event::FocusGained) => {
+                self.activity();
+                self.mark_read();
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
     fn handle_key_event(&mut self, key: KeyEvent) -> Result<Option<Action>> {
-        match (&self.mode, key.code, key.modifiers) {
-            (Mode::Rooms, KeyCode::Down, KeyModifiers::NONE) => {
-                self.room_navigate(false);
+        self.activity();
+        // everything else comes bound to an action
+        if self.join_prompt.is_some() {
+            self.join_prompt_key(key);
+        } else if matches!(self.mode, Mode::Input) && !self.dialog_open() {
+            self.handle_input_key(key);
+        }
+        Ok(None)
+    }
+
+    fn handle_mouse_event(
+        &mut self,
+        mouse: MouseEvent,
+    ) -> Result<Option<Action>> {
+        // a dialog waits for its answer
+        if self.dialog_open() {
+            return Ok(None);
+        }
+        let position = Position::new(mouse.column, mouse.row);
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => self.click(position),
+            MouseEventKind::ScrollUp => self.scroll(position, true),
+            MouseEventKind::ScrollDown => self.scroll(position, false),
+            _ => return Ok(None),
+        }
+        self.activity();
+        Ok(None)
+    }
+
+    fn focus(&self) -> Option<app::Mode> {
+        if self.dialog_open() {
+            return Some(app::Mode::Dialog);
+        }
+        let mode = match self.mode {
+            Mode::Rooms => app::Mode::Rooms,
+            Mode::Chat => app::Mode::Chat,
+            Mode::Input => app::Mode::Input,
+        };
+        Some(mode)
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        self.perform(&action);
+        match action {
+            Action::MessageReceived(room, message) => {
+                self.message_received(room, *message)
+            }
+            Action::HistoryLoaded(room, messages) => {
+                self.history_loaded(&room, messages)
+            }
+            Action::ThreadLoaded(root_id, messages) => {
+                self.thread = Some(Thread { root_id, messages });
+            }
+            Action::ReactionsUpdated(room, message_id, reactions) => {
+                self.reactions_updated(&room, &message_id, reactions)
+            }
+            Action::PresenceChanged(room, peer_id, nickname, state) => {
+                self.presence_changed(&room, peer_id, nickname, state)
+            }
+            Action::ReadReceipt(room, peer_id, nickname, message_id) => {
+                self.read_receipt(&room, peer_id, nickname, message_id)
             }
-            (Mode::Rooms, KeyCode::Up, KeyModifiers::NONE) => {
-                self.room_navigate(true);
+            Action::TransferUpdated(file_id, status) => {
+                self.files.insert(file_id, FileState::Downloading(status));
             }
-            (_, KeyCode::Tab, KeyModifiers::NONE) => {
-                self.chnage_focus();
+            Action::OutboundUpdated(room, id, status) => {
+                self.outbound_updated(&room, &id, status)
             }
-            (_, KeyCode::Char('j'), KeyModifiers::CONTROL) => {
-                self.enter_room("room".to_owned());
+            Action::InviteCreated(room, token) => {
+                let text = format!(
+                    "Share this with whoever should join, they can paste it \
+                     after /accept:\n\n{token}"
+                );
+                self.notice = Some((format!("Invite to {room}"), text));
             }
+            Action::InviteAccepted(room) => self.invite_accepted(room),
+            Action::JumpToMessage(room, id) => self.jump_to_message(room, &id),
+            Action::RoomJoined(room) => {
+                self.room_list.set_subscription(&room, Subscription::Joined)
+            }
+            Action::RoomJoinFailed(room, error) => self
+                .room_list
+                .set_subscription(&room, Subscription::Failed(error)),
+            Action::MetricsLoaded(text) => {
+                self.notice = Some(("Metrics".to_owned(), text));
+            }
+            Action::SetTheme(name) => {
+                if let Some(theme) =
+                    Theme::load(&self.config, &name, app::Mode::Home)
+                {
+                    self.theme = theme;
+                }
+            }
+            Action::Tick => self.check_idle(),
+            Action::Suspend => self.suspended = true,
+            Action::Resume => self.suspended = false,
             _ => {}
         }
         Ok(None)
@@ -161,14 +1630,60 @@ impl Component for Home {
         let (header, main, footer) = vertical_layout(area);
         let (rooms, chat_panel) = horizontal_layout(main);
         let (chat, input, participants) = chat_layout(chat_panel);
-        frame.render_widget(HeaderWidget, header);
+        self.areas = Areas {
+            rooms,
+            chat,
+            input,
+            participants,
+        };
+        let style = self.theme.header;
+        frame.render_widget(HeaderWidget { style }, header);
         frame.render_stateful_widget(RoomsWidget, rooms, self);
-        frame.render_stateful_widget(ChatWidget, chat_panel, self);
+        match self.thread {
+            Some(_) => {
+                let (chat, thread) = thread_layout(chat);
+                frame.render_stateful_widget(ChatWidget, chat, self);
+                frame.render_stateful_widget(ThreadWidget, thread, self);
+            }
+            None => frame.render_stateful_widget(ChatWidget, chat, self),
+        }
+        frame.render_stateful_widget(InputWidget, input, self);
+        frame.render_stateful_widget(ParticipantsWidget, participants, self);
+        frame.render_stateful_widget(StatusWidget, footer, self);
+        frame.render_stateful_widget(NoticeWidget, area, self);
 
         Ok(())
     }
 }
 
+/// Fetches the messages the peer kept of `room`.
+async fn load_history(
+    command_bus: PeerCommandBus,
+    action_tx: Option<UnboundedSender<Action>>,
+    room: String,
+) {
+    let command = HistoryCommand::builder().topic(room.clone()).build();
+    match command_bus.send(command).await {
+        Ok(history) => {
+            if let Some(tx) = action_tx {
+                let messages = history.into_iter().map(Into::into).collect();
+                let _ = tx.send(Action::HistoryLoaded(room, messages));
+            }
+        }
+        Err(e) => send_error(action_tx, format!("Failed to load {room}: {e}")),
+    }
+}
+
+/// Entry of a bordered one-line-per-item list drawn in `area` that `row`
+/// falls on, scrolled by `offset`.
+fn list_row(area: Rect, offset: usize, row: u16) -> Option<usize> {
+    let first = area.y + 1;
+    let last = area.bottom().checked_sub(1)?;
+    (first..last)
+        .contains(&row)
+        .then(|| (row - first) as usize + offset)
+}
+
 fn vertical_layout(area: Rect) -> (Rect, Rect, Rect) {
     let areas = Layout::default()
         .direction(Direction::Vertical)
@@ -194,7 +1709,7 @@ fn horizontal_layout(area: Rect) -> (Rect, Rect) {
 fn chat_layout(area: Rect) -> (Rect, Rect, Rect) {
     let hr = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints(vec![Constraint::Min(1), Constraint::Length(10)])
+        .constraints(vec![Constraint::Min(1), Constraint::Length(20)])
         .split(area);
 
     let vr = Layout::default()
@@ -204,3 +1719,12 @@ fn chat_layout(area: Rect) -> (Rect, Rect, Rect) {
 
     (vr[0], vr[1], hr[1])
 }
+
+fn thread_layout(area: Rect) -> (Rect, Rect) {
+    let areas = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Percentage(60), Constraint::Min(1)])
+        .split(area);
+
+    (areas[0], areas[1])
+}
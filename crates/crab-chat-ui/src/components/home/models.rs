@@ -1,11 +1,435 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{DateTime, Local};
+use crab_chat_peer::{
+    FileOffer, FileOfferedEvent, MessageReceivedEvent, OutboundStatus,
+    Reactions, SignalKind, StoredMessage, TransferStatus,
+};
+use ratatui::widgets::ListState;
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug, Default)]
 pub struct Room {
-    name: String,
-    chat: Chat,
+    pub name: String,
+    pub chat: Chat,
+    /// Ephemeral state of the other peers in the room, by peer id.
+    pub presence: BTreeMap<String, PeerPresence>,
+    /// Id of the last message we read.
+    pub last_read: Option<String>,
+    /// First message that was unread when the room was last opened.
+    pub first_unread: Option<String>,
+    /// Latest read receipt of the other peers, by peer id.
+    pub receipts: BTreeMap<String, ReadMarker>,
+}
+
+impl Room {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            chat: Chat::default(),
+            presence: BTreeMap::new(),
+            last_read: None,
+            first_unread: None,
+            receipts: BTreeMap::new(),
+        }
+    }
+
+    /// Name shown for the room; a conversation with a single peer goes by
+    /// that peer's nickname once we know it.
+    pub fn title(&self) -> String {
+        let Some(peer_id) = self.name.strip_prefix('@') else {
+            return self.name.clone();
+        };
+        self.chat
+            .messages
+            .iter()
+            .find(|m| m.author == peer_id)
+            .and_then(|m| m.nickname.as_ref())
+            .map(|nickname| format!("@{nickname}"))
+            .unwrap_or_else(|| self.name.clone())
+    }
+
+    /// Messages of other peers after our read marker.
+    pub fn unread<'a>(
+        &'a self,
+        me: &'a str,
+    ) -> impl Iterator<Item = &'a ChatMessage> + 'a {
+        let start = self
+            .last_read
+            .as_ref()
+            .and_then(|id| self.chat.messages.iter().position(|m| &m.id == id))
+            .map_or(0, |i| i + 1);
+        self.chat.messages[start..]
+            .iter()
+            .filter(move |m| m.author != me)
+    }
+
+    /// Unread messages mentioning us.
+    pub fn unread_mentions(&self, me: &str) -> usize {
+        self.unread(me).filter(|m| m.mentions(me)).count()
+    }
+
+    /// Peer ids of the peers seen in the room, by nickname.
+    pub fn known_peers(&self) -> HashMap<String, String> {
+        let messages = self
+            .chat
+            .messages
+            .iter()
+            .map(|m| (m.nickname.as_ref(), &m.author));
+        let presence = self
+            .presence
+            .iter()
+            .map(|(peer_id, p)| (p.nickname.as_ref(), peer_id));
+        let receipts = self
+            .receipts
+            .iter()
+            .map(|(peer_id, r)| (r.nickname.as_ref(), peer_id));
+        messages
+            .chain(presence)
+            .chain(receipts)
+            .filter_map(|(nickname, peer_id)| {
+                Some((nickname?.clone(), peer_id.clone()))
+            })
+            .collect()
+    }
+
+    /// The other peers seen in the room as (peer id, name), by name.
+    pub fn participants(&self, me: &str) -> Vec<(String, String)> {
+        let mut nicknames: BTreeMap<&str, Option<&str>> = BTreeMap::new();
+        let messages = self
+            .chat
+            .messages
+            .iter()
+            .map(|m| (m.author.as_str(), m.nickname.as_deref()));
+        let presence = self
+            .presence
+            .iter()
+            .map(|(peer_id, p)| (peer_id.as_str(), p.nickname.as_deref()));
+        let receipts = self
+            .receipts
+            .iter()
+            .map(|(peer_id, r)| (peer_id.as_str(), r.nickname.as_deref()));
+        for (peer_id, nickname) in messages.chain(presence).chain(receipts) {
+            let known = nicknames.entry(peer_id).or_default();
+            *known = nickname.or(*known);
+        }
+        nicknames.remove(me);
+        let mut participants: Vec<_> = nicknames
+            .into_iter()
+            .map(|(peer_id, nickname)| {
+                (peer_id.to_owned(), display_name(peer_id, nickname))
+            })
+            .collect();
+        participants.sort_by(|a, b| a.1.cmp(&b.1));
+        participants
+    }
+
+    /// Moves the read marker to the newest message, returning its id when
+    /// the marker moved.
+    pub fn mark_read(&mut self) -> Option<String> {
+        let last = self.chat.messages.last().map(|m| m.id.clone());
+        if last.is_none() || last == self.last_read {
+            return None;
+        }
+        self.last_read = last.clone();
+        last
+    }
+
+    /// Display names of the peers whose read receipt points at a message.
+    pub fn seen_by(&self, message_id: &str) -> Vec<String> {
+        self.receipts
+            .iter()
+            .filter(|(_, r)| r.message_id == message_id)
+            .map(|(peer_id, r)| display_name(peer_id, r.nickname.as_deref()))
+            .collect()
+    }
+
+    /// Display names of the peers currently typing in the room.
+    pub fn typing(&self) -> Vec<String> {
+        self.presence
+            .iter()
+            .filter(|(_, p)| p.state == SignalKind::Typing)
+            .map(|(peer_id, p)| display_name(peer_id, p.nickname.as_deref()))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct ReadMarker {
+    pub nickname: Option<String>,
+    pub message_id: String,
+}
+
+#[derive(Debug)]
+pub struct PeerPresence {
+    pub nickname: Option<String>,
+    pub state: SignalKind,
 }
 
 #[derive(Debug, Default)]
 pub struct Chat {
-    messages: Vec<String>,
-    input: String,
+    pub messages: Vec<ChatMessage>,
+    pub input: String,
+    pub reply_to: Option<String>,
+    pub state: ListState,
+    /// Messages shown as typed rather than formatted, by id.
+    pub raw: HashSet<String>,
+}
+
+impl Chat {
+    pub fn push(&mut self, message: ChatMessage) {
+        if self.find(&message.id).is_none() {
+            self.messages.push(message);
+        }
+    }
+
+    /// Adds the messages not shown yet, keeping the chat in time order.
+    pub fn merge(&mut self, messages: Vec<ChatMessage>) {
+        let count = self.messages.len();
+        for message in messages {
+            self.push(message);
+        }
+        if self.messages.len() > count {
+            self.messages.sort_by_key(|m| m.timestamp);
+        }
+    }
+
+    pub fn find(&self, id: &str) -> Option<&ChatMessage> {
+        self.messages.iter().find(|m| m.id == id)
+    }
+
+    pub fn find_mut(&mut self, id: &str) -> Option<&mut ChatMessage> {
+        self.messages.iter_mut().find(|m| m.id == id)
+    }
+
+    pub fn selected(&self) -> Option<&ChatMessage> {
+        self.state.selected().and_then(|i| self.messages.get(i))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Thread {
+    pub root_id: String,
+    pub messages: Vec<ChatMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub id: String,
+    pub author: String,
+    pub nickname: Option<String>,
+    pub text: String,
+    pub timestamp: u64,
+    pub parent_id: Option<String>,
+    /// Peer ids of the peers mentioned in the message.
+    pub mentions: Vec<String>,
+    pub reactions: Reactions,
+    /// The offered file, for file offers.
+    pub file: Option<FileOffer>,
+    /// Where our own message stands while it waits for peers to join.
+    pub outbound: Option<OutboundStatus>,
+}
+
+/// What became of a file offered to us.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileState {
+    Rejected,
+    Downloading(TransferStatus),
+}
+
+impl ChatMessage {
+    pub fn mentions(&self, peer_id: &str) -> bool {
+        self.mentions.iter().any(|p| p == peer_id)
+    }
+
+    pub fn display_name(&self) -> String {
+        display_name(&self.author, self.nickname.as_deref())
+    }
+}
+
+/// Peer ids of the `@nickname` mentions in `text` that match a known peer.
+pub fn resolve_mentions(
+    text: &str,
+    known_peers: &HashMap<String, String>,
+) -> Vec<String> {
+    let mut mentions = vec![];
+    for word in text.split_whitespace() {
+        let Some(nickname) = word.strip_prefix('@') else {
+            continue;
+        };
+        let nickname =
+            nickname.trim_end_matches(|c: char| ",.:;!?".contains(c));
+        if let Some(peer_id) = known_peers.get(nickname) {
+            if !mentions.contains(peer_id) {
+                mentions.push(peer_id.clone());
+            }
+        }
+    }
+    mentions
+}
+
+/// The nickname of a peer when known, otherwise the last characters of its
+/// id, enough to tell peers apart.
+pub fn display_name(peer_id: &str, nickname: Option<&str>) -> String {
+    match nickname {
+        Some(nickname) => nickname.to_owned(),
+        None => {
+            let start = peer_id.len().saturating_sub(8);
+            peer_id.get(start..).unwrap_or(peer_id).to_owned()
+        }
+    }
+}
+
+impl From<&MessageReceivedEvent> for ChatMessage {
+    fn from(event: &MessageReceivedEvent) -> Self {
+        Self {
+            id: event.message_id().clone(),
+            author: event.peer_id().clone(),
+            nickname: event.nickname().clone(),
+            text: event.message().clone(),
+            timestamp: *event.timestamp(),
+            parent_id: event.parent_id().clone(),
+            mentions: event.mentions().clone(),
+            reactions: Reactions::new(),
+            file: None,
+            outbound: None,
+        }
+    }
+}
+
+impl From<StoredMessage> for ChatMessage {
+    fn from(message: StoredMessage) -> Self {
+        Self {
+            id: message.message_id().clone(),
+            author: message.peer_id().clone(),
+            nickname: message.nickname().clone(),
+            text: message.data().clone(),
+            timestamp: *message.timestamp(),
+            parent_id: message.parent_id().clone(),
+            mentions: message.mentions().clone(),
+            reactions: message.reactions().clone(),
+            file: None,
+            outbound: None,
+        }
+    }
+}
+
+impl From<&FileOfferedEvent> for ChatMessage {
+    fn from(event: &FileOfferedEvent) -> Self {
+        Self {
+            id: event.message_id().clone(),
+            author: event.peer_id().clone(),
+            nickname: event.nickname().clone(),
+            text: event.offer().name().clone(),
+            timestamp: *event.timestamp(),
+            parent_id: None,
+            mentions: vec![],
+            reactions: Reactions::new(),
+            file: Some(event.offer().clone()),
+            outbound: None,
+        }
+    }
+}
+
+/// Byte count in the largest unit that keeps it above one.
+/// Local time of day of a unix timestamp, in seconds.
+pub fn format_time(timestamp: u64) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|time| time.with_timezone(&Local).format("%H:%M").to_string())
+        .unwrap_or_default()
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{size:.1} {}", UNITS[unit]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, author: &str, text: &str) -> ChatMessage {
+        ChatMessage {
+            id: id.to_owned(),
+            author: author.to_owned(),
+            nickname: Some(author.to_owned()),
+            text: text.to_owned(),
+            timestamp: 0,
+            parent_id: None,
+            mentions: vec![],
+            reactions: Reactions::new(),
+            file: None,
+            outbound: None,
+        }
+    }
+
+    #[test]
+    fn test_unread_after_marker() {
+        let mut room = Room::new("room".to_owned());
+        room.chat.push(message("a", "bob", "hi"));
+        room.chat.push(message("b", "me", "hello"));
+        room.chat.push(ChatMessage {
+            mentions: vec!["me".to_owned()],
+            ..message("c", "bob", "hey @me")
+        });
+        assert_eq!(room.unread("me").count(), 2);
+        assert_eq!(room.unread_mentions("me"), 1);
+
+        assert_eq!(room.mark_read(), Some("c".to_owned()));
+        assert_eq!(room.mark_read(), None);
+        assert_eq!(room.unread("me").count(), 0);
+
+        room.chat.push(message("d", "bob", "still there?"));
+        let unread = room.unread("me").map(|m| m.id.as_str());
+        assert_eq!(unread.collect::<Vec<_>>(), vec!["d"]);
+    }
+
+    #[test]
+    fn test_resolve_mentions() {
+        let mut room = Room::new("room".to_owned());
+        room.chat.push(message("a", "bob", "hi"));
+        room.chat.push(message("b", "carol", "hello"));
+        let known = room.known_peers();
+
+        assert_eq!(
+            resolve_mentions("@bob, @dave and @carol: ping @bob", &known),
+            vec!["bob".to_owned(), "carol".to_owned()]
+        );
+        assert!(resolve_mentions("mail bob@example.com", &known).is_empty());
+    }
+
+    #[test]
+    fn test_participants() {
+        let mut room = Room::new("room".to_owned());
+        room.chat.push(message("a", "me", "hi"));
+        room.chat.push(message("b", "carol", "hello"));
+        room.presence.insert(
+            "12D3KooWalice".to_owned(),
+            PeerPresence {
+                nickname: None,
+                state: SignalKind::Typing,
+            },
+        );
+        room.chat.push(ChatMessage {
+            nickname: None,
+            ..message("c", "carol", "again")
+        });
+
+        assert_eq!(
+            room.participants("me"),
+            vec![
+                ("carol".to_owned(), "carol".to_owned()),
+                ("12D3KooWalice".to_owned(), "ooWalice".to_owned()),
+            ]
+        );
+    }
 }
@@ -0,0 +1,68 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Alignment, Constraint, Flex, Layout, Rect},
+    widgets::{
+        Block, BorderType, Borders, Clear, Paragraph, StatefulWidget, Widget,
+        Wrap,
+    },
+};
+
+use super::Home;
+
+/// Popup with text meant to be read or copied in full, such as an invite,
+/// or asking for the room to join or whether to leave one.
+pub struct NoticeWidget;
+
+impl StatefulWidget for NoticeWidget {
+    type State = Home;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        let (title, text, hint) = if let Some(room) = &state.leaving {
+            let text = format!(
+                "Leave {}? It goes away from the list and we stop receiving \
+                 its messages.",
+                state.room_title(room)
+            );
+            (
+                "Leave room".to_owned(),
+                text,
+                "Enter to leave · Esc to stay",
+            )
+        } else if let Some(name) = &state.join_prompt {
+            let text = format!(
+                "Name of the room to join, it is created if nobody is in \
+                 it yet:\n\n{name}█"
+            );
+            (
+                "Join room".to_owned(),
+                text,
+                "Enter to join · Esc to cancel",
+            )
+        } else if let Some((title, text)) = &state.notice {
+            (title.clone(), text.clone(), "Esc to close")
+        } else {
+            return;
+        };
+        let [area] = Layout::horizontal([Constraint::Percentage(70)])
+            .flex(Flex::Center)
+            .areas(area);
+        let [area] = Layout::vertical([Constraint::Percentage(40)])
+            .flex(Flex::Center)
+            .areas(area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(state.theme.focused_border)
+            .style(state.theme.background)
+            .title(title)
+            .title_alignment(Alignment::Center)
+            .title_style(state.theme.title)
+            .title_bottom(hint);
+        Clear.render(area, buf);
+        Paragraph::new(text)
+            .wrap(Wrap { trim: false })
+            .block(block)
+            .render(area, buf);
+    }
+}
@@ -0,0 +1,58 @@
+use crab_chat_peer::SignalKind;
+use ratatui::{
+    buffer::Buffer,
+    layout::{Alignment, Rect},
+    style::Stylize,
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, List, StatefulWidget, Widget},
+};
+
+use super::Home;
+
+/// The other peers of the current room; clicking one opens a direct
+/// conversation with it.
+pub struct ParticipantsWidget;
+
+impl StatefulWidget for ParticipantsWidget {
+    type State = Home;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        let theme = &state.theme;
+        let me = state.peer.peer_id().to_string();
+        let room = state
+            .actual_room
+            .as_ref()
+            .and_then(|room| state.rooms.get(room));
+        let names = room.into_iter().flat_map(|room| {
+            room.participants(&me).into_iter().map(|(peer_id, name)| {
+                let (marker, away) = match room.presence.get(&peer_id) {
+                    Some(p) if matches!(p.state, SignalKind::Idle) => {
+                        ("◌ ", true)
+                    }
+                    Some(p) if matches!(p.state, SignalKind::Away) => {
+                        ("○ ", true)
+                    }
+                    Some(_) => ("● ", false),
+                    None => ("  ", false),
+                };
+                let name = Span::styled(name, theme.author);
+                let line = Line::from(vec![Span::raw(marker), name]);
+                match away {
+                    true => line.dim(),
+                    false => line,
+                }
+            })
+        });
+        let list = List::new(names.collect::<Vec<_>>()).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border)
+                .border_type(BorderType::Rounded)
+                .style(theme.background)
+                .title("People")
+                .title_alignment(Alignment::Center)
+                .title_style(theme.title),
+        );
+        Widget::render(list, area, buf);
+    }
+}
@@ -0,0 +1,203 @@
+use std::{fs, path::PathBuf};
+
+use crab_chat_peer::is_inbox_topic;
+use serde::{Deserialize, Serialize};
+
+/// Name of the room list in the data directory.
+pub const ROOMS_FILE: &str = "rooms.json";
+
+/// Where a room stands with the network.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Subscription {
+    /// Waiting for the subscription to be answered.
+    #[default]
+    Joining,
+    Joined,
+    Failed(String),
+}
+
+/// A room of the rooms pane, as kept between runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomEntry {
+    /// Topic of the room, or `@peer_id` for a direct conversation.
+    pub name: String,
+    #[serde(default)]
+    pub pinned: bool,
+    /// Name we gave the room, only shown to us.
+    #[serde(default)]
+    pub alias: Option<String>,
+    #[serde(skip)]
+    pub subscription: Subscription,
+}
+
+impl RoomEntry {
+    fn new(name: String) -> Self {
+        // direct conversations come through our inbox
+        let subscription = match is_inbox_topic(&name) {
+            true => Subscription::Joined,
+            false => Subscription::Joining,
+        };
+        Self {
+            name,
+            pinned: false,
+            alias: None,
+            subscription,
+        }
+    }
+}
+
+/// The rooms we are in, pinned ones first and the others in the order we
+/// joined them, saved to a file on every change.
+#[derive(Debug, Default)]
+pub struct RoomList {
+    path: Option<PathBuf>,
+    entries: Vec<RoomEntry>,
+}
+
+impl RoomList {
+    /// Reads the list saved at `path`; a missing file is an empty list.
+    pub fn load(path: PathBuf) -> Self {
+        let saved: Vec<RoomEntry> = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                tracing::error!("Ignoring {}: {e}", path.display());
+                vec![]
+            }),
+            Err(_) => vec![],
+        };
+        let entries = saved
+            .into_iter()
+            .map(|entry| RoomEntry {
+                subscription: RoomEntry::new(entry.name.clone()).subscription,
+                ..entry
+            })
+            .collect();
+        Self {
+            path: Some(path),
+            entries,
+        }
+    }
+
+    pub fn entries(&self) -> &[RoomEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RoomEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.name == name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Adds `name` at the end of the list, telling whether it is new.
+    pub fn add(&mut self, name: &str) -> bool {
+        if self.get(name).is_some() {
+            return false;
+        }
+        self.entries.push(RoomEntry::new(name.to_owned()));
+        self.save();
+        true
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.entries.retain(|entry| entry.name != name);
+        self.save();
+    }
+
+    /// Pins or unpins `name`: a pinned room goes after the other pinned
+    /// ones, an unpinned one back before the others.
+    pub fn toggle_pin(&mut self, name: &str) {
+        let Some(i) = self.position(name) else {
+            return;
+        };
+        let mut entry = self.entries.remove(i);
+        entry.pinned = !entry.pinned;
+        let pinned = self.entries.iter().filter(|e| e.pinned).count();
+        self.entries.insert(pinned, entry);
+        self.save();
+    }
+
+    /// Names `name` locally, or takes its own name back with `None`.
+    pub fn rename(&mut self, name: &str, alias: Option<String>) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.name == name) {
+            entry.alias = alias;
+            self.save();
+        }
+    }
+
+    pub fn set_subscription(&mut self, name: &str, subscription: Subscription) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.name == name) {
+            entry.subscription = subscription;
+        }
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let saved = serde_json::to_string_pretty(&self.entries)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                }
+                fs::write(path, json).map_err(|e| e.to_string())
+            });
+        if let Err(e) = saved {
+            tracing::error!("Failed to save {}: {e}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &RoomList) -> Vec<&str> {
+        list.entries().iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_order_and_persistence() {
+        let path = std::env::temp_dir()
+            .join(format!("crab-chat-rooms-{}", std::process::id()))
+            .join("rooms.json");
+        let mut list = RoomList::load(path.clone());
+        assert!(list.add("rust"));
+        assert!(list.add("@12D3KooW"));
+        assert!(list.add("random"));
+        assert!(!list.add("rust"));
+        assert_eq!(
+            list.get("rust").unwrap().subscription,
+            Subscription::Joining
+        );
+        assert_eq!(
+            list.get("@12D3KooW").unwrap().subscription,
+            Subscription::Joined
+        );
+
+        list.toggle_pin("random");
+        list.toggle_pin("@12D3KooW");
+        assert_eq!(names(&list), ["random", "@12D3KooW", "rust"]);
+        list.toggle_pin("random");
+        assert_eq!(names(&list), ["@12D3KooW", "random", "rust"]);
+
+        list.rename("rust", Some("crabs".to_owned()));
+        list.remove("random");
+        list.set_subscription("rust", Subscription::Joined);
+
+        let restored = RoomList::load(path.clone());
+        assert_eq!(names(&restored), ["@12D3KooW", "rust"]);
+        let rust = restored.get("rust").unwrap();
+        assert_eq!(rust.alias.as_deref(), Some("crabs"));
+        assert_eq!(rust.subscription, Subscription::Joining);
+        let direct = restored.get("@12D3KooW").unwrap();
+        assert!(direct.pinned);
+        assert_eq!(direct.subscription, Subscription::Joined);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -1,35 +1,60 @@
 use ratatui::{
     buffer::Buffer,
     layout::{Alignment, Rect},
-    style::{Color, Style, Stylize},
+    style::Stylize,
+    text::{Line, Span},
     widgets::{Block, BorderType, Borders, List, StatefulWidget},
 };
-use super::{Home, Mode};
+use super::{room_list::Subscription, Home, Mode};
 
 pub struct RoomsWidget;
 
 impl StatefulWidget for RoomsWidget {
     type State = Home;
     fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
-        let style = match state.mode {
-            Mode::Rooms => Style::default().fg(Color::White),
-            _ => Style::default().fg(Color::Green),
-        };
+        let theme = &state.theme;
+        let focused = matches!(state.mode, Mode::Rooms);
 
-        let rooms = List::new(state.rooms.keys().cloned().collect::<Vec<_>>())
-            .highlight_style(
-                Style::new().bg(Color::LightGreen).fg(Color::Black).italic(),
-            )
+        let me = state.peer.peer_id().to_string();
+        let names = state.room_list.entries().iter().map(|entry| {
+            let mut spans = vec![];
+            if entry.pinned {
+                spans.push(Span::raw("⚑ "));
+            }
+            spans.push(Span::raw(state.room_title(&entry.name)));
+            match &entry.subscription {
+                Subscription::Joining => spans.push(Span::raw(" …").dim()),
+                Subscription::Failed(_) => {
+                    spans.push(Span::styled(" ✗", theme.error))
+                }
+                Subscription::Joined => {}
+            }
+            let Some(r) = state.rooms.get(&entry.name) else {
+                return Line::from(spans);
+            };
+            let unread = r.unread(&me).count();
+            if unread > 0 {
+                spans.push(Span::raw(format!(" ({unread})")).bold());
+            }
+            let mentions = r.unread_mentions(&me);
+            if mentions > 0 {
+                spans
+                    .push(Span::styled(format!(" @{mentions}"), theme.mention));
+            }
+            Line::from(spans)
+        });
+        let rooms = List::new(names.collect::<Vec<_>>())
+            .highlight_style(theme.highlight)
             .highlight_symbol(">> ")
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .border_style(style)
+                    .border_style(theme.border_style(focused))
                     .border_type(BorderType::Rounded)
-                    .bg(Color::Black)
+                    .style(theme.background)
                     .title("Rooms")
                     .title_alignment(Alignment::Center)
-                    .title_style(Style::default().fg(Color::Green)),
+                    .title_style(theme.title),
             );
 
         rooms.render(area, buf, &mut state.rooms_state);
@@ -0,0 +1,38 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Stylize,
+    widgets::{Block, Borders, Paragraph, StatefulWidget, Widget},
+};
+
+use super::Home;
+
+/// Footer line telling who is typing in the current room.
+pub struct StatusWidget;
+
+impl StatefulWidget for StatusWidget {
+    type State = Home;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        let typing = state
+            .actual_room
+            .as_ref()
+            .and_then(|room| state.rooms.get(room))
+            .map(|room| room.typing())
+            .unwrap_or_default();
+
+        Paragraph::new(typing_text(&typing))
+            .style(state.theme.system_notice.italic())
+            .block(Block::default().borders(Borders::TOP))
+            .render(area, buf);
+    }
+}
+
+fn typing_text(names: &[String]) -> String {
+    match names {
+        [] => String::new(),
+        [name] => format!("{name} is typing…"),
+        [first, second] => format!("{first} and {second} are typing…"),
+        _ => format!("{} people are typing…", names.len()),
+    }
+}
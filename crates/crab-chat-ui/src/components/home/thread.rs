@@ -0,0 +1,79 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Alignment, Rect},
+    text::{Line, Span},
+    widgets::{
+        Block, BorderType, Borders, List, ListItem, StatefulWidget, Widget,
+    },
+};
+
+use super::{models::ChatMessage, Home};
+use crate::theme::Theme;
+
+/// Side pane listing every message of the thread opened from the chat.
+pub struct ThreadWidget;
+
+impl StatefulWidget for ThreadWidget {
+    type State = Home;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        let Some(thread) = &state.thread else {
+            return;
+        };
+
+        let theme = &state.theme;
+        let me = state.peer.peer_id().to_string();
+        let items = thread
+            .messages
+            .iter()
+            .map(|message| thread_item(&thread.messages, message, &me, theme))
+            .collect::<Vec<_>>();
+
+        let list = List::new(items).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(theme.border)
+                .style(theme.background)
+                .title("Thread")
+                .title_alignment(Alignment::Center)
+                .title_style(theme.title),
+        );
+        Widget::render(list, area, buf);
+    }
+}
+
+fn thread_item<'a>(
+    thread: &[ChatMessage],
+    message: &'a ChatMessage,
+    me: &str,
+    theme: &Theme,
+) -> ListItem<'a> {
+    let indent = "  ".repeat(depth(thread, message));
+    let author = match message.author == me {
+        true => theme.own_message,
+        false => theme.author,
+    };
+    ListItem::new(Line::from(vec![
+        Span::raw(indent),
+        Span::styled(message.display_name(), author),
+        Span::raw(": "),
+        Span::raw(message.text.as_str()),
+    ]))
+}
+
+/// Number of known ancestors of a message within the thread.
+fn depth(thread: &[ChatMessage], message: &ChatMessage) -> usize {
+    let mut depth = 0;
+    let mut parent_id = message.parent_id.as_ref();
+    while let Some(parent) =
+        parent_id.and_then(|id| thread.iter().find(|m| &m.id == id))
+    {
+        depth += 1;
+        if depth >= thread.len() {
+            break;
+        }
+        parent_id = parent.parent_id.as_ref();
+    }
+    depth
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use color_eyre::Result;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{layout::Flex, prelude::*, widgets::*};
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+use super::{common, home::models::format_time, Component};
+use crate::{action::Action, app::Mode, config::Config, theme::Theme};
+
+/// Notifications kept for the history.
+const HISTORY: usize = 200;
+
+/// Toasts on screen at once, the newest ones.
+const MAX_TOASTS: usize = 3;
+
+const TOAST_WIDTH: u16 = 48;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Display, Serialize, Deserialize,
+)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// How long a toast of this severity stays on screen.
+    fn ttl(self) -> Duration {
+        match self {
+            Severity::Info => Duration::from_secs(4),
+            Severity::Warning => Duration::from_secs(6),
+            Severity::Error => Duration::from_secs(10),
+        }
+    }
+
+    fn style(self, theme: &Theme) -> Style {
+        match self {
+            Severity::Info => theme.info,
+            Severity::Warning => theme.warning,
+            Severity::Error => theme.error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Notification {
+    severity: Severity,
+    text: String,
+    timestamp: u64,
+}
+
+/// Errors, warnings and news shown as toasts that fade after a while, and
+/// kept in a history that can be opened and scrolled.
+pub struct Notifications {
+    config: Config,
+    theme: Theme,
+    history: VecDeque<Notification>,
+    /// Notifications on screen, with when they go away.
+    toasts: VecDeque<(Notification, Instant)>,
+    /// Whether the history is open.
+    open: bool,
+    /// Lines scrolled up from the newest notification.
+    scroll: usize,
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Self {
+            config: Config::default(),
+            theme: Theme::default(),
+            history: VecDeque::new(),
+            toasts: VecDeque::new(),
+            open: false,
+            scroll: 0,
+        }
+    }
+
+    fn push(&mut self, severity: Severity, text: String, now: Instant) {
+        let notification = Notification {
+            severity,
+            text,
+            timestamp: common::now(),
+        };
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(notification.clone());
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back((notification, now + severity.ttl()));
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.toasts.retain(|(_, until)| *until > now);
+    }
+
+    fn draw_toasts(&self, frame: &mut Frame, area: Rect) {
+        let width = TOAST_WIDTH.min(area.width);
+        // from the bottom up, the newest lowest
+        let mut bottom = area.bottom().saturating_sub(3);
+        for (notification, _) in self.toasts.iter().rev() {
+            let style = notification.severity.style(&self.theme);
+            let paragraph = Paragraph::new(notification.text.as_str())
+                .wrap(Wrap { trim: true })
+                .block(
+                    Block::bordered()
+                        .border_type(BorderType::Rounded)
+                        .border_style(style)
+                        .style(self.theme.background)
+                        .title(notification.severity.to_string())
+                        .title_style(style),
+                );
+            let chars = notification.text.chars().count() as u16;
+            let lines = chars.div_ceil(width.saturating_sub(2).max(1));
+            let height = (lines.max(1) + 2).min(6);
+            if bottom < area.y + height {
+                break;
+            }
+            let toast = Rect {
+                x: area.right().saturating_sub(width + 1),
+                y: bottom - height,
+                width,
+                height,
+            };
+            frame.render_widget(Clear, toast);
+            frame.render_widget(paragraph, toast);
+            bottom -= height;
+        }
+    }
+
+    fn draw_history(&mut self, frame: &mut Frame, area: Rect) {
+        let [area] = Layout::horizontal([Constraint::Percentage(80)])
+            .flex(Flex::Center)
+            .areas(area);
+        let [area] = Layout::vertical([Constraint::Percentage(60)])
+            .flex(Flex::Center)
+            .areas(area);
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.focused_border)
+            .style(self.theme.background)
+            .title(format!("Notifications ({})", self.history.len()))
+            .title_alignment(Alignment::Center)
+            .title_style(self.theme.title)
+            .title_bottom("↑/↓ to scroll · Esc to close");
+        let lines: Vec<_> = self
+            .history
+            .iter()
+            .map(|notification| {
+                let style = notification.severity.style(&self.theme);
+                Line::from(vec![
+                    Span::styled(
+                        format!("{} ", format_time(notification.timestamp)),
+                        self.theme.timestamp,
+                    ),
+                    Span::styled(
+                        format!("{:<8}", notification.severity.to_string()),
+                        style,
+                    ),
+                    Span::raw(notification.text.clone()),
+                ])
+            })
+            .collect();
+        let height = block.inner(area).height as usize;
+        let max_scroll = lines.len().saturating_sub(height);
+        self.scroll = self.scroll.min(max_scroll);
+        let top = max_scroll - self.scroll;
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines).scroll((top as u16, 0)).block(block),
+            area,
+        );
+    }
+}
+
+impl Component for Notifications {
+    fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.theme = Theme::configured(&config, Mode::Home);
+        self.config = config;
+        Ok(())
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) -> Result<Option<Action>> {
+        match key.code {
+            KeyCode::PageUp => self.scroll += 10,
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(10),
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn focus(&self) -> Option<Mode> {
+        self.open.then_some(Mode::Notifications)
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        match action {
+            Action::Error(text) => {
+                self.push(Severity::Error, text, Instant::now())
+            }
+            Action::Notify(severity, text) => {
+                self.push(severity, text, Instant::now())
+            }
+            Action::Cancel => self.open = false,
+            Action::SelectPrevious => self.scroll += 1,
+            Action::SelectNext => self.scroll = self.scroll.saturating_sub(1),
+            Action::ToggleNotifications => {
+                self.open = !self.open;
+                self.scroll = 0;
+                // what is in the history needs no toast
+                self.toasts.clear();
+            }
+            Action::Tick => self.expire(Instant::now()),
+            Action::SetTheme(name) => {
+                if let Some(theme) =
+                    Theme::load(&self.config, &name, Mode::Home)
+                {
+                    self.theme = theme;
+                }
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rect) -> Result<()> {
+        match self.open {
+            true => self.draw_history(frame, area),
+            false => self.draw_toasts(frame, area),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toasts_expire_and_history_is_capped() {
+        let mut notifications = Notifications::new();
+        let start = Instant::now();
+        notifications.push(Severity::Info, "joined".to_owned(), start);
+        notifications.push(Severity::Error, "failed".to_owned(), start);
+        notifications.expire(start + Duration::from_secs(5));
+        let left: Vec<_> =
+            notifications.toasts.iter().map(|(n, _)| &n.text).collect();
+        assert_eq!(left, ["failed"]);
+
+        for i in 0..HISTORY + 10 {
+            notifications.push(Severity::Warning, i.to_string(), start);
+        }
+        assert_eq!(notifications.history.len(), HISTORY);
+        assert_eq!(notifications.toasts.len(), MAX_TOASTS);
+        assert_eq!(notifications.history[0].text, "10");
+    }
+}
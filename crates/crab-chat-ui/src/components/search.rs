@@ -0,0 +1,499 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use chrono::{Local, NaiveDate, TimeZone};
+use color_eyre::Result;
+use crab_chat_peer::OutboundStatus;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{prelude::*, widgets::*};
+
+use super::{
+    home::models::{display_name, format_time, ChatMessage},
+    Component,
+};
+use crate::{action::Action, app::Mode, config::Config, theme::Theme};
+
+/// Hits listed at most, the newest ones.
+const MAX_HITS: usize = 200;
+
+/// Filters a query may carry besides its words.
+const SYNTAX: &str =
+    "words in:room by:author since:YYYY-MM-DD until:YYYY-MM-DD";
+
+/// A message as kept in the index.
+#[derive(Debug, Clone, PartialEq)]
+struct Indexed {
+    room: String,
+    id: String,
+    author: String,
+    nickname: Option<String>,
+    text: String,
+    timestamp: u64,
+}
+
+impl Indexed {
+    fn author_name(&self) -> String {
+        display_name(&self.author, self.nickname.as_deref())
+    }
+}
+
+/// Full-text index of the messages seen since the start, fed one message at
+/// a time as they arrive.
+#[derive(Debug, Default)]
+struct SearchIndex {
+    messages: Vec<Indexed>,
+    /// Positions of the messages holding each word, lowercased.
+    words: BTreeMap<String, BTreeSet<usize>>,
+    /// Positions of the messages of each room, in arrival order.
+    rooms: HashMap<String, Vec<usize>>,
+    /// Position of every message, by room and id.
+    ids: HashMap<(String, String), usize>,
+}
+
+impl SearchIndex {
+    fn insert(&mut self, room: &str, message: &ChatMessage) {
+        let key = (room.to_owned(), message.id.clone());
+        if self.ids.contains_key(&key) {
+            return;
+        }
+        let position = self.messages.len();
+        for word in words(&message.text) {
+            self.words.entry(word).or_default().insert(position);
+        }
+        self.rooms
+            .entry(room.to_owned())
+            .or_default()
+            .push(position);
+        self.ids.insert(key, position);
+        self.messages.push(Indexed {
+            room: room.to_owned(),
+            id: message.id.clone(),
+            author: message.author.clone(),
+            nickname: message.nickname.clone(),
+            text: message.text.clone(),
+            timestamp: message.timestamp,
+        });
+    }
+
+    /// Follows a queued message that got its final id once sent.
+    fn rename(&mut self, room: &str, id: &str, new_id: &str) {
+        let key = (room.to_owned(), id.to_owned());
+        let Some(position) = self.ids.remove(&key) else {
+            return;
+        };
+        self.messages[position].id = new_id.to_owned();
+        self.ids
+            .insert((room.to_owned(), new_id.to_owned()), position);
+    }
+
+    /// Positions of the messages matching `query`, newest first.
+    fn search(&self, query: &Query) -> Vec<usize> {
+        let mut matching: Option<BTreeSet<usize>> = None;
+        for word in &query.words {
+            // a word matches the words it starts
+            let found: BTreeSet<usize> = self
+                .words
+                .range(word.clone()..)
+                .take_while(|(indexed, _)| indexed.starts_with(word.as_str()))
+                .flat_map(|(_, positions)| positions.iter().copied())
+                .collect();
+            matching = Some(match matching {
+                Some(matching) => &matching & &found,
+                None => found,
+            });
+        }
+        let candidates: Vec<usize> = match matching {
+            Some(matching) => matching.into_iter().collect(),
+            None => (0..self.messages.len()).collect(),
+        };
+        let mut hits: Vec<usize> = candidates
+            .into_iter()
+            .filter(|position| query.accepts(&self.messages[*position]))
+            .collect();
+        hits.sort_by_key(|position| {
+            std::cmp::Reverse((self.messages[*position].timestamp, *position))
+        });
+        hits.truncate(MAX_HITS);
+        hits
+    }
+
+    /// The messages around the one at `position` in its room.
+    fn context(&self, position: usize) -> (Option<&Indexed>, Option<&Indexed>) {
+        let room = &self.rooms[&self.messages[position].room];
+        let Ok(i) = room.binary_search(&position) else {
+            return (None, None);
+        };
+        let before = i.checked_sub(1).map(|i| &self.messages[room[i]]);
+        let after = room.get(i + 1).map(|p| &self.messages[*p]);
+        (before, after)
+    }
+}
+
+/// Lowercased words of `text`.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// What is searched for, as typed in the search box.
+#[derive(Debug, Default, PartialEq)]
+struct Query {
+    words: Vec<String>,
+    room: Option<String>,
+    author: Option<String>,
+    /// First second of the range, inclusive.
+    since: Option<u64>,
+    /// End of the range, exclusive.
+    until: Option<u64>,
+}
+
+impl Query {
+    fn parse(input: &str) -> Result<Self, String> {
+        let mut query = Self::default();
+        for part in input.split_whitespace() {
+            match part.split_once(':') {
+                Some(("in", room)) if !room.is_empty() => {
+                    query.room = Some(room.to_lowercase())
+                }
+                Some(("by", author)) if !author.is_empty() => {
+                    query.author =
+                        Some(author.trim_start_matches('@').to_lowercase())
+                }
+                Some(("since", date)) => query.since = Some(day_start(date)?),
+                Some(("until", date)) => {
+                    query.until = Some(day_start(date)? + 24 * 60 * 60)
+                }
+                _ => query.words.extend(words(part)),
+            }
+        }
+        Ok(query)
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn accepts(&self, message: &Indexed) -> bool {
+        let room = self
+            .room
+            .as_ref()
+            .is_none_or(|room| message.room.to_lowercase().contains(room));
+        let author = self.author.as_ref().is_none_or(|author| {
+            message.author.to_lowercase().contains(author)
+                || message
+                    .nickname
+                    .as_ref()
+                    .is_some_and(|n| n.to_lowercase().contains(author))
+        });
+        let since = self.since.is_none_or(|since| message.timestamp >= since);
+        let until = self.until.is_none_or(|until| message.timestamp < until);
+        room && author && since && until
+    }
+}
+
+/// Unix time of the local midnight starting `date`.
+fn day_start(date: &str) -> Result<u64, String> {
+    let day = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| format!("{date} is no YYYY-MM-DD date"))?;
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap_or_default();
+    let time = Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .ok_or_else(|| format!("{date} has no midnight here"))?;
+    Ok(time.timestamp().max(0) as u64)
+}
+
+/// Finds messages of every room by their words, author, room and date, and
+/// opens the one picked in its room.
+pub struct Search {
+    index: SearchIndex,
+    input: String,
+    query: Result<Query, String>,
+    hits: Vec<usize>,
+    hits_state: ListState,
+    config: Config,
+    theme: Theme,
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Self {
+            index: SearchIndex::default(),
+            input: String::new(),
+            query: Ok(Query::default()),
+            hits: vec![],
+            hits_state: ListState::default(),
+            config: Config::default(),
+            theme: Theme::default(),
+        }
+    }
+
+    /// Runs the query again, after it or the index changed.
+    fn refresh(&mut self) {
+        let selected = self
+            .hits_state
+            .selected()
+            .and_then(|i| self.hits.get(i))
+            .copied();
+        self.hits = match &self.query {
+            Ok(query) if !query.is_empty() => self.index.search(query),
+            _ => vec![],
+        };
+        // keep the selection on the same message
+        let i = selected
+            .and_then(|hit| self.hits.iter().position(|p| *p == hit))
+            .or((!self.hits.is_empty()).then_some(0));
+        self.hits_state.select(i);
+    }
+
+    fn edited(&mut self) {
+        self.query = Query::parse(&self.input);
+        self.hits_state.select(None);
+        self.refresh();
+    }
+
+    fn jump(&self) -> Option<Action> {
+        let hit = self.hits.get(self.hits_state.selected()?)?;
+        let message = &self.index.messages[*hit];
+        Some(Action::JumpToMessage(
+            message.room.clone(),
+            message.id.clone(),
+        ))
+    }
+
+    fn hit_item(&self, position: usize) -> ListItem<'static> {
+        let message = &self.index.messages[position];
+        let words = match &self.query {
+            Ok(query) => query.words.as_slice(),
+            Err(_) => &[],
+        };
+        let (before, after) = self.index.context(position);
+        let context = |message: Option<&Indexed>| {
+            message.map(|m| {
+                Line::from(format!("    {}: {}", m.author_name(), m.text)).dim()
+            })
+        };
+        let mut lines = vec![Line::from(vec![
+            Span::styled(message.room.clone(), self.theme.title),
+            Span::raw(" · "),
+            Span::styled(message.author_name(), self.theme.author),
+            Span::raw(" · "),
+            Span::styled(format_time(message.timestamp), self.theme.timestamp),
+        ])];
+        lines.extend(context(before));
+        let mut hit = vec![Span::raw("  > ")];
+        hit.extend(highlighted(&message.text, words, self.theme.mention));
+        lines.push(Line::from(hit));
+        lines.extend(context(after));
+        ListItem::new(Text::from(lines))
+    }
+}
+
+/// `text` with the words starting with one of `words` in `style`.
+fn highlighted(
+    text: &str,
+    words: &[String],
+    style: Style,
+) -> Vec<Span<'static>> {
+    let mut spans = vec![];
+    let mut rest = text;
+    while !rest.is_empty() {
+        let alphanumeric = rest.starts_with(char::is_alphanumeric);
+        let len = rest
+            .find(|c: char| c.is_alphanumeric() != alphanumeric)
+            .unwrap_or(rest.len());
+        let (part, tail) = rest.split_at(len);
+        let lower = part.to_lowercase();
+        let matches =
+            alphanumeric && words.iter().any(|w| lower.starts_with(w.as_str()));
+        spans.push(match matches {
+            true => Span::styled(part.to_owned(), style),
+            false => Span::raw(part.to_owned()),
+        });
+        rest = tail;
+    }
+    spans
+}
+
+impl Component for Search {
+    fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.theme = Theme::configured(&config, Mode::Search);
+        self.config = config;
+        Ok(())
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) -> Result<Option<Action>> {
+        let action = match (key.code, key.modifiers) {
+            (KeyCode::Backspace, _) => {
+                self.input.pop();
+                self.edited();
+                None
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                self.input.push(c);
+                self.edited();
+                None
+            }
+            _ => None,
+        };
+        Ok(action)
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        match action {
+            Action::Cancel => return Ok(Some(Action::SwitchMode(Mode::Home))),
+            Action::Confirm => return Ok(self.jump()),
+            Action::SelectNext => self.hits_state.select_next(),
+            Action::SelectPrevious => self.hits_state.select_previous(),
+            Action::MessageReceived(room, message) => {
+                self.index.insert(&room, &message);
+                self.refresh();
+            }
+            Action::HistoryLoaded(room, messages) => {
+                for message in &messages {
+                    self.index.insert(&room, message);
+                }
+                self.refresh();
+            }
+            Action::OutboundUpdated(
+                room,
+                id,
+                OutboundStatus::Sent { message_id },
+            ) => self.index.rename(&room, &id, &message_id),
+            // the hit is open, back to the chat
+            Action::JumpToMessage(..) => {
+                return Ok(Some(Action::SwitchMode(Mode::Home)))
+            }
+            Action::SetTheme(name) => {
+                if let Some(theme) =
+                    Theme::load(&self.config, &name, Mode::Search)
+                {
+                    self.theme = theme;
+                }
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rect) -> Result<()> {
+        let [input, hits, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+
+        let prompt = Paragraph::new(Line::from(vec![
+            self.input.as_str().into(),
+            "█".slow_blink(),
+        ]))
+        .block(
+            Block::bordered()
+                .border_style(self.theme.focused_border)
+                .style(self.theme.background)
+                .title("Search")
+                .title_style(self.theme.title),
+        );
+        frame.render_widget(prompt, input);
+
+        let title = match &self.query {
+            Err(e) => Line::styled(e.clone(), self.theme.error),
+            Ok(query) if query.is_empty() => Line::from(SYNTAX),
+            Ok(_) => Line::from(format!("{} hits", self.hits.len())),
+        };
+        let items: Vec<_> =
+            self.hits.iter().map(|hit| self.hit_item(*hit)).collect();
+        let list = List::new(items)
+            .highlight_style(self.theme.highlight)
+            .block(
+                Block::bordered()
+                    .border_style(self.theme.border)
+                    .style(self.theme.background)
+                    .title(title)
+                    .title_style(self.theme.title),
+            );
+        frame.render_stateful_widget(list, hits, &mut self.hits_state);
+
+        let help = "type to search · ↑/↓ select · Enter open · Esc back";
+        frame.render_widget(Line::from(help).dim(), footer);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crab_chat_peer::Reactions;
+
+    use super::*;
+
+    fn message(
+        id: &str,
+        author: &str,
+        text: &str,
+        timestamp: u64,
+    ) -> ChatMessage {
+        ChatMessage {
+            id: id.to_owned(),
+            author: author.to_owned(),
+            nickname: Some(author.to_owned()),
+            text: text.to_owned(),
+            timestamp,
+            parent_id: None,
+            mentions: vec![],
+            reactions: Reactions::new(),
+            file: None,
+            outbound: None,
+        }
+    }
+
+    fn ids(index: &SearchIndex, query: &str) -> Vec<String> {
+        let query = Query::parse(query).unwrap();
+        let hits = index.search(&query);
+        hits.iter().map(|p| index.messages[*p].id.clone()).collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let mut index = SearchIndex::default();
+        index.insert("rust", &message("a", "alice", "Async traits!", 10));
+        index.insert("rust", &message("b", "bob", "what about async fn", 20));
+        index.insert("rust", &message("c", "alice", "later", 30));
+        index.insert("random", &message("d", "bob", "asynchronous", 40));
+        index.insert("random", &message("d", "bob", "asynchronous", 40));
+
+        assert_eq!(ids(&index, "ASYNC"), ["d", "b", "a"]);
+        assert_eq!(ids(&index, "async fn"), ["b"]);
+        assert_eq!(ids(&index, "async in:rust by:Alice"), ["a"]);
+        assert_eq!(ids(&index, "by:bob"), ["d", "b"]);
+        assert!(ids(&index, "sync").is_empty());
+
+        let (before, after) = index.context(1);
+        assert_eq!(before.map(|m| m.id.as_str()), Some("a"));
+        assert_eq!(after.map(|m| m.id.as_str()), Some("c"));
+
+        index.rename("rust", "c", "c2");
+        assert_eq!(ids(&index, "later"), ["c2"]);
+    }
+
+    #[test]
+    fn test_query() {
+        let query = Query::parse("since:2024-01-01 until:2024-01-01").unwrap();
+        let since = query.since.unwrap();
+        assert_eq!(query.until, Some(since + 24 * 60 * 60));
+        assert!(query.words.is_empty());
+        assert!(Query::parse("since:yesterday").is_err());
+
+        let spans = highlighted(
+            "Async, asynchronously",
+            &["async".to_owned()],
+            Style::new().bold(),
+        );
+        let bold: Vec<_> = spans
+            .iter()
+            .filter(|s| s.style == Style::new().bold())
+            .map(|s| s.content.as_ref())
+            .collect();
+        assert_eq!(bold, ["Async", "asynchronously"]);
+    }
+}
@@ -1,6 +1,6 @@
 #![allow(dead_code)] // Remove this once you start using the code
 
-use std::{collections::HashMap, env, path::PathBuf};
+use std::{collections::HashMap, env, net::SocketAddr, path::PathBuf};
 
 use color_eyre::Result;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
@@ -8,7 +8,10 @@ use derive_deref::{Deref, DerefMut};
 use directories::ProjectDirs;
 use lazy_static::lazy_static;
 use ratatui::style::{Color, Modifier, Style};
-use serde::{de::Deserializer, Deserialize};
+use serde::{
+    de::{Deserializer, Error},
+    Deserialize,
+};
 use tracing::error;
 
 use crate::{action::Action, app::Mode};
@@ -21,6 +24,54 @@ pub struct AppConfig {
     pub data_dir: PathBuf,
     #[serde(default)]
     pub config_dir: PathBuf,
+    /// Broadcast signed read receipts to the rooms we read.
+    #[serde(default)]
+    pub read_receipts: bool,
+    /// Program and arguments run to notify us of a mention while the
+    /// terminal is unfocused or suspended; the room and message are appended.
+    #[serde(default)]
+    pub notify_command: Vec<String>,
+    /// Where accepted files are saved, `downloads` in the data dir if unset.
+    #[serde(default)]
+    pub download_dir: Option<PathBuf>,
+    /// Name of the theme to start with, one of `themes`.
+    #[serde(default)]
+    pub theme: Option<String>,
+    /// Capture the mouse to click and scroll the panes; the terminal's own
+    /// text selection then needs a modifier, usually shift.
+    #[serde(default)]
+    pub mouse: bool,
+    /// Show messages as typed rather than formatting their markdown.
+    #[serde(default)]
+    pub raw_messages: bool,
+    /// Name shown to other peers, the current user if unset.
+    #[serde(default)]
+    pub nickname: Option<String>,
+    /// Multiaddr the peer listens on, every interface on a random port if
+    /// unset.
+    #[serde(default)]
+    pub listen_addr: Option<String>,
+    /// Peers to reach the network through, as `<peer_id>:<multiaddr>`.
+    #[serde(default)]
+    pub bootstrap: Vec<String>,
+    /// Whether peers on the local network are looked for, true if unset.
+    #[serde(default)]
+    pub mdns: Option<bool>,
+    /// Where Prometheus metrics are served over HTTP, if anywhere.
+    #[serde(default)]
+    pub metrics_addr: Option<SocketAddr>,
+    /// Whether the messages seen, direct ones included, are kept in the
+    /// data dir across restarts, true if unset.
+    #[serde(default)]
+    pub keep_history: Option<bool>,
+    /// How many messages of each room the history keeps, the oldest going
+    /// first.
+    #[serde(default)]
+    pub history_limit: Option<usize>,
+    #[serde(default)]
+    pub tick_rate: Option<f64>,
+    #[serde(default)]
+    pub frame_rate: Option<f64>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -31,19 +82,34 @@ pub struct Config {
     pub keybindings: KeyBindings,
     #[serde(default)]
     pub styles: Styles,
+    #[serde(default)]
+    pub themes: Themes,
 }
 
+/// Prefix of the environment variables before the binary became
+/// `crab-chat`, still read when the new ones are not set.
+const LEGACY_PROJECT_NAME: &str = "CRAB_CHAT_UI";
+
 lazy_static! {
     pub static ref PROJECT_NAME: String =
         env!("CARGO_CRATE_NAME").to_uppercase().to_string();
     pub static ref DATA_FOLDER: Option<PathBuf> =
-        env::var(format!("{}_DATA", PROJECT_NAME.clone()))
-            .ok()
-            .map(PathBuf::from);
+        env::var(env_name("DATA")).ok().map(PathBuf::from);
     pub static ref CONFIG_FOLDER: Option<PathBuf> =
-        env::var(format!("{}_CONFIG", PROJECT_NAME.clone()))
-            .ok()
-            .map(PathBuf::from);
+        env::var(env_name("CONFIG")).ok().map(PathBuf::from);
+}
+
+/// Name of the environment variable for `suffix`, the legacy one if only
+/// that one is set. Read before logging starts, so the deprecation goes to
+/// stderr.
+pub fn env_name(suffix: &str) -> String {
+    let name = format!("{}_{suffix}", *PROJECT_NAME);
+    let legacy = format!("{LEGACY_PROJECT_NAME}_{suffix}");
+    if env::var_os(&name).is_none() && env::var_os(&legacy).is_some() {
+        eprintln!("{legacy} is deprecated, set {name} instead");
+        return legacy;
+    }
+    name
 }
 
 impl Config {
@@ -86,12 +152,25 @@ impl Config {
                     .or_insert_with(|| cmd.clone());
             }
         }
+        let conflicts = cfg.keybindings.conflicts();
+        if !conflicts.is_empty() {
+            return Err(config::ConfigError::Message(format!(
+                "Conflicting keybindings:\n{}",
+                conflicts.join("\n")
+            )));
+        }
         for (mode, default_styles) in default_config.styles.iter() {
             let user_styles = cfg.styles.entry(*mode).or_default();
             for (style_key, style) in default_styles.iter() {
                 user_styles.entry(style_key.clone()).or_insert(*style);
             }
         }
+        for (name, default_theme) in default_config.themes.iter() {
+            let user_theme = cfg.themes.entry(name.clone()).or_default();
+            for (slot, style) in default_theme.iter() {
+                user_theme.entry(slot.clone()).or_insert(*style);
+            }
+        }
 
         Ok(cfg)
     }
@@ -135,23 +214,65 @@ impl<'de> Deserialize<'de> for KeyBindings {
             deserializer,
         )?;
 
-        let keybindings = parsed_map
-            .into_iter()
-            .map(|(mode, inner_map)| {
-                let converted_inner_map = inner_map
-                    .into_iter()
-                    .map(|(key_str, cmd)| {
-                        (parse_key_sequence(&key_str).unwrap(), cmd)
-                    })
-                    .collect();
-                (mode, converted_inner_map)
-            })
-            .collect();
+        let mut keybindings = HashMap::new();
+        for (mode, inner_map) in parsed_map {
+            let mut converted_inner_map = HashMap::new();
+            for (key_str, cmd) in inner_map {
+                let keys = parse_key_sequence(&key_str).map_err(|e| {
+                    D::Error::custom(format!("{e} in the {mode:?} keybindings"))
+                })?;
+                // the same keys spelled twice
+                if let Some(other) = converted_inner_map.insert(keys, cmd) {
+                    return Err(D::Error::custom(format!(
+                        "{key_str} is bound to {other:?} under another \
+                         spelling in the {mode:?} keybindings"
+                    )));
+                }
+            }
+            keybindings.insert(mode, converted_inner_map);
+        }
 
         Ok(KeyBindings(keybindings))
     }
 }
 
+impl KeyBindings {
+    /// Describes every key sequence that can never fire, because a shorter
+    /// one bound in the same mode, or in its parent, starts with it.
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = vec![];
+        for (mode, keymap) in self.iter() {
+            let parent = mode.parent().and_then(|parent| self.get(&parent));
+            let inherited = parent
+                .into_iter()
+                .flatten()
+                .filter(|(keys, _)| !keymap.contains_key(*keys));
+            let bindings: Vec<_> = keymap.iter().chain(inherited).collect();
+            for (short, short_action) in &bindings {
+                for (long, long_action) in &bindings {
+                    if long.len() > short.len() && long.starts_with(short) {
+                        conflicts.push(format!(
+                            "In {mode:?}, {} ({short_action}) hides {} \
+                             ({long_action})",
+                            key_sequence_to_string(short),
+                            key_sequence_to_string(long),
+                        ));
+                    }
+                }
+            }
+        }
+        conflicts.sort();
+        conflicts.dedup();
+        conflicts
+    }
+}
+
+pub fn key_sequence_to_string(keys: &[KeyEvent]) -> String {
+    keys.iter()
+        .map(|key| format!("<{}>", key_event_to_string(key)))
+        .collect()
+}
+
 fn parse_key_event(raw: &str) -> Result<KeyEvent, String> {
     let raw_lower = raw.to_ascii_lowercase();
     let (remaining, modifiers) = extract_modifiers(&raw_lower);
@@ -352,6 +473,35 @@ impl<'de> Deserialize<'de> for Styles {
     }
 }
 
+/// Style presets by theme name, each a map of style slots.
+#[derive(Clone, Debug, Default, Deref, DerefMut)]
+pub struct Themes(pub HashMap<String, HashMap<String, Style>>);
+
+impl<'de> Deserialize<'de> for Themes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed_map =
+            HashMap::<String, HashMap<String, String>>::deserialize(
+                deserializer,
+            )?;
+
+        let themes = parsed_map
+            .into_iter()
+            .map(|(name, slots)| {
+                let slots = slots
+                    .into_iter()
+                    .map(|(slot, style)| (slot, parse_style(&style)))
+                    .collect();
+                (name, slots)
+            })
+            .collect();
+
+        Ok(Themes(themes))
+    }
+}
+
 pub fn parse_style(line: &str) -> Style {
     let (foreground, background) =
         line.split_at(line.to_lowercase().find("on ").unwrap_or(line.len()));
@@ -518,15 +668,53 @@ mod tests {
         let c = Config::new()?;
         assert_eq!(
             c.keybindings
-                .get(&Mode::Home)
+                .get(&Mode::Rooms)
                 .unwrap()
                 .get(&parse_key_sequence("<q>").unwrap_or_default())
                 .unwrap(),
             &Action::Quit
         );
+        // typing a q is not quitting
+        let q = parse_key_sequence("<q>").unwrap_or_default();
+        assert!(!c.keybindings[&Mode::Input].contains_key(&q));
+        assert!(!c.keybindings[&Mode::Home].contains_key(&q));
+        assert_eq!(
+            c.keybindings
+                .get(&Mode::Diagnostics)
+                .unwrap()
+                .get(&parse_key_sequence("<F2>").unwrap_or_default())
+                .unwrap(),
+            &Action::SwitchMode(Mode::Home)
+        );
         Ok(())
     }
 
+    #[test]
+    fn test_keybinding_conflicts() {
+        let keybindings: KeyBindings = json5::from_str(
+            r#"{
+                "Home": {"<g>": "Quit", "<Ctrl-c>": "Quit"},
+                "Chat": {"<g><g>": "JumpToUnread", "<u>": "Reply"},
+                "Input": {"<u><u>": "Suspend"},
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            keybindings.conflicts(),
+            vec!["In Chat, <g> (Quit) hides <g><g> (JumpToUnread)"]
+        );
+
+        let error = json5::from_str::<KeyBindings>(
+            r#"{"Chat": {"<Ctrl-a>": "Quit", "<ctrl-a>": "Reply"}}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("another spelling"));
+        let error =
+            json5::from_str::<KeyBindings>(r#"{"Chat": {"<nope>": "Quit"}}"#)
+                .unwrap_err();
+        assert!(error.to_string().contains("in the Chat keybindings"));
+    }
+
     #[test]
     fn test_simple_keys() {
         assert_eq!(
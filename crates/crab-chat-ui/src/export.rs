@@ -0,0 +1,255 @@
+use std::path::Path;
+
+use chrono::DateTime;
+use clap::ValueEnum;
+use crab_chat_peer::StoredMessage;
+
+/// What a room's history can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// One line per message, for reading.
+    Text,
+    /// One JSON message per line, the format imports read.
+    Jsonl,
+    /// A standalone page.
+    Html,
+}
+
+impl Format {
+    /// The format a file name asks for by its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "txt" | "log" => Some(Self::Text),
+            "jsonl" | "ndjson" => Some(Self::Jsonl),
+            "html" | "htm" => Some(Self::Html),
+            _ => None,
+        }
+    }
+}
+
+/// The messages of `room`, oldest first, as `format`.
+pub fn render(
+    room: &str,
+    messages: &[StoredMessage],
+    format: Format,
+) -> String {
+    match format {
+        Format::Text => text(room, messages),
+        Format::Jsonl => messages
+            .iter()
+            .map(|message| serde_json::to_string(message).unwrap() + "\n")
+            .collect(),
+        Format::Html => html(room, messages),
+    }
+}
+
+/// Reads a JSON Lines export back, telling which line is broken if one is.
+pub fn parse_jsonl(text: &str) -> Result<Vec<StoredMessage>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| format!("line {}: {e}", i + 1))
+        })
+        .collect()
+}
+
+fn text(room: &str, messages: &[StoredMessage]) -> String {
+    let mut out = format!("# {room}\n");
+    for message in messages {
+        out.push_str(&format!(
+            "[{}] {} <{}> ",
+            time(*message.timestamp()),
+            message.message_id(),
+            author(message)
+        ));
+        if let Some(parent_id) = message.parent_id() {
+            out.push_str(&format!("(reply to {parent_id}) "));
+        }
+        // continuation lines are indented so each message starts a line
+        let text = message.data().replace('\n', "\n    ");
+        match (message.deleted(), message.edited()) {
+            (true, _) => out.push_str("(deleted)"),
+            (false, true) => out.push_str(&format!("{text} (edited)")),
+            (false, false) => out.push_str(&text),
+        }
+        if !message.reactions().is_empty() {
+            out.push_str(&format!(" [{}]", reactions(message)));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn html(room: &str, messages: &[StoredMessage]) -> String {
+    let room = escape(room);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{room}</title>\n<style>\n\
+         body {{ font-family: sans-serif; max-width: 50em; margin: auto; }}\n\
+         .meta {{ color: #777; font-size: small; }}\n\
+         .text {{ white-space: pre-wrap; margin: 0.2em 0 1em; }}\n\
+         </style>\n</head>\n<body>\n<h1>{room}</h1>\n"
+    );
+    for message in messages {
+        let id = escape(message.message_id());
+        out.push_str(&format!(
+            "<div class=\"message\" id=\"{id}\">\n<div class=\"meta\">\
+             <b>{}</b> <time>{}</time> {id}",
+            escape(&author(message)),
+            time(*message.timestamp()),
+        ));
+        if let Some(parent_id) = message.parent_id() {
+            let parent_id = escape(parent_id);
+            out.push_str(&format!(
+                " reply to <a href=\"#{parent_id}\">{parent_id}</a>"
+            ));
+        }
+        let text = match (message.deleted(), message.edited()) {
+            (true, _) => "<i>deleted</i>".to_owned(),
+            (false, true) => {
+                format!("{} <i>(edited)</i>", escape(message.data()))
+            }
+            (false, false) => escape(message.data()),
+        };
+        out.push_str(&format!("</div>\n<p class=\"text\">{text}</p>\n"));
+        if !message.reactions().is_empty() {
+            out.push_str(&format!(
+                "<div class=\"meta\">{}</div>\n",
+                escape(&reactions(message))
+            ));
+        }
+        out.push_str("</div>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Nickname and peer id of the author, as the peer id alone is unreadable.
+fn author(message: &StoredMessage) -> String {
+    match message.nickname() {
+        Some(nickname) => format!("{nickname} {}", message.peer_id()),
+        None => message.peer_id().clone(),
+    }
+}
+
+fn reactions(message: &StoredMessage) -> String {
+    message
+        .reactions()
+        .iter()
+        .map(|(emoji, reactors)| format!(":{emoji}: {}", reactors.len()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Timestamps are exported in UTC, to read the same on every machine.
+fn time(timestamp: u64) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_default()
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crab_chat_peer::Reactions;
+
+    use super::*;
+
+    fn messages() -> Vec<StoredMessage> {
+        let reactions =
+            Reactions::from([("tada".to_owned(), vec!["bob".to_owned()])]);
+        vec![
+            StoredMessage::builder()
+                .message_id("m1".to_owned())
+                .peer_id("12D3A".to_owned())
+                .nickname("alice".to_owned())
+                .topic("rust".to_owned())
+                .data("hi <all>\nsecond line".to_owned())
+                .timestamp(0)
+                .reactions(reactions)
+                .build(),
+            StoredMessage::builder()
+                .message_id("m2".to_owned())
+                .peer_id("12D3B".to_owned())
+                .topic("rust".to_owned())
+                .data("hello".to_owned())
+                .timestamp(60)
+                .parent_id("m1".to_owned())
+                .build(),
+        ]
+    }
+
+    #[test]
+    fn test_formats() {
+        let messages = messages();
+        assert_eq!(
+            render("rust", &messages, Format::Text),
+            "# rust\n\
+             [1970-01-01 00:00:00 UTC] m1 <alice 12D3A> hi <all>\n    \
+             second line [:tada: 1]\n\
+             [1970-01-01 00:01:00 UTC] m2 <12D3B> (reply to m1) hello\n"
+        );
+
+        let html = render("rust", &messages, Format::Html);
+        assert!(html.contains("hi &lt;all&gt;\nsecond line"));
+        assert!(html.contains("reply to <a href=\"#m1\">m1</a>"));
+
+        let jsonl = render("rust", &messages, Format::Jsonl);
+        let parsed = parse_jsonl(&jsonl).unwrap();
+        assert_eq!(render("rust", &parsed, Format::Jsonl), jsonl);
+        assert!(parse_jsonl("\n{}\n").unwrap_err().starts_with("line 2:"));
+
+        assert_eq!(Format::from_path(Path::new("rust.md")), None);
+        assert_eq!(
+            Format::from_path(Path::new("rust.jsonl")),
+            Some(Format::Jsonl)
+        );
+    }
+
+    #[test]
+    fn test_edited_and_deleted() {
+        let message = |id: &str, edited, deleted| {
+            StoredMessage::builder()
+                .message_id(id.to_owned())
+                .peer_id("12D3A".to_owned())
+                .topic("rust".to_owned())
+                .data("secret".to_owned())
+                .timestamp(0)
+                .edited(edited)
+                .deleted(deleted)
+                .build()
+        };
+        let messages =
+            vec![message("m1", true, false), message("m2", false, true)];
+
+        let text = render("rust", &messages, Format::Text);
+        assert!(text.contains("m1 <12D3A> secret (edited)\n"));
+        assert!(text.contains("m2 <12D3A> (deleted)\n"));
+
+        let html = render("rust", &messages, Format::Html);
+        assert!(html.contains("secret <i>(edited)</i>"));
+        assert!(html.contains("<i>deleted</i>"));
+        assert_eq!(html.matches("secret").count(), 1);
+
+        let jsonl = render("rust", &messages, Format::Jsonl);
+        assert!(jsonl.contains("\"edited\":true"));
+        let parsed = parse_jsonl(&jsonl).unwrap();
+        assert!(*parsed[0].edited() && *parsed[1].deleted());
+    }
+}
@@ -1,16 +1,21 @@
+use std::time::Duration;
+
 use clap::Parser;
-use cli::Cli;
+use cli::{Cli, Command, UiArgs};
 use color_eyre::Result;
-use crab_chat_peer::create_peer;
+use config::Config;
 use crate::app::App;
 
 mod action;
 mod app;
 mod cli;
+mod commands;
 mod components;
 mod config;
 mod errors;
+mod export;
 mod logging;
+mod theme;
 mod tui;
 
 #[tokio::main]
@@ -19,7 +24,40 @@ async fn main() -> Result<()> {
     crate::logging::init()?;
 
     let args = Cli::parse();
-    let mut app = App::new(args.tick_rate, args.frame_rate, create_peer())?;
-    app.run().await?;
+    let config = Config::new()?;
+    let app_config = &config.config;
+    match args.command.unwrap_or(Command::Tui(UiArgs::default())) {
+        Command::Tui(ui) => {
+            let (tick_rate, frame_rate) = ui.rates(app_config);
+            let peer = args.network.peer(app_config)?;
+            let mut app = App::new(config, tick_rate, frame_rate, peer)?;
+            app.run().await?;
+        }
+        Command::Daemon { room } => {
+            let peer = args.network.peer(app_config)?;
+            commands::daemon(peer, app_config, room).await?;
+        }
+        Command::Send {
+            room,
+            message,
+            timeout,
+        } => {
+            let peer = args.network.peer(app_config)?;
+            let timeout = Duration::from_secs(timeout);
+            commands::send(peer, room, message.join(" "), timeout).await?;
+        }
+        Command::Listen { rooms, json } => {
+            let peer = args.network.peer(app_config)?;
+            commands::listen(peer, app_config, rooms, json).await?;
+        }
+        Command::Identity => commands::identity(app_config)?,
+        Command::Rooms => commands::rooms(app_config),
+        Command::Export {
+            room,
+            output,
+            format,
+        } => commands::export(app_config, &room, output, format)?,
+        Command::Import { path } => commands::import(app_config, &path)?,
+    }
     Ok(())
 }
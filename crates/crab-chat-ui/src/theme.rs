@@ -0,0 +1,143 @@
+use ratatui::style::Style;
+
+use crate::{app::Mode, config::Config};
+
+/// Theme used when the config does not pick one, or picks an unknown one.
+pub const DEFAULT_THEME: &str = "dark";
+
+/// Named styles the widgets draw with.
+///
+/// A theme starts from one of the presets under `themes` in the config;
+/// the `styles` of a mode then override single slots of it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    /// Text and background of every pane.
+    pub background: Style,
+    pub border: Style,
+    pub focused_border: Style,
+    pub title: Style,
+    pub header: Style,
+    /// Selected entry of a list.
+    pub highlight: Style,
+    /// Names of the other authors.
+    pub author: Style,
+    /// Our own name on the messages we wrote.
+    pub own_message: Style,
+    /// Messages that mention us.
+    pub mention: Style,
+    /// Lines the app adds between messages, such as the unread marker.
+    pub system_notice: Style,
+    pub timestamp: Style,
+    /// Notifications, by severity.
+    pub info: Style,
+    pub warning: Style,
+    pub error: Style,
+    /// Inline code and code blocks of formatted messages.
+    pub code: Style,
+    pub code_keyword: Style,
+    pub code_string: Style,
+    pub code_comment: Style,
+    pub link: Style,
+    pub quote: Style,
+}
+
+impl Theme {
+    /// Resolves the preset `name` for `mode`, if there is such a preset.
+    pub fn load(config: &Config, name: &str, mode: Mode) -> Option<Self> {
+        let preset = config.themes.get(name)?;
+        let mut theme = Self {
+            name: name.to_owned(),
+            ..Self::default()
+        };
+        let overrides = config.styles.get(&mode).into_iter().flatten();
+        for (slot, style) in preset.iter().chain(overrides) {
+            theme.set(slot, *style);
+        }
+        Some(theme)
+    }
+
+    /// The theme picked in the config, falling back to the default one.
+    pub fn configured(config: &Config, mode: Mode) -> Self {
+        let name = config.config.theme.as_deref().unwrap_or(DEFAULT_THEME);
+        Self::load(config, name, mode).unwrap_or_else(|| {
+            tracing::error!("Unknown theme {name}, using {DEFAULT_THEME}");
+            Self::load(config, DEFAULT_THEME, mode).unwrap_or_default()
+        })
+    }
+
+    /// Names of every preset, sorted.
+    pub fn names(config: &Config) -> Vec<String> {
+        let mut names: Vec<_> = config.themes.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Border of a pane, depending on whether it has the focus.
+    pub fn border_style(&self, focused: bool) -> Style {
+        match focused {
+            true => self.focused_border,
+            false => self.border,
+        }
+    }
+
+    fn set(&mut self, slot: &str, style: Style) {
+        let slot = match slot {
+            "background" => &mut self.background,
+            "border" => &mut self.border,
+            "focused_border" => &mut self.focused_border,
+            "title" => &mut self.title,
+            "header" => &mut self.header,
+            "highlight" => &mut self.highlight,
+            "author" => &mut self.author,
+            "own_message" => &mut self.own_message,
+            "mention" => &mut self.mention,
+            "system_notice" => &mut self.system_notice,
+            "timestamp" => &mut self.timestamp,
+            "info" => &mut self.info,
+            "warning" => &mut self.warning,
+            "error" => &mut self.error,
+            "code" => &mut self.code,
+            "code_keyword" => &mut self.code_keyword,
+            "code_string" => &mut self.code_string,
+            "code_comment" => &mut self.code_comment,
+            "link" => &mut self.link,
+            "quote" => &mut self.quote,
+            _ => {
+                tracing::warn!("Unknown style slot {slot}");
+                return;
+            }
+        };
+        *slot = style;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::{Color, Modifier};
+
+    use super::*;
+
+    #[test]
+    fn test_presets_and_overrides() {
+        let mut config = Config::new().unwrap();
+        for name in ["dark", "light", "high-contrast"] {
+            let theme = Theme::load(&config, name, Mode::Home).unwrap();
+            assert_ne!(theme.border, theme.focused_border, "{name}");
+            assert_ne!(theme.mention, Style::default(), "{name}");
+        }
+        assert!(Theme::load(&config, "nope", Mode::Home).is_none());
+
+        let style = Style::default().fg(Color::Indexed(1));
+        config
+            .styles
+            .entry(Mode::Home)
+            .or_default()
+            .insert("mention".to_owned(), style);
+        let home = Theme::load(&config, "dark", Mode::Home).unwrap();
+        assert_eq!(home.mention, style);
+        let diagnostics =
+            Theme::load(&config, "dark", Mode::Diagnostics).unwrap();
+        assert!(diagnostics.mention.add_modifier.contains(Modifier::BOLD));
+    }
+}